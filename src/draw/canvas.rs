@@ -1,23 +1,76 @@
 use std::fs::File;
-use std::io::Write;
+use std::io::{self, BufWriter, Write};
 
-use super::color::Color;
+use super::{color::Color, texture::TextureMap};
+
+// 4x4 Bayer matrix used for ordered dithering
+const BAYER_4X4: [[f64; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
+
+// a typical display, used when gamma correction is asked for without a value
+pub const DEFAULT_GAMMA: f64 = 2.2;
+
+// the PPM spec does not allow lines longer than this
+const PPM_LINE_LIMIT: usize = 70;
+
+/*
+    How pixels are sampled from the original image when a canvas is resized
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResizeMode {
+    Nearest,  // copy the closest pixel, keeps hard edges
+    Bilinear, // blend the four closest pixels, smooth but slightly soft
+}
 
 pub struct Canvas {
     pub width: usize,
     pub height: usize,
+    pub dither: bool,   // apply ordered dithering when quantizing to reduce banding
+    pub gamma: f64,     // encode pixels with this gamma when quantizing, 1 writes linear values
+    pub tone_map: bool, // compress highlights with Reinhard's operator before quantizing
     canvas: Vec<Vec<Color>>,
 }
 
+// pixels are compared with the same tolerance as colors, dithering is not part of the image
+impl PartialEq for Canvas {
+    fn eq(&self, other: &Self) -> bool {
+        self.width == other.width && self.height == other.height && self.canvas == other.canvas
+    }
+}
+
 impl Canvas {
     pub fn new(width: usize, height: usize) -> Canvas {
         Canvas {
             width,
             height,
+            dither: false,
+            gamma: 1.0,
+            tone_map: false,
             canvas: vec![vec![Color::black(); width]; height],
         }
     }
 
+    /*
+        Read back a plain or binary PPM, such as one written by this canvas,
+        channels are scaled by the max value in the header into 0 to 1
+    */
+    pub fn from_ppm(path: &str) -> Result<Canvas, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+        let image = TextureMap::from_ppm(&bytes)?;
+
+        let mut canvas = Canvas::new(image.width(), image.height());
+        for y in 0..canvas.height {
+            for x in 0..canvas.width {
+                canvas.write_pixel(x, y, image.pixel_at(x, y));
+            }
+        }
+        Ok(canvas)
+    }
+
     pub fn write_pixel(&mut self, x: usize, y: usize, c: Color) {
         self.canvas[y][x] = c;
     }
@@ -26,39 +79,588 @@ impl Canvas {
         self.canvas[y][x]
     }
 
+    /*
+        The bytes written out for a pixel, gamma corrected and dithered as set
+        on this canvas, channels which are not finite are written as 0
+    */
+    pub fn pixel_bytes(&self, x: usize, y: usize) -> [u8; 3] {
+        self.pixel_bytes_with_gamma(x, y, self.gamma)
+    }
+
+    /*
+        Each channel c is written as floor(max(c, 0)^(1 / gamma) * 255 + d)
+        clamped to 255, where d is the dither offset or 0 without dithering,
+        with tone mapping c is first replaced by c / (1 + c)
+    */
+    fn pixel_bytes_with_gamma(&self, x: usize, y: usize, gamma: f64) -> [u8; 3] {
+        let offset = if self.dither {
+            (BAYER_4X4[y % 4][x % 4] + 0.5) / 16.0
+        } else {
+            0.0
+        };
+        let mut color = self.get_pixel(x, y).sanitized();
+        if self.tone_map {
+            color = color.tone_map_reinhard();
+        }
+        let color = if gamma == 1.0 {
+            color
+        } else {
+            color.gamma_corrected(gamma)
+        };
+        color.quantize_dithered(offset)
+    }
+
+    /*
+        Copy out the w by h region whose top left corner is at (x, y),
+        the region must lie entirely inside of this canvas
+    */
+    pub fn crop(&self, x: usize, y: usize, w: usize, h: usize) -> Canvas {
+        assert!(x + w <= self.width && y + h <= self.height);
+        let mut result = Canvas::new(w, h);
+        result.dither = self.dither;
+        result.gamma = self.gamma;
+        result.tone_map = self.tone_map;
+
+        for row in 0..h {
+            result.canvas[row].copy_from_slice(&self.canvas[y + row][x..x + w]);
+        }
+
+        result
+    }
+
+    /*
+        Copy src onto this canvas with its top left corner at (x, y),
+        any part of src which falls outside of this canvas is dropped
+    */
+    pub fn paste(&mut self, src: &Canvas, x: usize, y: usize) {
+        let w = src.width.min(self.width.saturating_sub(x));
+        let h = src.height.min(self.height.saturating_sub(y));
+
+        for row in 0..h {
+            self.canvas[y + row][x..x + w].copy_from_slice(&src.canvas[row][..w]);
+        }
+    }
+
+    /*
+        Scale the image to any size, pixel centers are lined up so the
+        image is not shifted by half a pixel in either direction
+    */
+    pub fn resize(&self, width: usize, height: usize, mode: ResizeMode) -> Canvas {
+        assert!(width > 0 && height > 0);
+        let mut result = Canvas::new(width, height);
+        result.dither = self.dither;
+        result.gamma = self.gamma;
+        result.tone_map = self.tone_map;
+
+        let sx = self.width as f64 / width as f64;
+        let sy = self.height as f64 / height as f64;
+        for y in 0..height {
+            for x in 0..width {
+                let color = match mode {
+                    ResizeMode::Nearest => {
+                        let src_x = ((x as f64 + 0.5) * sx) as usize;
+                        let src_y = ((y as f64 + 0.5) * sy) as usize;
+                        self.get_pixel(src_x.min(self.width - 1), src_y.min(self.height - 1))
+                    }
+                    ResizeMode::Bilinear => {
+                        // position in the original image measured between pixel centers
+                        let fx = ((x as f64 + 0.5) * sx - 0.5).clamp(0.0, (self.width - 1) as f64);
+                        let fy = ((y as f64 + 0.5) * sy - 0.5).clamp(0.0, (self.height - 1) as f64);
+                        let (x0, y0) = (fx as usize, fy as usize);
+                        let x1 = (x0 + 1).min(self.width - 1);
+                        let y1 = (y0 + 1).min(self.height - 1);
+                        let (tx, ty) = (fx - x0 as f64, fy - y0 as f64);
+
+                        let top = self.get_pixel(x0, y0) * (1.0 - tx) + self.get_pixel(x1, y0) * tx;
+                        let bottom =
+                            self.get_pixel(x0, y1) * (1.0 - tx) + self.get_pixel(x1, y1) * tx;
+                        top * (1.0 - ty) + bottom * ty
+                    }
+                };
+                result.write_pixel(x, y, color);
+            }
+        }
+
+        result
+    }
+
+    /*
+        A 64 bit FNV-1a hash of the size and the undithered bytes of every pixel,
+        stable between runs and platforms so it can be stored in a test
+    */
+    pub fn hash_u64(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut add = |byte: u8| {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        };
+
+        for b in (self.width as u64)
+            .to_le_bytes()
+            .into_iter()
+            .chain((self.height as u64).to_le_bytes())
+        {
+            add(b);
+        }
+        for row in &self.canvas {
+            for pixel in row {
+                pixel
+                    .sanitized()
+                    .quantize_dithered(0.0)
+                    .into_iter()
+                    .for_each(&mut add);
+            }
+        }
+        hash
+    }
+
+    /*
+        The image as 8 bit RGB, the same bytes in the same order as the PPM,
+        the top row first and each row from left to right
+    */
+    pub fn to_rgb_image(&self) -> image::RgbImage {
+        image::RgbImage::from_fn(self.width as u32, self.height as u32, |x, y| {
+            image::Rgb(self.pixel_bytes(x as usize, y as usize))
+        })
+    }
+
+    pub fn write_to_png(&self, file_name: &str) {
+        self.to_rgb_image()
+            .save_with_format(file_name, image::ImageFormat::Png)
+            .expect("could not write png");
+    }
+
     pub fn write_to_ppm(&self, file_name: &str) {
-        let mut file = File::create(file_name).expect("could not create file");
+        self.write_to_ppm_with_gamma(file_name, self.gamma);
+    }
+
+    /*
+        Write a plain PPM with every channel c encoded as max(c, 0)^(1 / gamma)
+        before it is scaled to 0 to 255, DEFAULT_GAMMA suits most displays
+    */
+    pub fn write_to_ppm_with_gamma(&self, file_name: &str, gamma: f64) {
+        let file = File::create(file_name).expect("could not create file");
+        self.write_plain_ppm(&mut BufWriter::new(file), gamma)
+            .unwrap();
+    }
 
+    pub fn write_to_ppm_binary(&self, file_name: &str) {
+        let file = File::create(file_name).expect("could not create file");
+        self.write_ppm_binary_to(&mut BufWriter::new(file)).unwrap();
+    }
+
+    /*
+        Write the image as a binary (P6) PPM, the same pixels as the plain
+        format but a byte per channel instead of up to four characters
+    */
+    pub fn write_ppm_binary_to<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                writer.write_all(&self.pixel_bytes(x, y))?;
+            }
+        }
+        writer.flush()
+    }
+
+    /*
+        Write the image as a plain PPM to any writer, such as stdout so the
+        output can be piped into another program
+    */
+    pub fn write_ppm_to<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        self.write_plain_ppm(writer, self.gamma)
+    }
+
+    fn write_plain_ppm<W: Write + ?Sized>(&self, writer: &mut W, gamma: f64) -> io::Result<()> {
         // file header
-        writeln!(&mut file, "P3").unwrap();
-        writeln!(&mut file, "{} {}", self.width, self.height).unwrap();
-        writeln!(&mut file, "255").unwrap();
+        writeln!(writer, "P3")?;
+        writeln!(writer, "{} {}", self.width, self.height)?;
+        writeln!(writer, "255")?;
 
         for y in 0..self.height {
-            let mut builder: String = "".to_string();
+            // each row starts on a new line and long rows are wrapped to stay within the spec
+            let mut line_length = 0;
             for x in 0..self.width {
-                builder.push_str(&format!("{} ", self.get_pixel(x, y)));
+                for value in self.pixel_bytes_with_gamma(x, y, gamma) {
+                    let digits = match value {
+                        100.. => 3,
+                        10.. => 2,
+                        _ => 1,
+                    };
+                    if line_length == 0 {
+                        line_length = digits;
+                    } else if line_length + 1 + digits > PPM_LINE_LIMIT {
+                        writeln!(writer)?;
+                        line_length = digits;
+                    } else {
+                        write!(writer, " ")?;
+                        line_length += 1 + digits;
+                    }
+                    write!(writer, "{}", value)?;
+                }
             }
-            writeln!(&mut file, "{}", builder).unwrap();
+            writeln!(writer)?;
         }
+        writer.flush()
     }
 }
 
-pub fn stitch_canvases(canvases: Vec<Canvas>) -> Canvas {
-    assert!(!canvases.is_empty());
-    let width = canvases[0].width;
-    let height = canvases.iter().map(|c| c.height).sum();
-    let mut result = Canvas::new(width, height);
-    let mut res_y = 0;
+#[cfg(test)]
+mod test {
+    use super::*;
 
-    for canvas in canvases {
-        for y in 0..canvas.height {
-            for x in 0..canvas.width {
-                result.write_pixel(x, res_y, canvas.get_pixel(x, y));
+    #[test]
+    fn comparing_and_hashing_canvases() {
+        let c = gradient_canvas(4, 3);
+        let mut other = gradient_canvas(4, 3);
+        assert!(c == other);
+        assert_eq!(c.hash_u64(), other.hash_u64());
+
+        other.write_pixel(2, 1, Color::new(0.9, 0.1, 0.5));
+        assert!(c != other);
+        assert_ne!(c.hash_u64(), other.hash_u64());
+
+        // the same pixels in a different shape are a different image
+        let mut wide = Canvas::new(3, 4);
+        wide.paste(&c, 0, 0);
+        assert!(c != wide);
+        assert_ne!(Canvas::new(2, 6).hash_u64(), Canvas::new(3, 4).hash_u64());
+
+        // the hash only depends on the image so it can be written down in a test
+        assert_eq!(Canvas::new(1, 1).hash_u64(), 0xd4a1_b510_e8b2_d617);
+    }
+
+    fn gradient_canvas(width: usize, height: usize) -> Canvas {
+        let mut c = Canvas::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                c.write_pixel(x, y, Color::new(x as f64 / 10.0, y as f64 / 10.0, 0.5));
             }
-            res_y += 1;
         }
+        c
     }
 
-    result
+    #[test]
+    fn crop_then_paste_is_identity() {
+        let original = gradient_canvas(6, 5);
+        let region = original.crop(1, 2, 4, 3);
+        assert_eq!(region.width, 4);
+        assert_eq!(region.height, 3);
+        assert_eq!(region.get_pixel(0, 0), original.get_pixel(1, 2));
+        assert_eq!(region.get_pixel(3, 2), original.get_pixel(4, 4));
+
+        let mut c = gradient_canvas(6, 5);
+        for y in 2..5 {
+            for x in 1..5 {
+                c.write_pixel(x, y, Color::black());
+            }
+        }
+        c.paste(&region, 1, 2);
+        for y in 0..5 {
+            for x in 0..6 {
+                assert_eq!(c.get_pixel(x, y), original.get_pixel(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn resizing_a_canvas() {
+        let (a, b, c, d) = (
+            Color::new(0.0, 0.0, 0.0),
+            Color::new(0.8, 0.0, 0.4),
+            Color::new(0.0, 0.4, 0.8),
+            Color::new(0.8, 0.8, 0.8),
+        );
+        let mut small = Canvas::new(2, 2);
+        small.write_pixel(0, 0, a);
+        small.write_pixel(1, 0, b);
+        small.write_pixel(0, 1, c);
+        small.write_pixel(1, 1, d);
+
+        // each pixel becomes a 2x2 block
+        let nearest = small.resize(4, 4, ResizeMode::Nearest);
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(nearest.get_pixel(x, y), small.get_pixel(x / 2, y / 2));
+            }
+        }
+
+        // corners keep their color and the pixels between them blend a quarter at a time
+        let bilinear = small.resize(4, 4, ResizeMode::Bilinear);
+        assert_eq!(bilinear.get_pixel(0, 0), a);
+        assert_eq!(bilinear.get_pixel(3, 3), d);
+        let top: Vec<Color> = (0..4).map(|x| bilinear.get_pixel(x, 0)).collect();
+        assert_eq!(top, vec![a, b * 0.25, b * 0.75, b]);
+        assert_eq!(bilinear.get_pixel(0, 1), c * 0.25);
+        assert_eq!(
+            bilinear.get_pixel(1, 1),
+            (a * 0.75 + b * 0.25) * 0.75 + (c * 0.75 + d * 0.25) * 0.25
+        );
+
+        // shrinking back gives the original
+        assert!(nearest.resize(2, 2, ResizeMode::Nearest) == small);
+    }
+
+    #[test]
+    fn paste_out_of_bounds_is_clipped() {
+        let mut c = Canvas::new(4, 4);
+        let src = gradient_canvas(3, 3);
+
+        c.paste(&src, 2, 3);
+        assert_eq!(c.get_pixel(2, 3), src.get_pixel(0, 0));
+        assert_eq!(c.get_pixel(3, 3), src.get_pixel(1, 0));
+        assert_eq!(c.get_pixel(1, 3), Color::black());
+        assert_eq!(c.get_pixel(2, 2), Color::black());
+
+        // entirely outside the canvas
+        c.paste(&src, 10, 10);
+    }
+
+    #[test]
+    fn dithering_off_matches_plain_output() {
+        let mut c = Canvas::new(4, 4);
+        c.write_pixel(1, 2, Color::new(0.5, 1.5, -0.5));
+        assert_eq!(c.pixel_bytes(1, 2), [127, 255, 0]);
+        assert_eq!(format!("{}", c.get_pixel(1, 2)), "127 255 0");
+    }
+
+    #[test]
+    fn non_finite_pixels_are_written_black() {
+        let mut c = Canvas::new(3, 1);
+        c.write_pixel(0, 0, Color::new(f64::NAN, f64::NAN, f64::NAN));
+        c.write_pixel(1, 0, Color::new(f64::INFINITY, 0.5, f64::NEG_INFINITY));
+        c.write_pixel(2, 0, Color::new(1.0, f64::NAN, 0.0));
+        assert_eq!(c.pixel_bytes(0, 0), [0, 0, 0]);
+        assert_eq!(c.pixel_bytes(1, 0), [0, 127, 0]);
+        assert_eq!(c.pixel_bytes(2, 0), [255, 0, 0]);
+
+        c.dither = true;
+        assert_eq!(c.pixel_bytes(0, 0), [0, 0, 0]);
+
+        let path =
+            std::env::temp_dir().join(format!("ray_tracer_non_finite_{}.ppm", std::process::id()));
+        let path = path.to_str().unwrap();
+        c.dither = false;
+        c.write_to_ppm(path);
+        let contents = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(contents.lines().nth(3).unwrap(), "0 0 0 0 127 0 255 0 0");
+    }
+
+    #[test]
+    fn writing_ppm_to_a_buffer() {
+        let mut c = Canvas::new(5, 3);
+        c.write_pixel(0, 0, Color::new(1.5, 0.0, 0.0));
+        c.write_pixel(2, 1, Color::new(0.0, 0.5, 0.0));
+        c.write_pixel(4, 2, Color::new(-0.5, 0.0, 1.0));
+
+        let mut buffer: Vec<u8> = vec![];
+        c.write_ppm_to(&mut buffer).unwrap();
+        let ppm = String::from_utf8(buffer).unwrap();
+        let lines: Vec<&str> = ppm.lines().collect();
+
+        assert_eq!(&lines[..3], &["P3", "5 3", "255"]);
+        assert_eq!(lines.len(), 3 + c.height);
+        assert_eq!(lines[3], "255 0 0 0 0 0 0 0 0 0 0 0 0 0 0");
+        assert_eq!(lines[4], "0 0 0 0 0 0 0 127 0 0 0 0 0 0 0");
+        assert_eq!(lines[5], "0 0 0 0 0 0 0 0 0 0 0 0 0 0 255");
+
+        // a header of 11 bytes followed by three rows of 31 bytes and a newline
+        assert_eq!(ppm.len(), 11 + 3 * 32);
+
+        // any writer works, including one only known at runtime
+        let mut streamed: Vec<u8> = vec![];
+        let writer: &mut dyn Write = &mut streamed;
+        c.write_ppm_to(writer).unwrap();
+        assert_eq!(String::from_utf8(streamed).unwrap(), ppm);
+    }
+
+    #[test]
+    fn png_matches_ppm() {
+        let mut c = gradient_canvas(7, 3);
+        c.write_pixel(2, 1, Color::new(f64::NAN, 3.0, -1.0));
+        let path =
+            std::env::temp_dir().join(format!("ray_tracer_canvas_{}.png", std::process::id()));
+        let path = path.to_str().unwrap();
+        c.write_to_png(path);
+        let png = image::open(path).unwrap().to_rgb8();
+        std::fs::remove_file(path).unwrap();
+
+        let mut ppm = vec![];
+        c.write_ppm_to(&mut ppm).unwrap();
+        let ppm: Vec<u8> = String::from_utf8(ppm)
+            .unwrap()
+            .split_whitespace()
+            .skip(4)
+            .map(|v| v.parse().unwrap())
+            .collect();
+
+        assert_eq!((png.width(), png.height()), (7, 3));
+        assert_eq!(png.into_raw(), ppm);
+    }
+
+    #[test]
+    fn writing_a_binary_ppm() {
+        let mut c = Canvas::new(3, 2);
+        c.write_pixel(0, 0, Color::new(1.5, 0.0, 0.0));
+        c.write_pixel(1, 0, Color::new(0.0, 0.5, 0.0));
+        c.write_pixel(2, 1, Color::new(-0.5, 0.0, 1.0));
+
+        let path =
+            std::env::temp_dir().join(format!("ray_tracer_binary_{}.ppm", std::process::id()));
+        let path = path.to_str().unwrap();
+        c.write_to_ppm_binary(path);
+        let bytes = std::fs::read(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let header = b"P6\n3 2\n255\n";
+        assert_eq!(&bytes[..header.len()], header);
+        assert_eq!(bytes.len(), header.len() + 3 * 2 * 3);
+        let pixels = &bytes[header.len()..];
+        assert_eq!(&pixels[0..3], &[255, 0, 0]);
+        assert_eq!(&pixels[3..6], &[0, 127, 0]);
+        assert_eq!(&pixels[15..18], &[0, 0, 255]);
+    }
+
+    #[test]
+    fn reading_back_a_ppm() {
+        let c = gradient_canvas(6, 4);
+        let dir = std::env::temp_dir();
+        let plain = dir.join(format!("ray_tracer_round_trip_{}.ppm", std::process::id()));
+        let binary = dir.join(format!(
+            "ray_tracer_round_trip_binary_{}.ppm",
+            std::process::id()
+        ));
+        let (plain, binary) = (plain.to_str().unwrap(), binary.to_str().unwrap());
+        c.write_to_ppm(plain);
+        c.write_to_ppm_binary(binary);
+
+        for path in [plain, binary] {
+            let read = Canvas::from_ppm(path).unwrap();
+            std::fs::remove_file(path).unwrap();
+            assert_eq!((read.width, read.height), (6, 4));
+            for y in 0..4 {
+                for x in 0..6 {
+                    assert_eq!(read.pixel_bytes(x, y), c.pixel_bytes(x, y));
+                }
+            }
+        }
+
+        let bad = dir.join(format!("ray_tracer_bad_{}.ppm", std::process::id()));
+        let bad = bad.to_str().unwrap();
+        std::fs::write(bad, "P3\n2 x\n255\n").unwrap();
+        let err = Canvas::from_ppm(bad).err().unwrap();
+        std::fs::remove_file(bad).unwrap();
+        assert!(err.contains("expected a number"), "{}", err);
+        assert!(Canvas::from_ppm("does/not/exist.ppm").is_err());
+    }
+
+    #[test]
+    fn gamma_correcting_the_output() {
+        let mut c = Canvas::new(2, 1);
+        c.write_pixel(0, 0, Color::new(0.5, 0.0, 1.0));
+        c.write_pixel(1, 0, Color::new(0.2, -0.3, 2.0));
+        assert_eq!(c.pixel_bytes(0, 0), [127, 0, 255]);
+
+        let path =
+            std::env::temp_dir().join(format!("ray_tracer_gamma_{}.ppm", std::process::id()));
+        let path = path.to_str().unwrap();
+        let write_with = |gamma: f64| {
+            c.write_to_ppm_with_gamma(path, gamma);
+            let ppm = std::fs::read_to_string(path).unwrap();
+            std::fs::remove_file(path).unwrap();
+            ppm.lines().nth(3).unwrap().to_string()
+        };
+
+        let mut linear = vec![];
+        c.write_ppm_to(&mut linear).unwrap();
+        assert_eq!(
+            write_with(1.0),
+            String::from_utf8(linear).unwrap().lines().nth(3).unwrap()
+        );
+        // 0.5^(1 / 2.2) = 0.72974 and 0.2^(1 / 2.2) = 0.48109
+        assert_eq!(write_with(DEFAULT_GAMMA), "186 0 255 122 0 255");
+
+        c.gamma = DEFAULT_GAMMA;
+        assert_eq!(c.pixel_bytes(1, 0), [122, 0, 255]);
+    }
+
+    #[test]
+    fn tone_mapping_rolls_off_highlights() {
+        let mut c = Canvas::new(3, 1);
+        c.write_pixel(0, 0, Color::new(1.0, 3.0, 9.0));
+        c.write_pixel(1, 0, Color::new(0.0, -2.0, f64::INFINITY));
+        c.write_pixel(2, 0, Color::new(0.25, 0.5, 1.0));
+        assert_eq!(c.pixel_bytes(0, 0), [255, 255, 255]);
+
+        c.tone_map = true;
+        // 1 / 2, 3 / 4 and 9 / 10 stay distinct instead of clipping to white
+        assert_eq!(c.pixel_bytes(0, 0), [127, 191, 229]);
+        assert_eq!(c.pixel_bytes(1, 0), [0, 0, 0]);
+        assert_eq!(c.pixel_bytes(2, 0), [51, 85, 127]);
+    }
+
+    #[test]
+    fn long_ppm_rows_are_wrapped() {
+        let mut c = Canvas::new(10, 2);
+        for y in 0..2 {
+            for x in 0..10 {
+                c.write_pixel(x, y, Color::new(1.0, 0.8, 0.6));
+            }
+        }
+
+        let mut buffer: Vec<u8> = vec![];
+        c.write_ppm_to(&mut buffer).unwrap();
+        let ppm = String::from_utf8(buffer).unwrap();
+        assert!(ppm.lines().all(|l| l.len() <= 70));
+        assert!(ppm.ends_with('\n'));
+
+        let lines: Vec<&str> = ppm.lines().collect();
+        assert_eq!(
+            lines[3],
+            "255 204 153 255 204 153 255 204 153 255 204 153 255 204 153 255 204"
+        );
+        assert_eq!(
+            lines[4],
+            "153 255 204 153 255 204 153 255 204 153 255 204 153"
+        );
+
+        // the body is still a plain list of numbers, one per channel
+        let mut tokens = ppm.split_whitespace();
+        assert_eq!(tokens.next(), Some("P3"));
+        let dimensions: Vec<usize> = tokens
+            .by_ref()
+            .take(2)
+            .map(|t| t.parse().unwrap())
+            .collect();
+        assert_eq!(dimensions, vec![10, 2]);
+        assert_eq!(tokens.next(), Some("255"));
+        let values: Vec<u8> = tokens.map(|t| t.parse().unwrap()).collect();
+        assert_eq!(values.len(), 10 * 2 * 3);
+        assert!(values.chunks(3).all(|p| p == [255, 204, 153]));
+    }
+
+    #[test]
+    fn dithering_flat_field() {
+        let mut c = Canvas::new(8, 8);
+        c.dither = true;
+        for y in 0..8 {
+            for x in 0..8 {
+                c.write_pixel(x, y, Color::new(0.5, 0.5, 0.5));
+            }
+        }
+
+        let mut values = vec![];
+        for y in 0..8 {
+            for x in 0..8 {
+                values.push(c.pixel_bytes(x, y)[0]);
+            }
+        }
+
+        assert!(values.iter().all(|v| *v == 127 || *v == 128));
+        assert!(values.contains(&127));
+        assert!(values.contains(&128));
+        let average = values.iter().map(|v| *v as f64).sum::<f64>() / values.len() as f64;
+        assert!((average / 255.0 - 0.5).abs() < 0.01);
+    }
 }
@@ -3,6 +3,7 @@ use std::{fmt, ops};
 use crate::math::utils::f64_eq;
 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
 pub struct Color {
     r: f64,
     g: f64,
@@ -29,6 +30,11 @@ impl ops::AddAssign for Color {
     }
 }
 
+/*
+    Channels are allowed to go negative, patterns such as Gradient take the
+    difference between two colors and add it back on scaled by a fraction.
+    Use saturating_sub when the result is meant to be a color in its own right
+*/
 impl ops::Sub for Color {
     type Output = Self;
 
@@ -145,12 +151,184 @@ impl Color {
         }
     }
 
+    /*
+        Parse a color written as "#RRGGBB" or "RRGGBB", each pair of hex
+        digits is a channel from 00 to ff scaled into 0 to 1
+    */
+    pub fn from_hex(hex: &str) -> Result<Color, String> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("{} is not a color of the form #RRGGBB", hex));
+        }
+        let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).unwrap() as f64 / 255.0;
+        Ok(Color::new(channel(0), channel(2), channel(4)))
+    }
+
+    pub fn red(&self) -> f64 {
+        self.r
+    }
+
+    pub fn green(&self) -> f64 {
+        self.g
+    }
+
+    pub fn blue(&self) -> f64 {
+        self.b
+    }
+
+    // perceived brightness using the Rec. 709 weights for linear light
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    pub fn grayscale(&self) -> Color {
+        let l = self.luminance();
+        Color::new(l, l, l)
+    }
+
+    // cap each channel at 1.0 so the color never goes brighter than white
+    pub fn clamp_to_white(&self) -> Color {
+        Color {
+            r: self.r.min(1.0),
+            g: self.g.min(1.0),
+            b: self.b.min(1.0),
+        }
+    }
+
+    // subtract channel by channel, stopping at black rather than going negative
+    pub fn saturating_sub(&self, other: Color) -> Color {
+        Color {
+            r: (self.r - other.r).max(0.0),
+            g: (self.g - other.g).max(0.0),
+            b: (self.b - other.b).max(0.0),
+        }
+    }
+
+    // the mean of a set of samples, black when there are none
+    pub fn average(colors: &[Color]) -> Color {
+        if colors.is_empty() {
+            return Color::black();
+        }
+        let mut total = Color::black();
+        for c in colors {
+            total += *c;
+        }
+        total / colors.len() as f64
+    }
+
+    /*
+        Replace any channel which is NaN or infinite with 0, bad material math
+        would otherwise show up as stray speckles once quantized
+    */
+    pub fn sanitized(&self) -> Color {
+        let channel = |v: f64| if v.is_finite() { v } else { 0.0 };
+        Color {
+            r: channel(self.r),
+            g: channel(self.g),
+            b: channel(self.b),
+        }
+    }
+
+    /*
+        Reinhard's operator, each channel c becomes c / (1 + c) so bright values
+        roll off towards 1 instead of clipping, negative channels become 0
+    */
+    pub fn tone_map_reinhard(&self) -> Color {
+        let channel = |v: f64| {
+            let v = v.max(0.0);
+            v / (1.0 + v)
+        };
+        Color {
+            r: channel(self.r),
+            g: channel(self.g),
+            b: channel(self.b),
+        }
+    }
+
+    /*
+        Encode a linear color for display, each channel becomes
+        max(c, 0)^(1 / gamma) so a gamma of 1 leaves the color unchanged
+    */
+    pub fn gamma_corrected(&self, gamma: f64) -> Color {
+        assert!(gamma > 0.0);
+        let channel = |v: f64| v.max(0.0).powf(1.0 / gamma);
+        Color {
+            r: channel(self.r),
+            g: channel(self.g),
+            b: channel(self.b),
+        }
+    }
+
+    /*
+        Approximate the color of a blackbody radiator at the given temperature in kelvin,
+        based on Tanner Helland's curve fit which holds from roughly 1000K to 40000K
+    */
+    pub fn from_temperature(kelvin: f64) -> Color {
+        let temp = kelvin.clamp(1000.0, 40000.0) / 100.0;
+
+        let r = if temp <= 66.0 {
+            255.0
+        } else {
+            329.698727446 * (temp - 60.0).powf(-0.1332047592)
+        };
+
+        let g = if temp <= 66.0 {
+            99.4708025861 * temp.ln() - 161.1195681661
+        } else {
+            288.1221695283 * (temp - 60.0).powf(-0.0755148492)
+        };
+
+        let b = if temp >= 66.0 {
+            255.0
+        } else if temp <= 19.0 {
+            0.0
+        } else {
+            138.5177312231 * (temp - 10.0).ln() - 305.0447927307
+        };
+
+        Color::new(
+            r.clamp(0.0, 255.0) / 255.0,
+            g.clamp(0.0, 255.0) / 255.0,
+            b.clamp(0.0, 255.0) / 255.0,
+        )
+    }
+
     fn clamp(val: f64) -> u8 {
+        Color::quantize(val, 0.0)
+    }
+
+    // the bytes the PPM writer would use for this color, without dithering,
+    // borrowed like the rest of the conversions on Color even though it is Copy
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_rgb8(&self) -> [u8; 3] {
+        self.sanitized().quantize_dithered(0.0)
+    }
+
+    // the same bytes with a fully opaque alpha
+    #[allow(clippy::wrong_self_convention)]
+    pub fn to_rgba8(&self) -> [u8; 4] {
+        let [r, g, b] = self.to_rgb8();
+        [r, g, b, 255]
+    }
+
+    /*
+        Convert each channel to a byte after adding a dither offset in the range [0, 1),
+        an offset of 0 gives the same result as the plain PPM output
+    */
+    pub fn quantize_dithered(&self, offset: f64) -> [u8; 3] {
+        [
+            Color::quantize(self.r, offset),
+            Color::quantize(self.g, offset),
+            Color::quantize(self.b, offset),
+        ]
+    }
+
+    fn quantize(val: f64, offset: f64) -> u8 {
         if val < 0.0 {
             0
         } else {
             // max u8 is 255 so no truncation needed
-            (val * 255.0) as u8
+            (val * 255.0 + offset) as u8
         }
     }
 }
@@ -167,6 +345,17 @@ mod test {
         assert_eq!(Color::clamp(0.5), 127);
     }
 
+    #[test]
+    fn temperature_colors() {
+        let daylight = Color::from_temperature(6500.0);
+        assert!(daylight.r > 0.95 && daylight.g > 0.95 && daylight.b > 0.95);
+
+        let candle = Color::from_temperature(2000.0);
+        assert!(f64_eq(candle.r, 1.0));
+        assert!(candle.g < 0.6);
+        assert!(candle.b < 0.1);
+    }
+
     #[test]
     fn color_create() {
         let c = Color::new(0.1, 0.2, 0.3);
@@ -188,6 +377,27 @@ mod test {
         assert!(res == c1);
     }
 
+    #[test]
+    fn saturating_subtraction_stops_at_black() {
+        let dark = Color::new(0.2, 0.5, 0.0);
+        let light = Color::new(0.7, 0.1, 0.25);
+
+        // a gradient from light to dark steps by a negative difference
+        let step = dark - light;
+        assert_eq!(step, Color::new(-0.5, 0.4, -0.25));
+        assert_eq!(light + step * 0.5, Color::new(0.45, 0.3, 0.125));
+
+        let clamped = dark.saturating_sub(light);
+        assert_eq!(clamped, Color::new(0.0, 0.4, 0.0));
+        for c in [
+            clamped,
+            light.saturating_sub(Color::white()),
+            Color::black().saturating_sub(dark),
+        ] {
+            assert!(c.red() >= 0.0 && c.green() >= 0.0 && c.blue() >= 0.0);
+        }
+    }
+
     #[test]
     fn subtracting_colors() {
         let mut c1 = Color::new(0.9, 0.6, 0.75);
@@ -217,6 +427,54 @@ mod test {
         assert!(res == c);
     }
 
+    #[test]
+    fn averaging_colors() {
+        assert_eq!(
+            Color::average(&[Color::white(), Color::black()]),
+            Color::new(0.5, 0.5, 0.5)
+        );
+        assert_eq!(
+            Color::average(&[Color::new(0.3, 0.0, 0.9); 3]),
+            Color::new(0.3, 0.0, 0.9)
+        );
+        assert_eq!(Color::average(&[]), Color::black());
+    }
+
+    #[test]
+    fn colors_from_hex() {
+        assert_eq!(Color::from_hex("#ff0000"), Ok(Color::new(1.0, 0.0, 0.0)));
+        assert_eq!(
+            Color::from_hex("808080"),
+            Ok(Color::new(128.0 / 255.0, 128.0 / 255.0, 128.0 / 255.0))
+        );
+        assert_eq!(
+            Color::from_hex("#1A2b3C"),
+            Ok(Color::new(0.10196, 0.16863, 0.23529))
+        );
+        for bad in ["", "#", "#fff", "ff00000", "#gg0000", "##ff000", "+f0000"] {
+            assert!(Color::from_hex(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn packing_colors_into_bytes() {
+        let c = Color::new(1.5, -0.2, 0.5);
+        assert_eq!(c.to_rgb8(), [255, 0, 127]);
+        assert_eq!(c.to_rgba8(), [255, 0, 127, 255]);
+        assert_eq!(Color::new(f64::NAN, 1.0, 0.0).to_rgba8(), [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn luminance_and_grayscale() {
+        assert!(f64_eq(Color::new(0.0, 1.0, 0.0).luminance(), 0.7152));
+        assert!(f64_eq(Color::new(1.0, 0.0, 0.0).luminance(), 0.2126));
+        assert!(f64_eq(Color::white().luminance(), 1.0));
+        assert_eq!(
+            Color::new(0.2, 0.4, 0.8).grayscale(),
+            Color::new(0.38636, 0.38636, 0.38636)
+        );
+    }
+
     #[test]
     fn mul_colors() {
         let mut c1 = Color::new(1.0, 0.2, 0.4);
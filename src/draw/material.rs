@@ -3,15 +3,21 @@ use super::{
     patterns::{Pattern, Solid},
 };
 
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
 pub struct Material {
+    #[cfg_attr(feature = "cache", serde(with = "crate::scene::cache::pattern"))]
     pub pattern: Box<dyn Pattern>,
-    pub ambient: f64,      // between 0 and 1
-    pub diffuse: f64,      // between 0 and 1
-    pub specular: f64,     // between 0 and 1
-    pub shininess: f64,    // between 10 and 200 (large to small)
-    pub reflective: f64,   // between 0 and 1
-    pub transparency: f64, // between 0 and 1
-    pub refractive_index: f64,
+    pub ambient: f64,          // between 0 and 1
+    pub diffuse: f64,          // between 0 and 1
+    pub specular: f64,         // between 0 and 1
+    pub shininess: f64,        // between 10 and 200 (large to small)
+    pub reflective: f64,       // between 0 and 1
+    pub transparency: f64,     // between 0 and 1
+    pub refractive_index: f64, // at least 1, 1 for a vacuum and around 1.5 for glass
+    pub double_sided: bool,    // light thin surfaces from either side
+    pub cull_backfaces: bool, // triangles ignore rays arriving from behind, only for closed opaque meshes
+    pub absorption: Color, // how much of each channel transparent material soaks up per unit travelled inside it
+    pub shadow_catcher: bool, // invisible apart from the shadows falling on it, for compositing onto photos
 }
 
 impl Material {
@@ -25,6 +31,10 @@ impl Material {
             reflective: 0.0,
             transparency: 0.0,     // opaque
             refractive_index: 1.0, // vacuum
+            double_sided: false,
+            cull_backfaces: false,
+            absorption: Color::black(), // clear
+            shadow_catcher: false,
         }
     }
 
@@ -38,6 +48,199 @@ impl Material {
             reflective: mat.reflective,
             transparency: mat.transparency,
             refractive_index: mat.refractive_index,
+            double_sided: mat.double_sided,
+            cull_backfaces: mat.cull_backfaces,
+            absorption: mat.absorption,
+            shadow_catcher: mat.shadow_catcher,
         }
     }
+
+    pub fn builder() -> MaterialBuilder {
+        MaterialBuilder::new()
+    }
+
+    // a perfect mirror which takes its color entirely from its surroundings
+    pub fn mirror() -> Material {
+        Material::builder()
+            .color(Color::black())
+            .ambient(0.0)
+            .diffuse(0.0)
+            .specular(1.0)
+            .shininess(300.0)
+            .reflective(1.0)
+            .build()
+    }
+
+    // a rough surface with no highlights
+    pub fn matte(color: Color) -> Material {
+        Material::builder()
+            .color(color)
+            .diffuse(0.9)
+            .specular(0.0)
+            .build()
+    }
+
+    // a smooth surface with a small sharp highlight
+    pub fn plastic(color: Color) -> Material {
+        Material::builder()
+            .color(color)
+            .diffuse(0.7)
+            .specular(0.3)
+            .shininess(100.0)
+            .build()
+    }
+}
+
+/*
+    Build a material starting from the default one, only
+    the values which differ from the default need to be set
+*/
+pub struct MaterialBuilder {
+    material: Material,
+}
+
+impl MaterialBuilder {
+    pub fn new() -> MaterialBuilder {
+        MaterialBuilder {
+            material: Material::default_material(),
+        }
+    }
+
+    pub fn color(self, color: Color) -> MaterialBuilder {
+        self.pattern(Box::new(Solid::new(color)))
+    }
+
+    pub fn pattern(mut self, pattern: Box<dyn Pattern>) -> MaterialBuilder {
+        self.material.pattern = pattern;
+        self
+    }
+
+    pub fn ambient(mut self, ambient: f64) -> MaterialBuilder {
+        self.material.ambient = ambient;
+        self
+    }
+
+    pub fn diffuse(mut self, diffuse: f64) -> MaterialBuilder {
+        self.material.diffuse = diffuse;
+        self
+    }
+
+    pub fn specular(mut self, specular: f64) -> MaterialBuilder {
+        self.material.specular = specular;
+        self
+    }
+
+    pub fn shininess(mut self, shininess: f64) -> MaterialBuilder {
+        self.material.shininess = shininess;
+        self
+    }
+
+    pub fn reflective(mut self, reflective: f64) -> MaterialBuilder {
+        self.material.reflective = reflective;
+        self
+    }
+
+    pub fn transparent(mut self, transparency: f64, refractive_index: f64) -> MaterialBuilder {
+        self.material.transparency = transparency;
+        self.material.refractive_index = refractive_index;
+        self
+    }
+
+    pub fn double_sided(mut self, double_sided: bool) -> MaterialBuilder {
+        self.material.double_sided = double_sided;
+        self
+    }
+
+    pub fn absorption(mut self, absorption: Color) -> MaterialBuilder {
+        self.material.absorption = absorption;
+        self
+    }
+
+    pub fn cull_backfaces(mut self, cull_backfaces: bool) -> MaterialBuilder {
+        self.material.cull_backfaces = cull_backfaces;
+        self
+    }
+
+    pub fn shadow_catcher(mut self, shadow_catcher: bool) -> MaterialBuilder {
+        self.material.shadow_catcher = shadow_catcher;
+        self
+    }
+
+    pub fn build(self) -> Material {
+        self.material
+    }
+}
+
+impl Default for MaterialBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{examples::test_scene, math::tuples::Tuple};
+
+    use super::*;
+
+    fn assert_same(a: &Material, b: &Material) {
+        let origin = Tuple::point(0.0, 0.0, 0.0);
+        assert_eq!(a.pattern.color_at(&origin), b.pattern.color_at(&origin));
+        assert_eq!(a.ambient, b.ambient);
+        assert_eq!(a.diffuse, b.diffuse);
+        assert_eq!(a.specular, b.specular);
+        assert_eq!(a.shininess, b.shininess);
+        assert_eq!(a.reflective, b.reflective);
+        assert_eq!(a.transparency, b.transparency);
+        assert_eq!(a.refractive_index, b.refractive_index);
+        assert_eq!(a.double_sided, b.double_sided);
+    }
+
+    #[test]
+    fn builder_reproduces_glass_sphere() {
+        let (_, world) = test_scene();
+        let middle = world.objects[1].get_material();
+
+        let built = Material::builder()
+            .color(Color::black())
+            .ambient(0.1)
+            .diffuse(0.1)
+            .specular(1.0)
+            .shininess(300.0)
+            .reflective(0.9)
+            .transparent(1.0, 1.52)
+            .build();
+        assert_same(&built, middle);
+    }
+
+    #[test]
+    fn builder_defaults_to_default_material() {
+        assert_same(
+            &MaterialBuilder::new().build(),
+            &Material::default_material(),
+        );
+    }
+
+    #[test]
+    fn default_material_is_opaque() {
+        let m = Material::default_material();
+        assert_eq!(m.transparency, 0.0);
+        assert_eq!(m.refractive_index, 1.0);
+
+        let copy = Material::from_material(&Material::builder().transparent(0.7, 2.4).build());
+        assert_eq!(copy.transparency, 0.7);
+        assert_eq!(copy.refractive_index, 2.4);
+    }
+
+    #[test]
+    fn presets() {
+        assert_eq!(Material::mirror().reflective, 1.0);
+        let matte = Material::matte(Color::new(0.5, 0.2, 0.1));
+        assert_eq!(matte.specular, 0.0);
+        assert_eq!(
+            matte.pattern.color_at(&Tuple::point(1.0, 2.0, 3.0)),
+            Color::new(0.5, 0.2, 0.1)
+        );
+        assert!(Material::plastic(Color::white()).specular > 0.0);
+    }
 }
@@ -2,3 +2,4 @@ pub mod canvas;
 pub mod color;
 pub mod material;
 pub mod patterns;
+pub mod texture;
@@ -1,27 +1,98 @@
-use crate::math::{matrix::Matrix, tuples::Tuple};
+use std::{any::Any, f64::consts::PI};
+
+use crate::math::{matrix::Matrix, noise::PerlinNoise, tuples::Tuple};
 
 use super::color::Color;
 
 pub trait Pattern: Sync + Send {
     fn color_at(&self, point: &Tuple) -> Color;
-    fn transform(&self) -> &Matrix;
-    fn inverse_transform(&self) -> &Matrix;
-    fn set_transform(&mut self, transform: Matrix);
+    fn pattern_transform(&self) -> &PatternTransform;
+    fn pattern_transform_mut(&mut self) -> &mut PatternTransform;
     fn copy_pattern(&self) -> Box<dyn Pattern>;
+    fn as_any(&self) -> &dyn Any;
+
+    fn transform(&self) -> &Matrix {
+        &self.pattern_transform().transform
+    }
+
+    fn inverse_transform(&self) -> &Matrix {
+        &self.pattern_transform().inv_transform
+    }
+
+    fn set_transform(&mut self, transform: Matrix) {
+        self.pattern_transform_mut().set(transform);
+    }
+
+    /*
+        The color averaged over a disc of the given radius around the point, both
+        in pattern space, patterns which can not filter themselves use the center
+    */
+    fn color_at_footprint(&self, point: &Tuple, _radius: f64) -> Color {
+        self.color_at(point)
+    }
+
+    /*
+        The color of the pattern at a point given in the space of the shape it is
+        painted on, spread over the footprint of the ray when the radius is not zero
+    */
+    fn color_at_object(&self, object_point: &Tuple, radius: f64) -> Color {
+        let transform = self.pattern_transform();
+        let point = transform.point_in_pattern_space(object_point);
+        if radius > 0.0 {
+            self.color_at_footprint(&point, radius * transform.inv_transform.max_stretch())
+        } else {
+            self.color_at(&point)
+        }
+    }
+}
+
+/*
+    The transform shared by every pattern, the inverse is kept next to it
+    since that is the one needed to find where a point lands on the pattern
+*/
+#[derive(Clone)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
+pub struct PatternTransform {
+    transform: Matrix,
+    inv_transform: Matrix,
+}
+
+impl PatternTransform {
+    pub fn identity() -> PatternTransform {
+        PatternTransform {
+            transform: Matrix::identity(4),
+            inv_transform: Matrix::identity(4),
+        }
+    }
+
+    pub fn set(&mut self, transform: Matrix) {
+        self.inv_transform = transform.inverse();
+        self.transform = transform;
+    }
+
+    /*
+        Move a point from the space of the shape into the space of the pattern,
+        points in world space go through Intersectable::world_to_object first
+        so the transforms of any parent groups are taken into account
+    */
+    pub fn point_in_pattern_space(&self, object_point: &Tuple) -> Tuple {
+        &self.inv_transform * object_point
+    }
 }
 
 // --- Solid ----
 #[derive(Clone)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
 pub struct Solid {
     c: Color,
-    transform: Matrix,
+    transform: PatternTransform, // kept so every pattern behaves the same, though moving a solid changes nothing
 }
 
 impl Solid {
     pub fn new(c: Color) -> Solid {
         Solid {
             c,
-            transform: Matrix::identity(4),
+            transform: PatternTransform::identity(),
         }
     }
 }
@@ -31,17 +102,12 @@ impl Pattern for Solid {
         self.c
     }
 
-    fn transform(&self) -> &Matrix {
+    fn pattern_transform(&self) -> &PatternTransform {
         &self.transform
     }
 
-    fn set_transform(&mut self, _: Matrix) {
-        // a transform on a solid pattern does nothing
-    }
-
-    fn inverse_transform(&self) -> &Matrix {
-        // transforming a solid pattern does nothing
-        &self.transform
+    fn pattern_transform_mut(&mut self) -> &mut PatternTransform {
+        &mut self.transform
     }
 
     fn copy_pattern(&self) -> Box<dyn Pattern> {
@@ -50,16 +116,20 @@ impl Pattern for Solid {
             transform: self.transform.clone(),
         })
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 // --------
 
 // ---- Stripe ----
 #[derive(Clone)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
 pub struct Stripe {
     a: Color,
     b: Color,
-    transform: Matrix,
-    inv_transform: Matrix,
+    transform: PatternTransform,
 }
 
 impl Stripe {
@@ -67,8 +137,7 @@ impl Stripe {
         Stripe {
             a,
             b,
-            transform: Matrix::identity(4),
-            inv_transform: Matrix::identity(4),
+            transform: PatternTransform::identity(),
         }
     }
 }
@@ -82,17 +151,12 @@ impl Pattern for Stripe {
         }
     }
 
-    fn transform(&self) -> &Matrix {
+    fn pattern_transform(&self) -> &PatternTransform {
         &self.transform
     }
 
-    fn set_transform(&mut self, transform: Matrix) {
-        self.transform = transform;
-        self.inv_transform = self.transform.inverse();
-    }
-
-    fn inverse_transform(&self) -> &Matrix {
-        &self.inv_transform
+    fn pattern_transform_mut(&mut self) -> &mut PatternTransform {
+        &mut self.transform
     }
 
     fn copy_pattern(&self) -> Box<dyn Pattern> {
@@ -100,15 +164,77 @@ impl Pattern for Stripe {
             a: self.a,
             b: self.b,
             transform: self.transform.clone(),
-            inv_transform: self.inv_transform.clone(),
         })
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 #[cfg(test)]
 mod stripe_test {
+    use crate::{
+        scene::{
+            light::{Light, PointLight},
+            world::World,
+        },
+        shapes::{intersect::Intersectable, sphere::Sphere},
+    };
+
     use super::*;
 
+    #[test]
+    fn stripes_follow_object_and_pattern_transforms() {
+        let mut s = Sphere::new(Some(Matrix::translation(10.0, 0.0, 0.0)));
+        s.material.ambient = 1.0;
+        s.material.diffuse = 0.0;
+        s.material.specular = 0.0;
+        let mut stripes = Stripe::new(Color::white(), Color::black());
+        stripes.set_transform(Matrix::translation(0.5, 0.0, 0.0));
+        s.material.pattern = Box::new(stripes);
+
+        let w = World::new();
+        let light = PointLight::new(Color::white(), Tuple::point(0.0, 0.0, -10.0));
+        let shade = |x: f64| {
+            let point = Tuple::point(x, 0.0, 0.0);
+            let eyev = Tuple::vector(0.0, 0.0, -1.0);
+            let object_point = s.world_to_object(point, &w);
+            light.lighting(s.get_material(), point, eyev, eyev, 0.0, object_point)
+        };
+
+        // both shifts together move the first band to start at x = 10.5
+        assert_eq!(shade(10.4), Color::black());
+        assert_eq!(shade(10.6), Color::white());
+        assert_eq!(shade(11.4), Color::white());
+        assert_eq!(shade(11.6), Color::black());
+    }
+
+    #[test]
+    fn stripes_on_a_scaled_sphere() {
+        let w = World::new();
+        let stripe_at = |object: Option<Matrix>, pattern: Option<Matrix>, x: f64| {
+            let s = Sphere::new(object);
+            let mut stripes = Stripe::new(Color::white(), Color::black());
+            if let Some(m) = pattern {
+                stripes.set_transform(m);
+            }
+            let object_point = s.world_to_object(Tuple::point(x, 0.0, 0.0), &w);
+            stripes.color_at_object(&object_point, 0.0)
+        };
+
+        let scaled = || Some(Matrix::scaling(2.0, 2.0, 2.0));
+        assert_eq!(stripe_at(scaled(), None, 1.5), Color::white());
+        assert_eq!(stripe_at(None, scaled(), 1.5), Color::white());
+        assert_eq!(
+            stripe_at(scaled(), Some(Matrix::translation(0.5, 0.0, 0.0)), 2.5),
+            Color::white()
+        );
+        // a point that would land on the second band without the object scaling
+        assert_eq!(stripe_at(scaled(), scaled(), 3.9), Color::white());
+        assert_eq!(stripe_at(scaled(), scaled(), 4.1), Color::black());
+    }
+
     #[test]
     fn stripe_test() {
         let p = Stripe::new(Color::white(), Color::black());
@@ -123,11 +249,12 @@ mod stripe_test {
 
 // ---- Gradient ----
 
+#[derive(Clone)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
 pub struct Gradient {
     a: Color,
     b: Color,
-    transform: Matrix,
-    inv_transform: Matrix,
+    transform: PatternTransform,
 }
 
 impl Gradient {
@@ -135,8 +262,7 @@ impl Gradient {
         Gradient {
             a,
             b,
-            transform: Matrix::identity(4),
-            inv_transform: Matrix::identity(4),
+            transform: PatternTransform::identity(),
         }
     }
 }
@@ -148,17 +274,12 @@ impl Pattern for Gradient {
         self.a + distance * fraction
     }
 
-    fn transform(&self) -> &Matrix {
+    fn pattern_transform(&self) -> &PatternTransform {
         &self.transform
     }
 
-    fn set_transform(&mut self, transform: Matrix) {
-        self.transform = transform;
-        self.inv_transform = self.transform.inverse();
-    }
-
-    fn inverse_transform(&self) -> &Matrix {
-        &self.inv_transform
+    fn pattern_transform_mut(&mut self) -> &mut PatternTransform {
+        &mut self.transform
     }
 
     fn copy_pattern(&self) -> Box<dyn Pattern> {
@@ -166,9 +287,12 @@ impl Pattern for Gradient {
             a: self.a,
             b: self.b,
             transform: self.transform.clone(),
-            inv_transform: self.inv_transform.clone(),
         })
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 #[cfg(test)]
@@ -201,11 +325,11 @@ mod gradient_tests {
 
 // ---- Rings ----
 #[derive(Clone)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
 pub struct Rings {
     a: Color,
     b: Color,
-    transform: Matrix,
-    inv_transform: Matrix,
+    transform: PatternTransform,
 }
 
 impl Rings {
@@ -213,8 +337,7 @@ impl Rings {
         Rings {
             a,
             b,
-            transform: Matrix::identity(4),
-            inv_transform: Matrix::identity(4),
+            transform: PatternTransform::identity(),
         }
     }
 }
@@ -228,17 +351,12 @@ impl Pattern for Rings {
         }
     }
 
-    fn transform(&self) -> &Matrix {
+    fn pattern_transform(&self) -> &PatternTransform {
         &self.transform
     }
 
-    fn set_transform(&mut self, transform: Matrix) {
-        self.transform = transform;
-        self.inv_transform = self.transform.inverse();
-    }
-
-    fn inverse_transform(&self) -> &Matrix {
-        &self.inv_transform
+    fn pattern_transform_mut(&mut self) -> &mut PatternTransform {
+        &mut self.transform
     }
 
     fn copy_pattern(&self) -> Box<dyn Pattern> {
@@ -246,20 +364,23 @@ impl Pattern for Rings {
             a: self.a,
             b: self.b,
             transform: self.transform.clone(),
-            inv_transform: self.inv_transform.clone(),
         })
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 // --------
 
 // ---- Checkered ----
 #[derive(Clone)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
 pub struct Checkered {
     a: Color,
     b: Color,
-    transform: Matrix,
-    inv_transform: Matrix,
+    transform: PatternTransform,
 }
 
 impl Checkered {
@@ -267,8 +388,7 @@ impl Checkered {
         Checkered {
             a,
             b,
-            transform: Matrix::identity(4),
-            inv_transform: Matrix::identity(4),
+            transform: PatternTransform::identity(),
         }
     }
 }
@@ -282,17 +402,12 @@ impl Pattern for Checkered {
         }
     }
 
-    fn transform(&self) -> &Matrix {
+    fn pattern_transform(&self) -> &PatternTransform {
         &self.transform
     }
 
-    fn set_transform(&mut self, transform: Matrix) {
-        self.transform = transform;
-        self.inv_transform = self.transform.inverse();
-    }
-
-    fn inverse_transform(&self) -> &Matrix {
-        &self.inv_transform
+    fn pattern_transform_mut(&mut self) -> &mut PatternTransform {
+        &mut self.transform
     }
 
     fn copy_pattern(&self) -> Box<dyn Pattern> {
@@ -300,9 +415,554 @@ impl Pattern for Checkered {
             a: self.a,
             b: self.b,
             transform: self.transform.clone(),
-            inv_transform: self.inv_transform.clone(),
         })
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+// --------
+
+// ---- RadialGradient ----
+#[derive(Clone)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
+pub struct RadialGradient {
+    a: Color,
+    b: Color,
+    radius: f64, // distance from the origin at which the color reaches b
+    transform: PatternTransform,
+}
+
+impl RadialGradient {
+    pub fn new(a: Color, b: Color, radius: f64) -> RadialGradient {
+        assert!(radius > 0.0);
+        RadialGradient {
+            a,
+            b,
+            radius,
+            transform: PatternTransform::identity(),
+        }
+    }
+}
+
+impl Pattern for RadialGradient {
+    fn color_at(&self, point: &Tuple) -> Color {
+        let distance = (point.x * point.x + point.z * point.z).sqrt();
+        let fraction = (distance / self.radius).min(1.0);
+        self.a + (self.b - self.a) * fraction
+    }
+
+    fn pattern_transform(&self) -> &PatternTransform {
+        &self.transform
+    }
+
+    fn pattern_transform_mut(&mut self) -> &mut PatternTransform {
+        &mut self.transform
+    }
+
+    fn copy_pattern(&self) -> Box<dyn Pattern> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod radial_gradient_tests {
+    use super::*;
+
+    #[test]
+    fn radial_gradient_works() {
+        let pattern = RadialGradient::new(Color::white(), Color::black(), 2.0);
+        assert_eq!(
+            pattern.color_at(&Tuple::point(0.0, 0.0, 0.0)),
+            Color::white()
+        );
+        assert_eq!(
+            pattern.color_at(&Tuple::point(0.0, 5.0, 1.0)),
+            Color::new(0.5, 0.5, 0.5)
+        );
+        assert_eq!(
+            pattern.color_at(&Tuple::point(2.0, 0.0, 0.0)),
+            Color::black()
+        );
+        assert_eq!(
+            pattern.color_at(&Tuple::point(3.0, 0.0, -4.0)),
+            Color::black()
+        );
+    }
+}
+
+// --------
+
+// ---- UvPattern ----
+
+// converts a point in pattern space to a (u, v) pair, each between 0 and 1
+pub type UvMap = fn(&Tuple) -> (f64, f64);
+
+pub fn spherical_map(point: &Tuple) -> (f64, f64) {
+    // azimuthal angle around the y axis, in the range (-pi, pi]
+    let theta = point.x.atan2(point.z);
+    let radius = Tuple::vector(point.x, point.y, point.z).magnitude();
+    // polar angle from the top of the sphere, in the range [0, pi]
+    let phi = (point.y / radius).acos();
+
+    let raw_u = theta / (2.0 * PI);
+    // flip u so it increases counter clockwise when viewed from above
+    (1.0 - (raw_u + 0.5), 1.0 - phi / PI)
+}
+
+pub fn planar_map(point: &Tuple) -> (f64, f64) {
+    (point.x.rem_euclid(1.0), point.z.rem_euclid(1.0))
+}
+
+pub fn cylindrical_map(point: &Tuple) -> (f64, f64) {
+    let theta = point.x.atan2(point.z);
+    let raw_u = theta / (2.0 * PI);
+    (1.0 - (raw_u + 0.5), point.y.rem_euclid(1.0))
+}
+
+/*
+    A two dimensional pattern looked up by (u, v) rather than a point in space
+*/
+pub trait UvTexture: Sync + Send {
+    fn uv_color_at(&self, u: f64, v: f64) -> Color;
+    fn copy_texture(&self) -> Box<dyn UvTexture>;
+    fn as_any(&self) -> &dyn Any;
+
+    // the color averaged over a square of the given half width in uv space
+    fn uv_color_filtered(&self, u: f64, v: f64, _footprint: f64) -> Color {
+        self.uv_color_at(u, v)
+    }
+}
+
+#[derive(Clone)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
+pub struct UvCheckers {
+    width: f64,  // number of squares across u
+    height: f64, // number of squares across v
+    a: Color,
+    b: Color,
+}
+
+impl UvCheckers {
+    pub fn new(width: usize, height: usize, a: Color, b: Color) -> UvCheckers {
+        UvCheckers {
+            width: width as f64,
+            height: height as f64,
+            a,
+            b,
+        }
+    }
+}
+
+impl UvTexture for UvCheckers {
+    fn uv_color_at(&self, u: f64, v: f64) -> Color {
+        if ((u * self.width).floor() + (v * self.height).floor()) as i64 % 2 == 0 {
+            self.a
+        } else {
+            self.b
+        }
+    }
+
+    fn copy_texture(&self) -> Box<dyn UvTexture> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/*
+    Wraps a texture so it can be used as a pattern, points are mapped
+    to (u, v) coordinates by the mapping function before the lookup
+*/
+pub struct UvPattern {
+    map: UvMap,
+    texture: Box<dyn UvTexture>,
+    transform: PatternTransform,
+}
+
+impl UvPattern {
+    pub fn new(map: UvMap, texture: Box<dyn UvTexture>) -> UvPattern {
+        UvPattern {
+            map,
+            texture,
+            transform: PatternTransform::identity(),
+        }
+    }
+
+    pub fn map(&self) -> UvMap {
+        self.map
+    }
+
+    pub fn texture(&self) -> &dyn UvTexture {
+        self.texture.as_ref()
+    }
+}
+
+impl Pattern for UvPattern {
+    fn color_at(&self, point: &Tuple) -> Color {
+        let (u, v) = (self.map)(point);
+        self.texture.uv_color_at(u, v)
+    }
+
+    fn color_at_footprint(&self, point: &Tuple, radius: f64) -> Color {
+        let (u, v) = (self.map)(point);
+
+        // step the point along each axis to see how far the uv coordinates move,
+        // a step that wraps around the seam of the map is measured the short way
+        let wrapped = |d: f64| d.abs().min(1.0 - d.abs());
+        let footprint = [
+            Tuple::vector(radius, 0.0, 0.0),
+            Tuple::vector(0.0, radius, 0.0),
+            Tuple::vector(0.0, 0.0, radius),
+        ]
+        .iter()
+        .map(|step| {
+            let (su, sv) = (self.map)(&(*point + *step));
+            wrapped(su - u).max(wrapped(sv - v))
+        })
+        .fold(0.0, f64::max);
+
+        self.texture.uv_color_filtered(u, v, footprint)
+    }
+
+    fn pattern_transform(&self) -> &PatternTransform {
+        &self.transform
+    }
+
+    fn pattern_transform_mut(&mut self) -> &mut PatternTransform {
+        &mut self.transform
+    }
+
+    fn copy_pattern(&self) -> Box<dyn Pattern> {
+        Box::new(Self {
+            map: self.map,
+            texture: self.texture.copy_texture(),
+            transform: self.transform.clone(),
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod uv_pattern_tests {
+    use std::f64::consts::FRAC_1_SQRT_2;
+
+    use crate::{
+        scene::light::{Light, PointLight},
+        shapes::{intersect::Intersectable, sphere::Sphere},
+    };
+
+    use super::*;
+
+    #[test]
+    fn mapping_points_to_uv() {
+        let cases = [
+            (Tuple::point(0.0, 0.0, -1.0), (0.0, 0.5)),
+            (Tuple::point(1.0, 0.0, 0.0), (0.25, 0.5)),
+            (Tuple::point(0.0, 0.0, 1.0), (0.5, 0.5)),
+            (Tuple::point(-1.0, 0.0, 0.0), (0.75, 0.5)),
+            (Tuple::point(0.0, 1.0, 0.0), (0.5, 1.0)),
+            (
+                Tuple::point(FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0.0),
+                (0.25, 0.75),
+            ),
+        ];
+        for (point, (u, v)) in cases {
+            let (mu, mv) = spherical_map(&point);
+            assert!((mu - u).abs() < 1e-9 && (mv - v).abs() < 1e-9);
+        }
+
+        assert_eq!(planar_map(&Tuple::point(0.25, 0.0, -0.25)), (0.25, 0.75));
+        let (u, v) = cylindrical_map(&Tuple::point(1.0, 1.5, 0.0));
+        assert!((u - 0.25).abs() < 1e-9 && (v - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn checkers_on_a_sphere() {
+        let mut s = Sphere::new(None);
+        s.material.ambient = 1.0;
+        s.material.diffuse = 0.0;
+        s.material.specular = 0.0;
+        s.material.pattern = Box::new(UvPattern::new(
+            spherical_map,
+            Box::new(UvCheckers::new(16, 8, Color::black(), Color::white())),
+        ));
+
+        let light = PointLight::new(Color::white(), Tuple::point(0.0, 0.0, -10.0));
+        let shade = |point: Tuple| {
+            let eyev = Tuple::vector(0.0, 0.0, -1.0);
+            light.lighting(s.get_material(), point, eyev, eyev, 0.0, point)
+        };
+
+        // the front of the equator sits on the corner of four squares, (u, v) = (0, 0.5)
+        assert_eq!(shade(Tuple::point(0.0, 0.0, -1.0)), Color::black());
+        assert_eq!(shade(Tuple::point(-0.1, 0.0, -0.99499)), Color::white());
+        assert_eq!(shade(Tuple::point(0.1, 0.0, -0.99499)), Color::black());
+        assert_eq!(shade(Tuple::point(0.0, -0.1, -0.99499)), Color::white());
+    }
+}
+
+// --------
+
+// ---- Perturbed ----
+
+/*
+    Pushes each point around by Perlin noise before looking it up in another
+    pattern, which bends stripes and rings into marble and wood grain
+*/
+pub struct Perturbed {
+    pattern: Box<dyn Pattern>,
+    seed: u64, // kept so the noise can be rebuilt when the pattern is loaded from the cache
+    noise: PerlinNoise,
+    pub scale: f64,     // how far a point can be pushed
+    pub octaves: usize, // layers of ever finer noise added together for turbulence
+    transform: PatternTransform,
+}
+
+impl Perturbed {
+    pub fn new(pattern: Box<dyn Pattern>, seed: u64) -> Perturbed {
+        Perturbed {
+            pattern,
+            seed,
+            noise: PerlinNoise::new(seed),
+            scale: 0.2,
+            octaves: 1,
+            transform: PatternTransform::identity(),
+        }
+    }
+
+    pub fn pattern(&self) -> &dyn Pattern {
+        self.pattern.as_ref()
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    fn turbulence(&self, x: f64, y: f64, z: f64) -> f64 {
+        let mut total = 0.0;
+        let mut frequency = 1.0;
+        let mut amplitude = 1.0;
+        for _ in 0..self.octaves {
+            total += amplitude
+                * self
+                    .noise
+                    .noise(x * frequency, y * frequency, z * frequency);
+            frequency *= 2.0;
+            amplitude *= 0.5;
+        }
+        total
+    }
+}
+
+impl Pattern for Perturbed {
+    fn color_at(&self, point: &Tuple) -> Color {
+        // sample the field at shifted points so each axis moves independently
+        let (x, y, z) = (point.x, point.y, point.z);
+        let jittered = Tuple::point(
+            x + self.scale * self.turbulence(x, y, z),
+            y + self.scale * self.turbulence(x + 31.4, y + 15.9, z + 26.5),
+            z + self.scale * self.turbulence(x - 35.8, y - 97.9, z - 32.3),
+        );
+        self.pattern.color_at_object(&jittered, 0.0)
+    }
+
+    fn pattern_transform(&self) -> &PatternTransform {
+        &self.transform
+    }
+
+    fn pattern_transform_mut(&mut self) -> &mut PatternTransform {
+        &mut self.transform
+    }
+
+    fn copy_pattern(&self) -> Box<dyn Pattern> {
+        Box::new(Self {
+            pattern: self.pattern.copy_pattern(),
+            seed: self.seed,
+            noise: self.noise.clone(),
+            scale: self.scale,
+            octaves: self.octaves,
+            transform: self.transform.clone(),
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod perturbed_tests {
+    use super::*;
+
+    fn marble(seed: u64) -> Perturbed {
+        let mut p = Perturbed::new(Box::new(Stripe::new(Color::white(), Color::black())), seed);
+        p.scale = 0.5;
+        p.octaves = 3;
+        p
+    }
+
+    #[test]
+    fn perturbing_a_pattern() {
+        let points: Vec<Tuple> = (0..100)
+            .map(|i| Tuple::point(i as f64 * 0.13, i as f64 * 0.29, i as f64 * -0.07))
+            .collect();
+        let colors = |p: &dyn Pattern| -> Vec<Color> {
+            points.iter().map(|point| p.color_at(point)).collect()
+        };
+        let stripes = Stripe::new(Color::white(), Color::black());
+
+        // the same seed always bends the stripes the same way
+        assert_eq!(colors(&marble(3)), colors(&marble(3)));
+        assert_eq!(
+            colors(&marble(3)),
+            colors(marble(3).copy_pattern().as_ref())
+        );
+        assert_ne!(colors(&marble(3)), colors(&stripes));
+
+        // with nothing to push the points the stripes are left alone
+        let mut still = marble(3);
+        still.scale = 0.0;
+        assert_eq!(colors(&still), colors(&stripes));
+
+        // the inner pattern keeps its own transform
+        let mut wide = Stripe::new(Color::white(), Color::black());
+        wide.set_transform(Matrix::scaling(100.0, 1.0, 1.0));
+        let mut p = Perturbed::new(Box::new(wide), 3);
+        p.set_transform(Matrix::translation(-150.0, 0.0, 0.0));
+        assert_eq!(
+            p.color_at_object(&Tuple::point(0.0, 0.0, 0.0), 0.0),
+            Color::black()
+        );
+    }
+}
+
+// --------
+
+// ---- Blend ----
+
+/*
+    Mixes two patterns together at every point, each keeps its own transform
+    so for example stripes running in two directions can be laid over each other
+*/
+pub struct Blend {
+    a: Box<dyn Pattern>,
+    b: Box<dyn Pattern>,
+    ratio: f64, // how much of b is in the mix, 0.5 for an even average
+    transform: PatternTransform,
+}
+
+impl Blend {
+    // ratios outside of 0 to 1 are clamped so the mix never goes negative
+    pub fn new(a: Box<dyn Pattern>, b: Box<dyn Pattern>, ratio: f64) -> Blend {
+        Blend {
+            a,
+            b,
+            ratio: ratio.clamp(0.0, 1.0),
+            transform: PatternTransform::identity(),
+        }
+    }
+
+    pub fn patterns(&self) -> (&dyn Pattern, &dyn Pattern) {
+        (self.a.as_ref(), self.b.as_ref())
+    }
+
+    pub fn ratio(&self) -> f64 {
+        self.ratio
+    }
+}
+
+impl Pattern for Blend {
+    fn color_at(&self, point: &Tuple) -> Color {
+        self.a.color_at_object(point, 0.0) * (1.0 - self.ratio)
+            + self.b.color_at_object(point, 0.0) * self.ratio
+    }
+
+    fn pattern_transform(&self) -> &PatternTransform {
+        &self.transform
+    }
+
+    fn pattern_transform_mut(&mut self) -> &mut PatternTransform {
+        &mut self.transform
+    }
+
+    fn copy_pattern(&self) -> Box<dyn Pattern> {
+        Box::new(Self {
+            a: self.a.copy_pattern(),
+            b: self.b.copy_pattern(),
+            ratio: self.ratio,
+            transform: self.transform.clone(),
+        })
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod blend_tests {
+    use super::*;
+
+    #[test]
+    fn blending_two_patterns() {
+        let stripes = || Box::new(Stripe::new(Color::white(), Color::black()));
+        // turned a quarter so its stripes run along x, white for z in (-1, 0]
+        let mut across = stripes();
+        across.set_transform(Matrix::rotation_y(std::f64::consts::FRAC_PI_2));
+        let mut plaid = Blend::new(stripes(), across, 0.5);
+
+        let grey = Color::new(0.5, 0.5, 0.5);
+        let cases = [
+            (Tuple::point(0.5, 0.0, -0.5), Color::white()),
+            (Tuple::point(1.5, 0.0, -0.5), grey),
+            (Tuple::point(0.5, 0.0, -1.5), grey),
+            (Tuple::point(1.5, 0.0, -1.5), Color::black()),
+        ];
+        for (point, color) in cases {
+            assert_eq!(plaid.color_at(&point), color);
+            assert_eq!(plaid.copy_pattern().color_at(&point), color);
+        }
+
+        // the blend moves as a whole
+        plaid.set_transform(Matrix::translation(1.0, 0.0, 0.0));
+        assert_eq!(
+            plaid.color_at_object(&Tuple::point(1.5, 0.0, -0.5), 0.0),
+            Color::white()
+        );
+
+        let lopsided = Blend::new(
+            Box::new(Solid::new(Color::white())),
+            Box::new(Solid::new(Color::black())),
+            0.25,
+        );
+        assert_eq!(
+            lopsided.color_at(&Tuple::point(0.0, 0.0, 0.0)),
+            Color::new(0.75, 0.75, 0.75)
+        );
+
+        let overdone = Blend::new(
+            Box::new(Solid::new(Color::white())),
+            Box::new(Solid::new(Color::black())),
+            1.5,
+        );
+        assert_eq!(overdone.ratio(), 1.0);
+        assert_eq!(
+            overdone.color_at(&Tuple::point(0.0, 0.0, 0.0)),
+            Color::black()
+        );
+    }
 }
 
 // --------
@@ -0,0 +1,328 @@
+use std::{any::Any, fs, path::Path};
+
+use super::{color::Color, patterns::UvTexture};
+
+/*
+    An image wrapped onto a shape through (u, v) coordinates, u runs left to
+    right across the image and v runs from the bottom row up to the top
+*/
+#[derive(Clone)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
+pub struct TextureMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,    // row major, starting from the top left
+    mips: Vec<TextureMap>, // successively halved copies, empty unless mipmapping is on
+}
+
+impl TextureMap {
+    /*
+        Load an image, PNG and JPEG files are decoded by the image crate
+        and anything else is read as a PPM
+    */
+    pub fn from_file(path: &str) -> Result<TextureMap, String> {
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("png") | Some("jpg") | Some("jpeg") => {
+                let image = image::open(path)
+                    .map_err(|e| format!("could not read {}: {}", path, e))?
+                    .to_rgb8();
+                let pixels = image
+                    .pixels()
+                    .map(|p| {
+                        Color::new(
+                            p[0] as f64 / 255.0,
+                            p[1] as f64 / 255.0,
+                            p[2] as f64 / 255.0,
+                        )
+                    })
+                    .collect();
+                Ok(TextureMap {
+                    width: image.width() as usize,
+                    height: image.height() as usize,
+                    pixels,
+                    mips: vec![],
+                })
+            }
+            _ => {
+                let bytes =
+                    fs::read(path).map_err(|e| format!("could not read {}: {}", path, e))?;
+                TextureMap::from_ppm(&bytes)
+            }
+        }
+    }
+
+    /*
+        Parse a plain (P3) or binary (P6) PPM image, comments are allowed anywhere
+        in the header and in the body of a plain image
+    */
+    pub fn from_ppm(bytes: &[u8]) -> Result<TextureMap, String> {
+        let mut reader = PpmReader { bytes, pos: 0 };
+
+        let magic = reader.token().ok_or("ppm is missing its magic number")?;
+        if magic != "P3" && magic != "P6" {
+            return Err(format!("unsupported ppm type {}", magic));
+        }
+        let width = reader.number()?;
+        let height = reader.number()?;
+        let max = reader.number()?;
+        if width == 0 || height == 0 || max == 0 || max > 255 {
+            return Err("ppm header is not valid".to_string());
+        }
+
+        let count = width * height * 3;
+        let values: Vec<usize> = if magic == "P3" {
+            (0..count)
+                .map(|_| reader.number())
+                .collect::<Result<_, _>>()?
+        } else {
+            // a single whitespace byte separates the header from the binary data
+            let start = reader.pos + 1;
+            let data = bytes
+                .get(start..start + count)
+                .ok_or("ppm has fewer pixels than its header claims")?;
+            data.iter().map(|&b| b as usize).collect()
+        };
+
+        let channel = |v: usize| v.min(max) as f64 / max as f64;
+        let pixels = values
+            .chunks(3)
+            .map(|c| Color::new(channel(c[0]), channel(c[1]), channel(c[2])))
+            .collect();
+        Ok(TextureMap {
+            width,
+            height,
+            pixels,
+            mips: vec![],
+        })
+    }
+
+    /*
+        Build a chain of box filtered copies, each half the size of the one before,
+        so wide rays far from the camera can sample a texel covering their footprint
+    */
+    pub fn with_mipmaps(mut self) -> TextureMap {
+        self.mips.clear();
+        let mut level = self.clone();
+        while level.width > 1 || level.height > 1 {
+            level = level.downsample();
+            self.mips.push(level.clone());
+        }
+        self
+    }
+
+    fn downsample(&self) -> TextureMap {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                // odd sizes repeat the last row or column
+                let x0 = (2 * x).min(self.width - 1);
+                let x1 = (2 * x + 1).min(self.width - 1);
+                let y0 = (2 * y).min(self.height - 1);
+                let y1 = (2 * y + 1).min(self.height - 1);
+                pixels.push(Color::average(&[
+                    self.pixel_at(x0, y0),
+                    self.pixel_at(x1, y0),
+                    self.pixel_at(x0, y1),
+                    self.pixel_at(x1, y1),
+                ]));
+            }
+        }
+        TextureMap {
+            width,
+            height,
+            pixels,
+            mips: vec![],
+        }
+    }
+
+    /*
+        Which copy to sample for a footprint of the given half width in uv space,
+        level zero is the full image and each level after that halves it
+    */
+    pub fn mip_level(&self, footprint: f64) -> usize {
+        let texels = 2.0 * footprint * self.width.max(self.height) as f64;
+        if texels.is_nan() || texels <= 1.0 {
+            return 0;
+        }
+        (texels.log2().floor() as usize).min(self.mips.len())
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixel_at(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+}
+
+impl UvTexture for TextureMap {
+    fn uv_color_at(&self, u: f64, v: f64) -> Color {
+        // flip v so the top of the image sits at the top of the shape
+        let v = 1.0 - v;
+        let x = (u.clamp(0.0, 1.0) * (self.width - 1) as f64).round() as usize;
+        let y = (v.clamp(0.0, 1.0) * (self.height - 1) as f64).round() as usize;
+        self.pixel_at(x, y)
+    }
+
+    fn copy_texture(&self) -> Box<dyn UvTexture> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn uv_color_filtered(&self, u: f64, v: f64, footprint: f64) -> Color {
+        match self.mip_level(footprint) {
+            0 => self.uv_color_at(u, v),
+            level => self.mips[level - 1].uv_color_at(u, v),
+        }
+    }
+}
+
+struct PpmReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl PpmReader<'_> {
+    fn token(&mut self) -> Option<String> {
+        // skip whitespace and comments running to the end of the line
+        while let Some(&b) = self.bytes.get(self.pos) {
+            if b == b'#' {
+                while self.bytes.get(self.pos).is_some_and(|&b| b != b'\n') {
+                    self.pos += 1;
+                }
+            } else if b.is_ascii_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+
+        let start = self.pos;
+        while self
+            .bytes
+            .get(self.pos)
+            .is_some_and(|b| !b.is_ascii_whitespace() && *b != b'#')
+        {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            None
+        } else {
+            Some(String::from_utf8_lossy(&self.bytes[start..self.pos]).into_owned())
+        }
+    }
+
+    fn number(&mut self) -> Result<usize, String> {
+        let token = self.token().ok_or("ppm ended early")?;
+        token
+            .parse()
+            .map_err(|_| format!("expected a number in ppm but found {}", token))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::draw::canvas::Canvas;
+
+    use super::*;
+
+    fn corners() -> [Color; 4] {
+        [
+            Color::new(1.0, 0.0, 0.0),
+            Color::new(0.0, 1.0, 0.0),
+            Color::new(0.0, 0.0, 1.0),
+            Color::new(1.0, 1.0, 51.0 / 255.0),
+        ]
+    }
+
+    #[test]
+    fn png_and_ppm_textures_match() {
+        let dir = std::env::temp_dir();
+        let png_path = dir.join(format!("ray_tracer_texture_{}.png", std::process::id()));
+        let ppm_path = dir.join(format!("ray_tracer_texture_{}.ppm", std::process::id()));
+        let png_path = png_path.to_str().unwrap();
+        let ppm_path = ppm_path.to_str().unwrap();
+
+        let colors = corners();
+        let mut canvas = Canvas::new(2, 2);
+        let mut png = image::RgbImage::new(2, 2);
+        for (i, c) in colors.iter().enumerate() {
+            let (x, y) = (i % 2, i / 2);
+            canvas.write_pixel(x, y, *c);
+            png.put_pixel(x as u32, y as u32, image::Rgb(c.quantize_dithered(0.0)));
+        }
+        canvas.write_to_ppm(ppm_path);
+        png.save(png_path).unwrap();
+
+        let from_png = TextureMap::from_file(png_path).unwrap();
+        let from_ppm = TextureMap::from_file(ppm_path).unwrap();
+        std::fs::remove_file(png_path).unwrap();
+        std::fs::remove_file(ppm_path).unwrap();
+
+        assert_eq!((from_png.width(), from_png.height()), (2, 2));
+        for (u, v) in [(0.0, 1.0), (1.0, 1.0), (0.0, 0.0), (1.0, 0.0), (0.3, 0.8)] {
+            assert_eq!(from_png.uv_color_at(u, v), from_ppm.uv_color_at(u, v));
+        }
+        // the top left of the image is the top left of the uv square
+        assert_eq!(from_ppm.uv_color_at(0.0, 1.0), colors[0]);
+        assert_eq!(from_ppm.uv_color_at(1.0, 0.0), colors[3]);
+    }
+
+    #[test]
+    fn reading_ppm_variants() {
+        let plain = b"P3\n# made by hand\n2 1\n15\n15 0 0 0 15 # trailing comment\n 0\n";
+        let t = TextureMap::from_ppm(plain).unwrap();
+        assert_eq!(t.pixel_at(0, 0), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(t.pixel_at(1, 0), Color::new(0.0, 1.0, 0.0));
+
+        let mut binary = b"P6 1 1 255\n".to_vec();
+        binary.extend([255, 51, 0]);
+        let t = TextureMap::from_ppm(&binary).unwrap();
+        assert_eq!(t.pixel_at(0, 0), Color::new(1.0, 0.2, 0.0));
+
+        assert!(TextureMap::from_ppm(b"P3 2 2 255 0 0 0").is_err());
+        assert!(TextureMap::from_ppm(b"P5 1 1 255 0").is_err());
+        assert!(TextureMap::from_file("does/not/exist.png").is_err());
+    }
+
+    #[test]
+    fn choosing_a_mip_level() {
+        let mut ppm = b"P3 8 4 1".to_vec();
+        for i in 0..32 {
+            ppm.extend(if (i % 8 + i / 8) % 2 == 0 {
+                b" 0 0 0"
+            } else {
+                b" 1 1 1"
+            });
+        }
+        let plain = TextureMap::from_ppm(&ppm).unwrap();
+        let t = plain.clone().with_mipmaps();
+
+        // 8x4, 4x2, 2x1 then 1x1
+        assert_eq!(t.mips.len(), 3);
+        assert_eq!((t.mips[2].width(), t.mips[2].height()), (1, 1));
+        assert_eq!(t.mips[0].pixel_at(0, 0), Color::new(0.5, 0.5, 0.5));
+
+        assert_eq!(t.mip_level(0.0), 0);
+        assert_eq!(t.mip_level(1.0 / 16.0), 0);
+        assert_eq!(t.mip_level(1.0 / 8.0), 1);
+        assert_eq!(t.mip_level(0.3), 2);
+        assert_eq!(t.mip_level(100.0), 3);
+        assert_eq!(plain.mip_level(100.0), 0);
+    }
+}
@@ -1,4 +1,9 @@
-use std::{f64::consts::PI, fs};
+use std::{
+    env,
+    f64::consts::PI,
+    fs,
+    path::{Path, PathBuf},
+};
 
 use crate::{
     draw::{
@@ -10,17 +15,38 @@ use crate::{
     obj_parser::parse_obj_file,
     scene::{
         camera::{view_transform, Camera},
-        light::PointLight,
+        light::{AreaLight, DirectionalLight, PointLight, SpotLight},
         world::World,
     },
-    shapes::{cone::Cone, cube::Cube, cylinder::Cylinder, plane::Plane, sphere::Sphere},
+    shapes::{
+        cone::Cone, cube::Cube, cylinder::Cylinder, group::Group, intersect::Intersectable,
+        plane::Plane, sphere::Sphere,
+    },
 };
 
-pub fn tea_set() -> (Camera, World) {
+// set to a directory holding the obj files to load them from somewhere other than this repo
+pub const ASSETS_DIR_VAR: &str = "RAY_TRACER_ASSETS";
+
+/*
+    Where a bundled model lives, the obj folder of this repo unless the assets
+    directory is overridden, so examples work from any working directory
+*/
+pub fn asset_path(name: &str) -> PathBuf {
+    let dir = match env::var_os(ASSETS_DIR_VAR) {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("obj"),
+    };
+    dir.join(name)
+}
+
+fn read_obj(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("could not read {}: {}", path.display(), e))
+}
+
+pub fn tea_set() -> Result<(Camera, World), String> {
     let mut world = World::new();
 
-    let obj =
-        fs::read_to_string("./obj/teapot.obj").expect("Something went wrong reading the file");
+    let obj = read_obj(&asset_path("teapot.obj"))?;
 
     let mut tea_mat = Material::default_material();
     tea_mat.pattern = Box::new(Solid::new(Color::new(0.68359375, 0.55859375, 0.91015625)));
@@ -32,8 +58,7 @@ pub fn tea_set() -> (Camera, World) {
         Some(Material::from_material(&tea_mat)),
     );
 
-    let obj = fs::read_to_string("./obj/teacup.obj")
-        .expect("Something went wrong reading the file");
+    let obj = read_obj(&asset_path("teacup.obj"))?;
 
     let tea_cup_right = parse_obj_file(
         &obj,
@@ -45,9 +70,6 @@ pub fn tea_set() -> (Camera, World) {
         Some(Material::from_material(&tea_mat)),
     );
 
-    let obj = fs::read_to_string("./obj/teacup.obj")
-        .expect("Something went wrong reading the file");
-
     let tea_cup_left = parse_obj_file(
         &obj,
         Some(
@@ -81,19 +103,18 @@ pub fn tea_set() -> (Camera, World) {
         ),
     );
 
-    world.light_sources = vec![PointLight::new(
+    world.light_sources = vec![Box::new(PointLight::new(
         Color::new(1.0, 1.0, 1.0),
         Tuple::point(-7.0, 11.0, -10.),
-    )];
+    ))];
 
-    (camera, world)
+    Ok((camera, world))
 }
 
-pub fn pawn_chess() -> (Camera, World) {
+pub fn pawn_chess() -> Result<(Camera, World), String> {
     let mut world = World::new();
 
-    let obj =
-        fs::read_to_string("./obj/pawn-chess.obj").expect("Something went wrong reading the file");
+    let obj = read_obj(&asset_path("pawn-chess.obj"))?;
 
     let mut pawn_mat = Material::default_material();
     pawn_mat.specular = 1.;
@@ -112,10 +133,10 @@ pub fn pawn_chess() -> (Camera, World) {
 
     world.objects = vec![Box::new(g), Box::new(plane)];
 
-    world.light_sources = vec![PointLight::new(
+    world.light_sources = vec![Box::new(PointLight::new(
         Color::new(1.0, 1.0, 1.0),
         Tuple::point(-10.0, 13.0, -10.),
-    )];
+    ))];
 
     let camera = Camera::new_with_transform(
         1000,
@@ -128,16 +149,49 @@ pub fn pawn_chess() -> (Camera, World) {
         ),
     );
 
-    (camera, world)
+    Ok((camera, world))
+}
+
+/*
+    Show any obj file on its own, the camera and light are placed around
+    the bounds of the model so nothing has to be tuned by hand
+*/
+pub fn obj_model(path: &str) -> Result<(Camera, World), String> {
+    let mut world = World::new();
+
+    let obj = read_obj(Path::new(path))?;
+    world.objects = vec![Box::new(parse_obj_file(&obj, None, None))];
+
+    let bounds = world.bounds();
+    if bounds.is_empty() {
+        return Err(format!("{}: model has no geometry", path));
+    }
+    let camera = Camera::auto_frame(bounds, 500, 500, PI / 3.0)?;
+
+    // over the shoulder of the camera so the visible side of the model is lit
+    let reach = (bounds.max - bounds.min).magnitude();
+    world.light_sources = vec![Box::new(PointLight::new(
+        Color::white(),
+        camera.eye() + Tuple::vector(-reach, reach, 0.0),
+    ))];
+
+    Ok((camera, world))
 }
 
 pub fn book_cover() -> (Camera, World) {
     let mut world = World::new();
 
     world.light_sources = vec![
-        PointLight::new(Color::new(1.0, 1.0, 1.0), Tuple::point(50.0, 100.0, -50.0)),
-        PointLight::new(Color::new(0.2, 0.2, 0.2), Tuple::point(-400.0, 50.0, -10.0)),
+        Box::new(PointLight::new(
+            Color::new(1.0, 1.0, 1.0),
+            Tuple::point(50.0, 100.0, -50.0),
+        )),
+        Box::new(PointLight::new(
+            Color::new(0.2, 0.2, 0.2),
+            Tuple::point(-400.0, 50.0, -10.0),
+        )),
     ];
+    world.clamp_lighting = true;
 
     let mut white_material = Material::default_material();
     white_material.pattern = Box::new(Solid::new(Color::white()));
@@ -384,10 +438,10 @@ pub fn test_scene() -> (Camera, World) {
         Box::new(ceil),
     ];
 
-    world.light_sources = vec![PointLight::new(
+    world.light_sources = vec![Box::new(PointLight::new(
         Color::new(1.0, 1.0, 1.0),
         Tuple::point(-10.0, 13.0, -10.),
-    )];
+    ))];
 
     let camera = Camera::new_with_transform(
         1920,
@@ -402,3 +456,272 @@ pub fn test_scene() -> (Camera, World) {
 
     (camera, world)
 }
+
+/*
+    A closed box of planes with its floor at y = 0, centered on the y axis.
+    The walls are given in the order floor, ceiling, -x, +x, -z, +z
+    and each one faces into the room
+*/
+pub fn room(size: f64, walls: [Material; 6]) -> Group {
+    let half = size / 2.0;
+    let placements = [
+        Matrix::identity(4),
+        &Matrix::translation(0.0, size, 0.0) * &Matrix::rotation_x(PI),
+        &Matrix::translation(-half, half, 0.0) * &Matrix::rotation_z(-PI / 2.0),
+        &Matrix::translation(half, half, 0.0) * &Matrix::rotation_z(PI / 2.0),
+        &Matrix::translation(0.0, half, -half) * &Matrix::rotation_x(PI / 2.0),
+        &Matrix::translation(0.0, half, half) * &Matrix::rotation_x(-PI / 2.0),
+    ];
+
+    let mut room = Group::new(None, None);
+    for (transform, material) in placements.into_iter().zip(walls) {
+        let mut plane = Plane::new(Some(transform));
+        plane.material = material;
+        room.add_object_keep_material(Box::new(plane));
+    }
+    room
+}
+
+/*
+    A single shape at the origin seen from 5 units down -z, lit by a white light
+    sitting at the camera so a surface facing the camera is fully lit and unshadowed
+*/
+pub fn single_shape_scene(shape: Box<dyn Intersectable>) -> (Camera, World) {
+    let mut world = World::new();
+    world.objects.push(shape);
+    world.light_sources.push(Box::new(PointLight::new(
+        Color::white(),
+        Tuple::point(0.0, 0.0, -5.0),
+    )));
+
+    let camera = Camera::new_with_transform(
+        11,
+        11,
+        PI / 3.0,
+        view_transform(
+            Tuple::point(0.0, 0.0, -5.0),
+            Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0),
+        ),
+    );
+
+    (camera, world)
+}
+
+pub fn light_test_scene() -> (Camera, World) {
+    let mut world = World::new();
+
+    let floor = Material::builder()
+        .color(Color::white())
+        .specular(0.0)
+        .build();
+    let wall = || Material::matte(Color::new(0.6, 0.6, 0.6));
+    let mut room = room(30.0, [floor, wall(), wall(), wall(), wall(), wall()]);
+    // take the ceiling off so the sun can reach the floor
+    let ceiling = room.get_object(1).unwrap().get_id();
+    room.remove_object(ceiling);
+    world.objects.push(Box::new(room));
+
+    // a row of spheres so the falloff of each light can be compared against the others
+    for x in [-3.0, 0.0, 3.0] {
+        let mut sphere = Sphere::new(Some(Matrix::translation(x, 1.0, 0.0)));
+        sphere.material.pattern = Box::new(Solid::new(Color::white()));
+        sphere.material.diffuse = 0.7;
+        sphere.material.specular = 0.3;
+        world.objects.push(Box::new(sphere));
+    }
+
+    // a different kind of light above each sphere, tinted so their contributions
+    // can be told apart on the floor, and a dim sun straight overhead
+    world.light_sources = vec![
+        Box::new(PointLight::new(
+            Color::new(1.0, 0.5, 0.5),
+            Tuple::point(-3.0, 5.0, -2.0),
+        )),
+        Box::new(SpotLight::new(
+            Color::new(0.5, 1.0, 0.5),
+            Tuple::point(0.0, 5.0, -2.0),
+            Tuple::vector(0.0, -1.0, 0.0),
+            PI / 12.0,
+            PI / 6.0,
+        )),
+        Box::new(AreaLight::new(
+            Color::new(0.5, 0.5, 1.0),
+            Tuple::point(2.0, 5.0, -3.0),
+            Tuple::vector(2.0, 0.0, 0.0),
+            4,
+            Tuple::vector(0.0, 0.0, 2.0),
+            4,
+        )),
+        Box::new(DirectionalLight::new(
+            Color::new(0.3, 0.3, 0.3),
+            Tuple::vector(0.0, -1.0, 0.0),
+        )),
+    ];
+
+    let camera = Camera::new_with_transform(
+        1000,
+        500,
+        PI / 3.0,
+        view_transform(
+            Tuple::point(0.0, 6.0, -10.0),
+            Tuple::point(0.0, 0.5, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0),
+        ),
+    );
+
+    (camera, world)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        math::{ray::Ray, utils::f64_eq},
+        scene::camera::render,
+        shapes::{intersect::hit, triangle::Triangle},
+    };
+
+    use super::*;
+
+    #[test]
+    fn light_test_scene_builds() {
+        let (_, mut world) = light_test_scene();
+        assert_eq!(world.light_sources.len(), 4);
+        assert_eq!(world.objects.len(), 4);
+
+        // the floor under the point, spot and area lights and a spot away from the spheres
+        let floor = [(-3.0, -2.0), (0.0, -2.0), (3.0, -2.0), (0.0, -6.0)];
+        let mut shade_with = |only: Option<usize>, (x, z): (f64, f64)| {
+            for (i, light) in world.light_sources.iter_mut().enumerate() {
+                light.set_enabled(only == Some(i));
+            }
+            let down = Ray::new(Tuple::point(x, 0.5, z), Tuple::vector(0.0, -1.0, 0.0));
+            world.color_at(&down, 5)
+        };
+
+        for (i, &point) in floor.iter().enumerate() {
+            let unlit = shade_with(None, point);
+            let lit = shade_with(Some(i), point);
+            assert!(lit.luminance() > unlit.luminance() + 0.1, "light {}", i);
+        }
+        // the spot light only reaches the floor inside its cone
+        assert_eq!(shade_with(Some(1), floor[0]), shade_with(None, floor[0]));
+    }
+
+    #[test]
+    fn finding_bundled_assets() {
+        // the manifest directory is absolute so the working directory does not matter
+        let path = asset_path("teapot.obj");
+        assert!(path.is_absolute());
+        assert!(read_obj(&path).is_ok());
+
+        let missing = read_obj(&asset_path("missing.obj")).unwrap_err();
+        assert!(missing.contains("missing.obj"));
+        assert!(obj_model("does/not/exist.obj").is_err());
+
+        // a file without any faces is an error rather than a panic
+        let empty =
+            std::env::temp_dir().join(format!("ray_tracer_empty_{}.obj", std::process::id()));
+        fs::write(&empty, "v 0 0 0\nv 1 0 0\n").unwrap();
+        let err = obj_model(empty.to_str().unwrap()).err().unwrap();
+        fs::remove_file(&empty).unwrap();
+        assert!(err.contains("no geometry"), "{}", err);
+    }
+
+    #[test]
+    fn tea_set_builds() {
+        let (_, world) = tea_set().unwrap();
+        let count = |is: fn(&dyn Intersectable) -> bool| {
+            world.objects.iter().filter(|o| is(o.as_ref())).count()
+        };
+        assert_eq!(count(|o| o.as_any().is::<Group>()), 3);
+        assert_eq!(count(|o| o.as_any().is::<Plane>()), 1);
+        assert!(!world.light_sources.is_empty());
+    }
+
+    #[test]
+    fn room_encloses_a_point() {
+        let walls = [0.1, 0.2, 0.3, 0.4, 0.5, 0.6].map(|v| Material::matte(Color::new(v, v, v)));
+        let room = room(10.0, walls);
+        assert_eq!(room.len(), 6);
+
+        let center = Tuple::point(1.0, 4.0, -2.0);
+        let directions = [
+            (Tuple::vector(0.0, -1.0, 0.0), 4.0, 0.1),
+            (Tuple::vector(0.0, 1.0, 0.0), 6.0, 0.2),
+            (Tuple::vector(-1.0, 0.0, 0.0), 6.0, 0.3),
+            (Tuple::vector(1.0, 0.0, 0.0), 4.0, 0.4),
+            (Tuple::vector(0.0, 0.0, -1.0), 3.0, 0.5),
+            (Tuple::vector(0.0, 0.0, 1.0), 7.0, 0.6),
+        ];
+
+        for (direction, distance, shade) in directions {
+            let ray = Ray::new(center, direction);
+            let xs = room.intersect(&ray);
+            let hit = hit(&xs).unwrap();
+            assert!(f64_eq(hit.t, distance));
+            assert_eq!(
+                hit.shape
+                    .get_material()
+                    .pattern
+                    .color_at(&Tuple::point(0.0, 0.0, 0.0)),
+                Color::new(shade, shade, shade)
+            );
+
+            // every wall faces into the room
+            let normal = hit.shape.normal_at(ray.position(hit.t), hit, None);
+            assert_eq!(normal, -direction);
+        }
+    }
+
+    fn surface() -> Color {
+        Color::new(0.8, 0.4, 0.2)
+    }
+
+    fn center_pixel(mut shape: Box<dyn Intersectable>) -> Color {
+        shape.set_material(Material::matte(surface()));
+        let (camera, world) = single_shape_scene(shape);
+        render(camera, world, 1).get_pixel(5, 5)
+    }
+
+    #[test]
+    fn every_shape_is_lit() {
+        // ambient plus diffuse adds up to exactly the surface color when the surface faces the light
+        let facing = surface();
+
+        // children take the material of the group when they are added
+        let mut group = Group::new(
+            Some(Matrix::scaling(2.0, 2.0, 2.0)),
+            Some(Material::matte(surface())),
+        );
+        group.add_object(Box::new(Sphere::new(None)));
+
+        let mut cylinder = Cylinder::new(None);
+        cylinder.minimum = -1.0;
+        cylinder.maximum = 1.0;
+
+        let facing_shapes: Vec<Box<dyn Intersectable>> = vec![
+            Box::new(Sphere::new(None)),
+            Box::new(Plane::new(Some(Matrix::rotation_x(-PI / 2.0)))),
+            Box::new(Cube::new(None)),
+            Box::new(cylinder),
+            Box::new(Triangle::new(
+                Tuple::point(0.0, 1.0, 0.0),
+                Tuple::point(-1.0, 0.0, 0.0),
+                Tuple::point(1.0, 0.0, 0.0),
+                Some(Matrix::translation(0.0, -0.5, 0.0)),
+            )),
+            Box::new(group),
+        ];
+        for shape in facing_shapes {
+            assert_eq!(center_pixel(shape), facing);
+        }
+
+        // the side of a cone leans back at 45 degrees so it only catches cos(45) of the diffuse light
+        let mut cone = Cone::new(Some(Matrix::translation(0.0, 0.5, 0.0)));
+        cone.minimum = -1.0;
+        cone.maximum = 0.0;
+        let tilted = 0.1 + 0.9 * (PI / 4.0).cos();
+        assert_eq!(center_pixel(Box::new(cone)), facing * tilted);
+    }
+}
@@ -1,7 +1,9 @@
 #![allow(dead_code, non_snake_case)]
 
+use std::{env, io, path::Path};
+
 use clap::{App, Arg};
-use scene::camera::render;
+use scene::camera::{render, render_with_metadata};
 
 mod draw;
 mod examples;
@@ -24,15 +26,73 @@ fn main() {
                 .default_value("6")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("samples")
+                .short("s")
+                .long("samples")
+                .value_name("N")
+                .help("Anti-alias by tracing an N by N grid of rays through every pixel")
+                .default_value("1")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("examples")
                 .short("e")
                 .long("example")
                 .value_name("EXAMPLE")
                 .help("The scene to render")
-                .possible_values(&["pawn", "cover", "tea set"])
+                .possible_values(&["pawn", "cover", "tea set", "lights"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("obj")
+                .long("obj")
+                .value_name("FILE")
+                .help("Render an obj model with the camera framed around it")
+                .conflicts_with("examples")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("assets")
+                .long("assets-dir")
+                .value_name("DIR")
+                .help("Where the examples load their obj models from")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("dither")
+                .short("d")
+                .long("dither")
+                .help("Apply ordered dithering to reduce banding in smooth gradients"),
+        )
+        .arg(
+            Arg::with_name("tone map")
+                .long("tone-map")
+                .help("Compress bright highlights so they fade to white instead of clipping"),
+        )
+        .arg(
+            Arg::with_name("gamma")
+                .short("g")
+                .long("gamma")
+                .value_name("GAMMA")
+                .help("Gamma correct the image for display, 2.2 suits most screens")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("FILE")
+                .help("Where to write the image, a .png file is written as a PNG and anything else as a PPM, use - to write a PPM to stdout")
+                .default_value("canvas.ppm")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("metadata")
+                .short("m")
+                .long("metadata")
+                .help("Write the camera and render stats to a json file next to the image"),
+        )
         .get_matches();
 
     let threads = match matches.value_of("threads").unwrap().parse::<usize>() {
@@ -43,13 +103,74 @@ fn main() {
         }
     };
 
-    let scene = match matches.value_of("examples").unwrap_or("cover") {
-        "cover" => examples::book_cover(),
-        "pawn" => examples::pawn_chess(),
-        "tea set" => examples::tea_set(),
-        _ => panic!("Unrecognized scene"),
+    let samples = match matches.value_of("samples").unwrap().parse::<usize>() {
+        Ok(s) if s > 0 => s,
+        _ => {
+            println!("Invalid number of samples");
+            return;
+        }
+    };
+
+    let gamma = match matches.value_of("gamma").map(|g| g.parse::<f64>()) {
+        None => 1.0,
+        Some(Ok(g)) if g > 0.0 => g,
+        _ => {
+            println!("Invalid gamma");
+            return;
+        }
+    };
+
+    if let Some(dir) = matches.value_of("assets") {
+        env::set_var(examples::ASSETS_DIR_VAR, dir);
+    }
+
+    let scene = match matches.value_of("obj") {
+        Some(path) => examples::obj_model(path),
+        None => match matches.value_of("examples").unwrap_or("cover") {
+            "cover" => Ok(examples::book_cover()),
+            "pawn" => examples::pawn_chess(),
+            "tea set" => examples::tea_set(),
+            "lights" => Ok(examples::light_test_scene()),
+            _ => panic!("Unrecognized scene"),
+        },
     };
+    let (mut camera, world) = match scene {
+        Ok(scene) => scene,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    camera.samples = samples;
+
+    let output = matches.value_of("output").unwrap();
+    let mut image = if matches.is_present("metadata") {
+        let metadata_file = if output == "-" {
+            "canvas.json".to_string()
+        } else {
+            Path::new(output)
+                .with_extension("json")
+                .to_string_lossy()
+                .into_owned()
+        };
+        render_with_metadata(camera, world, threads, &metadata_file)
+    } else {
+        render(camera, world, threads)
+    };
+    image.dither = matches.is_present("dither");
+    image.gamma = gamma;
+    image.tone_map = matches.is_present("tone map");
 
-    let image = render(scene.0, scene.1, threads);
-    image.write_to_ppm("canvas.ppm");
+    if output == "-" {
+        if let Err(e) = image.write_ppm_to(&mut io::stdout().lock()) {
+            eprintln!("Could not write image to stdout: {}", e);
+        }
+    } else if Path::new(output)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("png"))
+    {
+        image.write_to_png(output);
+    } else {
+        image.write_to_ppm(output);
+    }
 }
@@ -9,6 +9,7 @@ use super::utils::f64_eq;
 */
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
 pub struct Matrix {
     pub size: usize,
     pub matrix: Vec<Vec<f64>>,
@@ -52,6 +53,19 @@ impl ops::Mul<&Matrix> for &Matrix {
     }
 }
 
+impl ops::Mul<Matrix> for Matrix {
+    type Output = Matrix;
+    fn mul(self, rhs: Matrix) -> Matrix {
+        &self * &rhs
+    }
+}
+
+impl ops::MulAssign<&Matrix> for Matrix {
+    fn mul_assign(&mut self, rhs: &Matrix) {
+        *self = &*self * rhs;
+    }
+}
+
 impl ops::Mul<&Tuple> for &Matrix {
     type Output = Tuple;
     fn mul(self, rhs: &Tuple) -> Tuple {
@@ -104,6 +118,30 @@ impl Matrix {
         m
     }
 
+    /*
+        A 4x4 transform made only of scaling and translation, boxes stay axis
+        aligned when put through one of these
+    */
+    pub fn is_axis_aligned(&self) -> bool {
+        self.size == 4
+            && (0..4).all(|i| {
+                (0..3).all(|j| i == j || self.matrix[i][j] == 0.0)
+                    && (i != 3 || self.matrix[3][3] == 1.0)
+            })
+    }
+
+    // how much the transform lengthens a vector along the axis it stretches the most
+    pub fn max_stretch(&self) -> f64 {
+        (0..3)
+            .map(|j| {
+                (0..3)
+                    .map(|i| self.matrix[i][j].powi(2))
+                    .sum::<f64>()
+                    .sqrt()
+            })
+            .fold(0.0, f64::max)
+    }
+
     pub fn transpose(&mut self) {
         for n in 0..self.size - 1 {
             for m in n + 1..self.size {
@@ -154,12 +192,17 @@ impl Matrix {
     pub fn inverse(&self) -> Matrix {
         let mut inverse = self.clone();
 
-        let det = self.determinant();
+        // every cofactor is found once, the first row of them also gives the determinant
+        let cofactors: Vec<Vec<f64>> = (0..self.size)
+            .map(|n| (0..self.size).map(|m| self.cofactor(n, m)).collect())
+            .collect();
+        let det: f64 = (0..self.size)
+            .map(|col| self.matrix[0][col] * cofactors[0][col])
+            .sum();
         assert_ne!(det, 0.0); // is matrix invertible
 
-        for n in 0..self.size {
-            for m in 0..self.size {
-                let c = self.cofactor(n, m);
+        for (n, row) in cofactors.iter().enumerate() {
+            for (m, c) in row.iter().enumerate() {
                 inverse.matrix[m][n] = c / det;
             }
         }
@@ -167,6 +210,17 @@ impl Matrix {
         inverse
     }
 
+    /*
+        The inverse and the transpose of the inverse, shapes keep both to move rays
+        into object space and normals back out of it
+    */
+    pub fn inverse_and_transpose(&self) -> (Matrix, Matrix) {
+        let inverse = self.inverse();
+        let mut transpose = inverse.clone();
+        transpose.transpose();
+        (inverse, transpose)
+    }
+
     pub fn translation(x: f64, y: f64, z: f64) -> Matrix {
         let mut m = Matrix::identity(4);
         m.matrix[0][3] = x;
@@ -226,6 +280,32 @@ impl Matrix {
         m
     }
 
+    /*
+        Rotate about an arbitrary axis through the origin using Rodrigues' formula,
+        the axis does not need to be normalized
+    */
+    pub fn rotation(axis: Tuple, radians: f64) -> Matrix {
+        assert!(axis.is_vector());
+        let k = axis.normalize();
+        let mut m = Matrix::identity(4);
+
+        let cos = radians.cos();
+        let sin = radians.sin();
+        let t = 1.0 - cos;
+
+        m.matrix[0][0] = cos + t * k.x * k.x;
+        m.matrix[0][1] = t * k.x * k.y - sin * k.z;
+        m.matrix[0][2] = t * k.x * k.z + sin * k.y;
+        m.matrix[1][0] = t * k.y * k.x + sin * k.z;
+        m.matrix[1][1] = cos + t * k.y * k.y;
+        m.matrix[1][2] = t * k.y * k.z - sin * k.x;
+        m.matrix[2][0] = t * k.z * k.x - sin * k.y;
+        m.matrix[2][1] = t * k.z * k.y + sin * k.x;
+        m.matrix[2][2] = cos + t * k.z * k.z;
+
+        m
+    }
+
     pub fn shear(x_y: f64, x_z: f64, y_x: f64, y_z: f64, z_x: f64, z_y: f64) -> Matrix {
         let mut m = Matrix::identity(4);
 
@@ -596,6 +676,32 @@ mod test {
         assert!(&full_quarter * &p == Tuple::point(-1.0, 0.0, 0.0));
     }
 
+    #[test]
+    fn arbitrary_axis_rotation() {
+        assert_eq!(
+            Matrix::rotation(Tuple::vector(1.0, 0.0, 0.0), PI / 2.0),
+            Matrix::rotation_x(PI / 2.0)
+        );
+        assert_eq!(
+            Matrix::rotation(Tuple::vector(0.0, 3.0, 0.0), PI / 3.0),
+            Matrix::rotation_y(PI / 3.0)
+        );
+        assert_eq!(
+            Matrix::rotation(Tuple::vector(0.0, 0.0, 1.0), -PI / 5.0),
+            Matrix::rotation_z(-PI / 5.0)
+        );
+
+        // a third of a turn about the diagonal cycles the axes
+        let diagonal = Matrix::rotation(Tuple::vector(1.0, 1.0, 1.0), 2.0 * PI / 3.0);
+        assert!(&diagonal * &Tuple::point(1.0, 0.0, 0.0) == Tuple::point(0.0, 1.0, 0.0));
+        assert!(&diagonal * &Tuple::point(0.0, 1.0, 0.0) == Tuple::point(0.0, 0.0, 1.0));
+        assert!(&diagonal * &Tuple::point(2.0, 3.0, 4.0) == Tuple::point(4.0, 2.0, 3.0));
+
+        // points on the axis do not move
+        let p = Tuple::point(2.0, 2.0, 2.0);
+        assert!(&diagonal * &p == p);
+    }
+
     #[test]
     fn shear() {
         let p = Tuple::point(2.0, 3.0, 4.0);
@@ -628,4 +734,20 @@ mod test {
         let T = &(&C * &B) * &A;
         assert!(&T * &p == Tuple::point(15.0, 0.0, 7.0));
     }
+
+    #[test]
+    fn chained_transformations_owned() {
+        let A = Matrix::rotation_x(PI / 2.0);
+        let B = Matrix::scaling(5.0, 5.0, 5.0);
+        let C = Matrix::translation(10.0, 5.0, 7.0);
+        let by_ref = &(&C * &B) * &A;
+
+        let owned = C.clone() * B.clone() * A.clone();
+        assert_eq!(owned, by_ref);
+
+        let mut assigned = C;
+        assigned *= &B;
+        assigned *= &A;
+        assert_eq!(assigned, by_ref);
+    }
 }
@@ -1,4 +1,8 @@
 pub mod matrix;
+pub mod noise;
+pub mod polynomial;
+pub mod quaternion;
 pub mod ray;
+pub mod rng;
 pub mod tuples;
 pub mod utils;
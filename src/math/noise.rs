@@ -0,0 +1,115 @@
+use super::rng::Rng;
+
+/*
+    Ken Perlin's improved gradient noise, the lattice is shuffled from a seed
+    so the same seed always gives back the same field
+*/
+#[derive(Clone, Debug)]
+pub struct PerlinNoise {
+    permutation: Vec<usize>, // 0 to 255 shuffled, repeated twice to save wrapping indices
+}
+
+impl PerlinNoise {
+    pub fn new(seed: u64) -> PerlinNoise {
+        let mut rng = Rng::new(seed);
+        let mut p: Vec<usize> = (0..256).collect();
+        for i in (1..p.len()).rev() {
+            let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+            p.swap(i, j);
+        }
+        PerlinNoise {
+            permutation: p.iter().chain(p.iter()).copied().collect(),
+        }
+    }
+
+    // smoothly varying noise roughly in [-1, 1], zero at every lattice point
+    pub fn noise(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (fx, fy, fz) = (x.floor(), y.floor(), z.floor());
+        let (x, y, z) = (x - fx, y - fy, z - fz);
+        let cell = |v: f64| v.rem_euclid(256.0) as usize;
+        let (xi, yi, zi) = (cell(fx), cell(fy), cell(fz));
+
+        // hash each corner of the cell surrounding the point
+        let p = &self.permutation;
+        let a = p[xi] + yi;
+        let (aa, ab) = (p[a] + zi, p[a + 1] + zi);
+        let b = p[xi + 1] + yi;
+        let (ba, bb) = (p[b] + zi, p[b + 1] + zi);
+
+        let (u, v, w) = (fade(x), fade(y), fade(z));
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, grad(p[aa], x, y, z), grad(p[ba], x - 1.0, y, z)),
+                lerp(
+                    u,
+                    grad(p[ab], x, y - 1.0, z),
+                    grad(p[bb], x - 1.0, y - 1.0, z),
+                ),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(p[aa + 1], x, y, z - 1.0),
+                    grad(p[ba + 1], x - 1.0, y, z - 1.0),
+                ),
+                lerp(
+                    u,
+                    grad(p[ab + 1], x, y - 1.0, z - 1.0),
+                    grad(p[bb + 1], x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+    }
+}
+
+// 6t^5 - 15t^4 + 10t^3, flat at both ends so neighbouring cells join smoothly
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f64, a: f64, b: f64) -> f64 {
+    a + t * (b - a)
+}
+
+// dot product of the offset with one of twelve edge directions picked by the hash
+fn grad(hash: usize, x: f64, y: f64, z: f64) -> f64 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn seeded_perlin_noise() {
+        let a = PerlinNoise::new(7);
+        let b = PerlinNoise::new(7);
+        let c = PerlinNoise::new(8);
+
+        assert_eq!(a.noise(3.0, -2.0, 17.0), 0.0);
+        let mut differs = false;
+        for i in 0..200 {
+            let (x, y, z) = (i as f64 * 0.37, i as f64 * -0.11, i as f64 * 0.73);
+            let n = a.noise(x, y, z);
+            assert!(n.abs() <= 1.5, "{}", n);
+            assert_eq!(n, b.noise(x, y, z));
+            differs |= n != c.noise(x, y, z);
+
+            // no jumps between nearby points
+            assert!((n - a.noise(x + 1e-4, y, z)).abs() < 1e-2);
+        }
+        assert!(differs);
+    }
+}
@@ -0,0 +1,135 @@
+/*
+    The real roots of a polynomial in ascending order, coefficients are given
+    from the highest power down. Roots are bracketed between the turning points
+    of the polynomial, found from the roots of its derivative, then bisected
+*/
+pub fn real_roots(coefficients: &[f64]) -> Vec<f64> {
+    // leading zeros do not change the roots, they only lower the degree
+    let start = coefficients
+        .iter()
+        .position(|&c| c != 0.0)
+        .unwrap_or(coefficients.len());
+    let coefficients = &coefficients[start..];
+
+    match coefficients.len() {
+        0 | 1 => vec![],
+        2 => vec![-coefficients[1] / coefficients[0]],
+        3 => quadratic_roots(coefficients[0], coefficients[1], coefficients[2]),
+        _ => {
+            let monic: Vec<f64> = coefficients.iter().map(|c| c / coefficients[0]).collect();
+            bracketed_roots(&monic)
+        }
+    }
+}
+
+fn quadratic_roots(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let disc = b * b - 4.0 * a * c;
+    if disc < 0.0 {
+        return vec![];
+    }
+    // avoid subtracting two nearly equal numbers when b is much larger than the rest
+    let q = -0.5 * (b + b.signum() * disc.sqrt());
+    if q == 0.0 {
+        return vec![0.0, 0.0];
+    }
+    let (r0, r1) = (q / a, c / q);
+    if r0 < r1 {
+        vec![r0, r1]
+    } else {
+        vec![r1, r0]
+    }
+}
+
+fn bracketed_roots(monic: &[f64]) -> Vec<f64> {
+    let degree = monic.len() - 1;
+    let derivative: Vec<f64> = monic[..degree]
+        .iter()
+        .enumerate()
+        .map(|(i, c)| c * (degree - i) as f64)
+        .collect();
+
+    // every root lies within this distance of zero
+    let bound = 1.0 + monic[1..].iter().fold(0.0, |m: f64, c| m.max(c.abs()));
+    let mut edges = vec![-bound];
+    edges.extend(
+        real_roots(&derivative)
+            .into_iter()
+            .filter(|r| r.abs() < bound),
+    );
+    edges.push(bound);
+
+    // the polynomial only rises or falls between neighbouring edges
+    let mut roots: Vec<f64> = vec![];
+    for pair in edges.windows(2) {
+        let (lo, hi) = (pair[0], pair[1]);
+        let (f_lo, f_hi) = (evaluate(monic, lo), evaluate(monic, hi));
+        let root = if f_lo == 0.0 {
+            lo
+        } else if f_lo.signum() == f_hi.signum() {
+            continue;
+        } else {
+            bisect(monic, lo, hi, f_lo)
+        };
+        if roots.last().is_none_or(|&last| last != root) {
+            roots.push(root);
+        }
+    }
+    if evaluate(monic, bound) == 0.0 {
+        roots.push(bound);
+    }
+    roots
+}
+
+fn bisect(coefficients: &[f64], mut lo: f64, mut hi: f64, f_lo: f64) -> f64 {
+    for _ in 0..200 {
+        let mid = 0.5 * (lo + hi);
+        if mid <= lo || mid >= hi {
+            break;
+        }
+        let f_mid = evaluate(coefficients, mid);
+        if f_mid == 0.0 {
+            return mid;
+        }
+        if f_mid.signum() == f_lo.signum() {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    0.5 * (lo + hi)
+}
+
+// Horner's method
+fn evaluate(coefficients: &[f64], x: f64) -> f64 {
+    coefficients.iter().fold(0.0, |acc, c| acc * x + c)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::math::utils::f64_eq;
+
+    use super::*;
+
+    fn assert_roots(coefficients: &[f64], expected: &[f64]) {
+        let roots = real_roots(coefficients);
+        assert_eq!(roots.len(), expected.len(), "{:?}", roots);
+        for (r, e) in roots.iter().zip(expected) {
+            assert!(f64_eq(*r, *e), "{:?}", roots);
+        }
+    }
+
+    #[test]
+    fn finding_real_roots() {
+        assert_roots(&[2.0, -4.0], &[2.0]);
+        assert_roots(&[1.0, 0.0, 1.0], &[]);
+        assert_roots(&[0.0, 1.0, -3.0, 2.0], &[1.0, 2.0]);
+        // (x + 2)(x - 1)(x - 3)
+        assert_roots(&[1.0, -2.0, -5.0, 6.0], &[-2.0, 1.0, 3.0]);
+        // (x^2 - 1)(x^2 - 4) and (x^2 + 1)(x - 0.5)(x - 7)
+        assert_roots(&[1.0, 0.0, -5.0, 0.0, 4.0], &[-2.0, -1.0, 1.0, 2.0]);
+        assert_roots(&[1.0, -7.5, 4.5, -7.5, 3.5], &[0.5, 7.0]);
+        // widely spread roots, (x - 0.001)(x - 1000)(x + 50)(x - 2)
+        let spread = [1.0, -952.001, -48099.048, 100048.1, -100.0];
+        assert_roots(&spread, &[-50.0, 0.001, 2.0, 1000.0]);
+    }
+}
@@ -0,0 +1,173 @@
+use std::ops;
+
+use super::{matrix::Matrix, tuples::Tuple, utils::f64_eq};
+
+/*
+    A unit quaternion representing an orientation, these can be blended smoothly
+    between animation frames and then turned back into a rotation matrix
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+impl ops::Mul for Quaternion {
+    type Output = Self;
+
+    // the rotation of rhs followed by the rotation of self, the same order as multiplying matrices
+    fn mul(self, rhs: Self) -> Self {
+        Quaternion {
+            w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+            x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+        }
+    }
+}
+
+impl PartialEq for Quaternion {
+    fn eq(&self, other: &Self) -> bool {
+        f64_eq(self.w, other.w)
+            && f64_eq(self.x, other.x)
+            && f64_eq(self.y, other.y)
+            && f64_eq(self.z, other.z)
+    }
+}
+
+impl Quaternion {
+    pub fn new(w: f64, x: f64, y: f64, z: f64) -> Quaternion {
+        Quaternion { w, x, y, z }
+    }
+
+    pub fn identity() -> Quaternion {
+        Quaternion::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    pub fn from_axis_angle(axis: Tuple, radians: f64) -> Quaternion {
+        assert!(axis.is_vector());
+        let k = axis.normalize();
+        let half = radians / 2.0;
+        let sin = half.sin();
+        Quaternion::new(half.cos(), k.x * sin, k.y * sin, k.z * sin)
+    }
+
+    pub fn dot(&self, other: &Quaternion) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn normalize(&self) -> Quaternion {
+        let magnitude = self.dot(self).sqrt();
+        Quaternion::new(
+            self.w / magnitude,
+            self.x / magnitude,
+            self.y / magnitude,
+            self.z / magnitude,
+        )
+    }
+
+    pub fn to_matrix(self) -> Matrix {
+        let Quaternion { w, x, y, z } = self.normalize();
+        let mut m = Matrix::identity(4);
+
+        m.matrix[0][0] = 1.0 - 2.0 * (y * y + z * z);
+        m.matrix[0][1] = 2.0 * (x * y - w * z);
+        m.matrix[0][2] = 2.0 * (x * z + w * y);
+        m.matrix[1][0] = 2.0 * (x * y + w * z);
+        m.matrix[1][1] = 1.0 - 2.0 * (x * x + z * z);
+        m.matrix[1][2] = 2.0 * (y * z - w * x);
+        m.matrix[2][0] = 2.0 * (x * z - w * y);
+        m.matrix[2][1] = 2.0 * (y * z + w * x);
+        m.matrix[2][2] = 1.0 - 2.0 * (x * x + y * y);
+
+        m
+    }
+
+    /*
+        Spherical linear interpolation, turns at a constant rate from self at t = 0
+        to other at t = 1 along the shortest path
+    */
+    pub fn slerp(&self, other: &Quaternion, t: f64) -> Quaternion {
+        let a = self.normalize();
+        let mut b = other.normalize();
+        let mut cos = a.dot(&b);
+
+        // q and -q are the same orientation, flip one so we do not go the long way around
+        if cos < 0.0 {
+            b = Quaternion::new(-b.w, -b.x, -b.y, -b.z);
+            cos = -cos;
+        }
+
+        // nearly identical orientations, a straight blend avoids dividing by a tiny sine
+        let (wa, wb) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let angle = cos.acos();
+            let sin = angle.sin();
+            (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+        };
+
+        Quaternion::new(
+            a.w * wa + b.w * wb,
+            a.x * wa + b.x * wb,
+            a.y * wa + b.y * wb,
+            a.z * wa + b.z * wb,
+        )
+        .normalize()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::PI;
+
+    use super::*;
+
+    #[test]
+    fn axis_angle_matches_rotation_matrix() {
+        let axes = [
+            Tuple::vector(1.0, 0.0, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0),
+            Tuple::vector(1.0, 2.0, -3.0),
+        ];
+        for axis in axes {
+            for radians in [PI / 2.0, -PI / 7.0, 2.5] {
+                assert_eq!(
+                    Quaternion::from_axis_angle(axis, radians).to_matrix(),
+                    Matrix::rotation(axis, radians)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn multiplying_composes_rotations() {
+        let a = Quaternion::from_axis_angle(Tuple::vector(0.0, 1.0, 0.0), PI / 3.0);
+        let b = Quaternion::from_axis_angle(Tuple::vector(1.0, 0.0, 1.0), PI / 4.0);
+        assert_eq!((a * b).to_matrix(), &a.to_matrix() * &b.to_matrix());
+        assert_eq!(a * Quaternion::identity(), a);
+    }
+
+    #[test]
+    fn slerp_endpoints_and_midpoint() {
+        let axis = Tuple::vector(0.0, 0.0, 1.0);
+        let start = Quaternion::identity();
+        let end = Quaternion::from_axis_angle(axis, PI / 2.0);
+
+        assert_eq!(start.slerp(&end, 0.0), start);
+        assert_eq!(start.slerp(&end, 1.0), end);
+        assert_eq!(
+            start.slerp(&end, 0.5),
+            Quaternion::from_axis_angle(axis, PI / 4.0)
+        );
+
+        // the negated quaternion is the same orientation, slerp still takes the short way
+        let flipped = Quaternion::new(-end.w, -end.x, -end.y, -end.z);
+        assert_eq!(
+            start.slerp(&flipped, 0.5).to_matrix(),
+            Matrix::rotation_z(PI / 4.0)
+        );
+    }
+}
@@ -4,13 +4,43 @@ use super::{matrix::Matrix, tuples::Tuple};
 pub struct Ray {
     pub origin: Tuple,
     pub direction: Tuple,
+    pub width: f64, // radius the ray grows by per unit travelled, zero for an ideal ray
 }
 
 impl Ray {
     pub fn new(origin: Tuple, direction: Tuple) -> Ray {
         assert!(origin.is_point());
         assert!(direction.is_vector());
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            width: 0.0,
+        }
+    }
+
+    /*
+        Build a ray from an origin and direction already known to be a point and a vector,
+        the checks in new only run in debug builds here since this is called for every
+        secondary ray during a render
+    */
+    pub fn new_unchecked(origin: Tuple, direction: Tuple) -> Ray {
+        debug_assert!(origin.is_point());
+        debug_assert!(direction.is_vector());
+        Ray {
+            origin,
+            direction,
+            width: 0.0,
+        }
+    }
+
+    /*
+        Treat the ray as a thin cone so shapes narrower than the cone
+        can report how much of it they cover
+    */
+    pub fn with_width(mut self, width: f64) -> Ray {
+        assert!(width >= 0.0);
+        self.width = width;
+        self
     }
 
     pub fn position(&self, t: f64) -> Tuple {
@@ -30,9 +60,17 @@ impl Ray {
     }
 
     pub fn apply_transform(&self, transform: &Matrix) -> Ray {
+        let width = if self.width > 0.0 {
+            // scale by the largest stretch of any axis, this overestimates the cone for non uniform scaling
+            self.width * transform.max_stretch()
+        } else {
+            0.0
+        };
+
         Ray {
             origin: transform * &self.origin,
             direction: transform * &self.direction,
+            width,
         }
     }
 }
@@ -68,6 +106,7 @@ mod test {
         let r = Ray {
             origin: Tuple::point(1.0, 2.0, 3.0),
             direction: Tuple::vector(0.0, 1.0, 0.0),
+            width: 0.0,
         };
 
         let res = r.translate(3.0, 4.0, 5.0);
@@ -81,6 +120,7 @@ mod test {
         let r = Ray {
             origin: Tuple::point(1.0, 2.0, 3.0),
             direction: Tuple::vector(0.0, 1.0, 0.0),
+            width: 0.0,
         };
 
         let res = r.scale(2.0, 3.0, 4.0);
@@ -88,4 +128,34 @@ mod test {
         assert!(res.origin == Tuple::point(2.0, 6.0, 12.0));
         assert!(res.direction == Tuple::vector(0.0, 3.0, 0.0));
     }
+
+    #[test]
+    fn unchecked_ray_matches_checked() {
+        let origin = Tuple::point(1.0, 2.0, 3.0);
+        let direction = Tuple::vector(4.0, 5.0, 6.0);
+        let checked = Ray::new(origin, direction);
+        let unchecked = Ray::new_unchecked(origin, direction);
+        assert!(checked == unchecked);
+        assert_eq!(checked.width, unchecked.width);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn unchecked_ray_still_validates_in_debug() {
+        Ray::new_unchecked(Tuple::vector(1.0, 2.0, 3.0), Tuple::vector(4.0, 5.0, 6.0));
+    }
+
+    #[test]
+    fn width_follows_transform() {
+        let r =
+            Ray::new(Tuple::point(0.0, 0.0, 0.0), Tuple::vector(0.0, 0.0, 1.0)).with_width(0.01);
+        let res = r.scale(1.0, 100.0, 1.0);
+        assert!((res.width - 1.0).abs() < 1e-9);
+        assert_eq!(r.translate(1.0, 2.0, 3.0).width, 0.01);
+        assert_eq!(
+            Ray::new(r.origin, r.direction).scale(5.0, 5.0, 5.0).width,
+            0.0
+        );
+    }
 }
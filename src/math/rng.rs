@@ -0,0 +1,59 @@
+/*
+    A small SplitMix64 generator, anything random in a render draws from one of
+    these so the same seed always gives back the same image
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    /*
+        A generator for a single pixel, every pixel gets its own stream so the
+        result does not depend on which thread renders it or in what order
+    */
+    pub fn for_pixel(seed: u64, x: usize, y: usize) -> Rng {
+        let mut rng = Rng::new(
+            seed ^ (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+                ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F),
+        );
+        // throw away the first value so neighbouring pixels do not start out correlated
+        rng.next_u64();
+        rng
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // uniform in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = Rng::for_pixel(42, 3, 7);
+        let mut b = Rng::for_pixel(42, 3, 7);
+        let mut c = Rng::for_pixel(42, 7, 3);
+        for _ in 0..100 {
+            let v = a.next_f64();
+            assert!((0.0..1.0).contains(&v));
+            assert_eq!(v, b.next_f64());
+            assert_ne!(v, c.next_f64());
+        }
+    }
+}
@@ -3,6 +3,7 @@ use std::ops;
 use super::utils::f64_eq;
 
 #[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
 pub struct Tuple {
     pub x: f64,
     pub y: f64,
@@ -100,9 +101,9 @@ impl ops::Neg for Tuple {
     fn neg(self) -> Self::Output {
         assert!(self.is_vector());
         Tuple {
-            x: self.x * -1.0,
-            y: self.y * -1.0,
-            z: self.z * -1.0,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
             w: self.w,
         }
     }
@@ -172,10 +173,28 @@ impl Tuple {
         let scalar = *normal_vector * 2.0 * self.dot(normal_vector);
         *self - scalar
     }
+
+    /*
+        The angle in radians between two vectors, the cosine is clamped since
+        rounding can push it just outside of [-1, 1] for parallel vectors
+    */
+    pub fn angle_between(&self, other: &Tuple) -> f64 {
+        assert!(self.is_vector() && other.is_vector());
+        let cos = self.normalize().dot(&other.normalize());
+        cos.clamp(-1.0, 1.0).acos()
+    }
+
+    // the part of this vector which points along other
+    pub fn project_onto(&self, other: &Tuple) -> Tuple {
+        assert!(self.is_vector() && other.is_vector());
+        *other * (self.dot(other) / other.dot(other))
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use std::f64::consts::PI;
+
     use super::*;
 
     #[test]
@@ -369,4 +388,39 @@ mod test {
         ));
         assert!(res == Tuple::vector(1.0, 0.0, 0.0));
     }
+
+    #[test]
+    fn angle_between_vectors() {
+        let x = Tuple::vector(1.0, 0.0, 0.0);
+        assert!(f64_eq(
+            x.angle_between(&Tuple::vector(0.0, 3.0, 0.0)),
+            PI / 2.0
+        ));
+        assert!(f64_eq(x.angle_between(&Tuple::vector(-2.0, 0.0, 0.0)), PI));
+        assert!(f64_eq(
+            x.angle_between(&Tuple::vector(1.0, 1.0, 0.0)),
+            PI / 4.0
+        ));
+
+        // rounding would otherwise give a cosine just above 1 and a NaN angle
+        let v = Tuple::vector(0.1, 0.7, 0.3);
+        assert!(f64_eq(v.angle_between(&(v * 3.0)), 0.0));
+    }
+
+    #[test]
+    fn projecting_vectors() {
+        let v = Tuple::vector(3.0, -2.0, 5.0);
+        assert_eq!(
+            v.project_onto(&Tuple::vector(0.0, 1.0, 0.0)),
+            Tuple::vector(0.0, -2.0, 0.0)
+        );
+        assert_eq!(
+            v.project_onto(&Tuple::vector(0.0, 0.0, 4.0)),
+            Tuple::vector(0.0, 0.0, 5.0)
+        );
+        assert_eq!(
+            Tuple::vector(2.0, 0.0, 0.0).project_onto(&Tuple::vector(1.0, 1.0, 0.0)),
+            Tuple::vector(1.0, 1.0, 0.0)
+        );
+    }
 }
@@ -1,6 +1,11 @@
 pub const EPSILON: f64 = 0.00001;
 pub fn f64_eq(a: f64, b: f64) -> bool {
-    (a - b).abs() < EPSILON
+    f64_eq_tol(a, b, EPSILON)
+}
+
+// compare with a tolerance suited to the scale of the values, e.g. a scene measured in millimeters
+pub fn f64_eq_tol(a: f64, b: f64, tol: f64) -> bool {
+    (a - b).abs() < tol
 }
 
 #[cfg(test)]
@@ -13,4 +18,14 @@ mod test {
         assert!(!f64_eq(0.01, 0.015));
         assert!(f64_eq(1.0 * 2.0 / 2.0, 1.0));
     }
+
+    #[test]
+    fn f64_eq_with_tolerance() {
+        let a = 3.0;
+        let b = a + 1e-4;
+        assert!(!f64_eq(a, b));
+        assert!(!f64_eq_tol(a, b, EPSILON));
+        assert!(f64_eq_tol(a, b, 1e-3));
+        assert!(!f64_eq_tol(a, b, 1e-4 / 2.0));
+    }
 }
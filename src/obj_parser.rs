@@ -6,12 +6,35 @@ use crate::shapes::intersect::Intersectable;
 use crate::shapes::smooth_triangle::SmoothTriangle;
 use crate::shapes::triangle::Triangle;
 
+/*
+    What to do with the vertex normals of a mesh, some exporters write them
+    pointing into the model which makes it look inside out
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NormalFlip {
+    Never,
+    Always,
+    Auto, // flip when most vertex normals point away from the faces they belong to
+}
+
+type Face = (Vec<usize>, Vec<Option<usize>>);
+
 pub fn parse_obj_file(s: &str, transform: Option<Matrix>, material: Option<Material>) -> Group {
+    parse_obj_file_with_normals(s, transform, material, NormalFlip::Never)
+}
+
+pub fn parse_obj_file_with_normals(
+    s: &str,
+    transform: Option<Matrix>,
+    material: Option<Material>,
+    flip: NormalFlip,
+) -> Group {
     let mut group = Group::new(transform, material);
 
     // obj files are 1-indexed so add a dummy vector to shift all data over by 1
     let mut vertices: Vec<Tuple> = vec![Tuple::vector(0.0, 0.0, 0.0)];
     let mut normals: Vec<Tuple> = vec![Tuple::vector(0.0, 0.0, 0.0)];
+    let mut faces: Vec<Face> = vec![];
 
     for line in s.lines() {
         let symbols: Vec<&str> = line
@@ -41,22 +64,12 @@ pub fn parse_obj_file(s: &str, transform: Option<Matrix>, material: Option<Mater
                     let face_info: Vec<&str> = symbol.split('/').collect();
                     face_vertices_indices.push(face_info[0].parse::<usize>().unwrap());
                     face_normal_indices.push(if face_info.len() >= 2 {
-                        match face_info[2].parse::<usize>() {
-                            Ok(i) => Some(i),
-                            Err(_) => None,
-                        }
+                        face_info[2].parse::<usize>().ok()
                     } else {
                         None
                     })
                 }
-                for t in fan_triangulation(
-                    face_vertices_indices,
-                    face_normal_indices,
-                    &vertices,
-                    &normals,
-                ) {
-                    group.add_object(t);
-                }
+                faces.push((face_vertices_indices, face_normal_indices));
             }
             _ => {
                 // ignore unrecognized lines
@@ -64,35 +77,94 @@ pub fn parse_obj_file(s: &str, transform: Option<Matrix>, material: Option<Mater
         }
     }
 
+    // the whole mesh has to be read before we can tell which way its normals point
+    let flip_normals = match flip {
+        NormalFlip::Never => false,
+        NormalFlip::Always => true,
+        NormalFlip::Auto => normals_look_inverted(&faces, &vertices, &normals),
+    };
+
+    // every face remembers the mesh it came from, even once the group is divided
+    let mesh = group.get_id();
+    for (face_vertices_indices, face_normal_indices) in faces {
+        for t in fan_triangulation(
+            face_vertices_indices,
+            face_normal_indices,
+            &vertices,
+            &normals,
+            flip_normals,
+            mesh,
+        ) {
+            group.add_object(t);
+        }
+    }
+
     group
 }
 
+/*
+    Compare each vertex normal against the normal of the face it belongs to,
+    obj files wind the corners of a face counter clockwise when seen from the front
+*/
+fn normals_look_inverted(faces: &[Face], vertices: &[Tuple], normals: &[Tuple]) -> bool {
+    let mut votes: i64 = 0;
+    for (vector_indices, normal_indices) in faces {
+        for i in 1..vector_indices.len() - 1 {
+            let corners = [0, i, i + 1];
+            let [a, b, c] = corners.map(|k| vertices[vector_indices[k]]);
+            let face_normal = (b - a).cross(&(c - a));
+            for k in corners {
+                if let Some(n) = normal_indices[k] {
+                    let d = normals[n].dot(&face_normal);
+                    if d > 0.0 {
+                        votes += 1;
+                    } else if d < 0.0 {
+                        votes -= 1;
+                    }
+                }
+            }
+        }
+    }
+    votes < 0
+}
+
 // convert a face into a set of triangles
 fn fan_triangulation(
     vector_indices: Vec<usize>,
     normal_indices: Vec<Option<usize>>,
     vertices: &[Tuple],
     normals: &[Tuple],
+    flip_normals: bool,
+    mesh: usize,
 ) -> Vec<Box<dyn Intersectable>> {
     let mut triangles: Vec<Box<dyn Intersectable>> = vec![];
 
     for i in 1..vector_indices.len() - 1 {
         triangles.push(match normal_indices[i] {
-            Some(_) => Box::new(SmoothTriangle::new(
-                vertices[vector_indices[0]],
-                vertices[vector_indices[i]],
-                vertices[vector_indices[i + 1]],
-                normals[normal_indices[0].unwrap()],
-                normals[normal_indices[i].unwrap()],
-                normals[normal_indices[i + 1].unwrap()],
-                None,
-            )),
-            None => Box::new(Triangle::new(
-                vertices[vector_indices[0]],
-                vertices[vector_indices[i]],
-                vertices[vector_indices[i + 1]],
-                None,
-            )),
+            Some(_) => {
+                let mut t = SmoothTriangle::new(
+                    vertices[vector_indices[0]],
+                    vertices[vector_indices[i]],
+                    vertices[vector_indices[i + 1]],
+                    normals[normal_indices[0].unwrap()],
+                    normals[normal_indices[i].unwrap()],
+                    normals[normal_indices[i + 1].unwrap()],
+                    None,
+                );
+                t.flip_normals = flip_normals;
+                t.mesh = Some(mesh);
+                Box::new(t)
+            }
+            None => {
+                let mut t = Triangle::new(
+                    vertices[vector_indices[0]],
+                    vertices[vector_indices[i]],
+                    vertices[vector_indices[i + 1]],
+                    None,
+                );
+                t.mesh = Some(mesh);
+                Box::new(t)
+            }
         });
     }
 
@@ -101,7 +173,52 @@ fn fan_triangulation(
 
 #[cfg(test)]
 mod test {
-    use super::parse_obj_file;
+    use crate::{
+        draw::color::Color,
+        math::{matrix::Matrix, ray::Ray, tuples::Tuple, utils::f64_eq},
+        scene::{light::PointLight, world::World},
+        shapes::intersect::{hit, prepare_computations, Intersectable},
+    };
+
+    use super::{parse_obj_file, parse_obj_file_with_normals, NormalFlip};
+
+    #[test]
+    fn flipping_inverted_normals() {
+        // a square facing -z, once with normals to match and once with them pointing inwards
+        let square = |normal: &str| {
+            format!(
+                "v -1 -1 0\nv 1 -1 0\nv 1 1 0\nv -1 1 0\nvn {}\nf 1//1 4//1 3//1 2//1",
+                normal
+            )
+        };
+        let outward = square("0 0 -1");
+        let inward = square("0 0 1");
+
+        let shade = |obj: &str, flip: NormalFlip| {
+            let mut w = World::new();
+            w.light_sources.push(Box::new(PointLight::new(
+                Color::white(),
+                Tuple::point(0.0, 0.0, -5.0),
+            )));
+            w.objects
+                .push(Box::new(parse_obj_file_with_normals(obj, None, None, flip)));
+            let r = Ray::new(Tuple::point(0.2, 0.1, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+            let xs = w.intersect_world(&r);
+            let first = hit(&xs).unwrap();
+            let comps = prepare_computations(&first, &r, &xs, Some(&w));
+            (comps.inside, w.shade_hit(&comps, 5))
+        };
+
+        let (inside, lit) = shade(&outward, NormalFlip::Auto);
+        assert!(!inside);
+        assert_ne!(lit, Color::black());
+
+        // left alone the square looks like we are seeing it from inside the mesh
+        assert!(shade(&inward, NormalFlip::Never).0);
+        assert_eq!(shade(&inward, NormalFlip::Always), (false, lit));
+        assert_eq!(shade(&inward, NormalFlip::Auto), (false, lit));
+        assert!(shade(&outward, NormalFlip::Always).0);
+    }
 
     #[test]
     fn triangles_made() {
@@ -114,7 +231,7 @@ mod test {
         f 1 3 4";
 
         let g = parse_obj_file(data, None, None);
-        assert_eq!(g.objects.len(), 2);
+        assert_eq!(g.len(), 2);
     }
 
     #[test]
@@ -128,6 +245,62 @@ mod test {
         f 1 2 3 4 5";
 
         let g = parse_obj_file(data, None, None);
-        assert_eq!(g.objects.len(), 3);
+        assert_eq!(g.len(), 3);
+    }
+
+    #[test]
+    fn mesh_bounds_are_tight() {
+        let data = "
+        v -1.5 0.25 2
+        v 0.5 -3 1
+        v 2 1 -0.5
+        v 4 0.5 0
+        vn 0 0 1
+        f 1 2 3
+        f 2//1 4//1 3//1";
+
+        let g = parse_obj_file(data, None, None);
+        for o in g.objects() {
+            let b = o.bounds();
+            assert!(g.bounds().contains_box(&b));
+        }
+        assert_eq!(g.bounds().min, Tuple::point(-1.5, -3.0, -0.5));
+        assert_eq!(g.bounds().max, Tuple::point(4.0, 1.0, 2.0));
+
+        // the parent sees the mesh through the group transform
+        let g = parse_obj_file(data, Some(Matrix::translation(1.0, 2.0, 3.0)), None);
+        assert_eq!(g.parent_space_bounds().min, Tuple::point(-0.5, -1.0, 2.5));
+        assert_eq!(g.parent_space_bounds().max, Tuple::point(5.0, 3.0, 5.0));
+    }
+
+    #[test]
+    fn transforming_a_parsed_model() {
+        let data = "
+        v -1 -1 0
+        v 1 -1 0
+        v 1 1 0
+        v -1 1 0
+        f 1 2 3 4";
+
+        let mut g = parse_obj_file(data, Some(Matrix::translation(0.0, 0.0, 1.0)), None);
+        let r = Ray::new(Tuple::point(1.5, 0.5, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert!(hit(&g.intersect(&r)).is_none());
+
+        // applied on top of the transform given when parsing
+        g.apply_transform(&Matrix::scaling(2.0, 2.0, 2.0));
+        let xs = g.intersect(&r);
+        assert!(f64_eq(hit(&xs).unwrap().t, 7.0));
+        assert_eq!(g.parent_space_bounds().min, Tuple::point(-2.0, -2.0, 2.0));
+
+        g.apply_transform(&Matrix::translation(0.0, 0.0, 3.0));
+        let xs = g.intersect(&r);
+        assert!(f64_eq(hit(&xs).unwrap().t, 10.0));
+        assert_eq!(g.parent_space_bounds().max, Tuple::point(2.0, 2.0, 5.0));
+
+        // a rotation leaves the transform no longer axis aligned
+        g.apply_transform(&Matrix::rotation_y(std::f64::consts::PI));
+        assert!(hit(&g.intersect(&r)).is_none());
+        let back = Ray::new(Tuple::point(-1.5, 0.5, -10.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert!(f64_eq(hit(&g.intersect(&back)).unwrap().t, 5.0));
     }
 }
@@ -0,0 +1,797 @@
+use std::{collections::HashMap, fs, sync::atomic::Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    draw::{color::Color, material::Material},
+    math::{matrix::Matrix, tuples::Tuple},
+    shapes::{
+        cone::Cone,
+        cube::Cube,
+        cylinder::{Cylinder, CylinderParts},
+        disk::Disk,
+        group::Group,
+        intersect::{Intersectable, OBJECT_COUNTER},
+        plane::Plane,
+        quadric::Quadric,
+        rectangle::Rectangle,
+        smooth_triangle::SmoothTriangle,
+        sphere::Sphere,
+        torus::Torus,
+        triangle::Triangle,
+    },
+};
+
+use super::{
+    light::{AreaLight, DirectionalLight, Light, PointLight, SpotLight},
+    world::World,
+};
+
+/*
+    A plain data copy of a shape which can be written to disk, shapes are rebuilt
+    through their constructors on load so ids and cached matrices are fresh
+*/
+#[derive(Serialize, Deserialize)]
+enum CachedShape {
+    Sphere {
+        transform: Matrix,
+        material: Material,
+    },
+    Plane {
+        transform: Matrix,
+        material: Material,
+    },
+    Cube {
+        transform: Matrix,
+        material: Material,
+    },
+    Cylinder {
+        transform: Matrix,
+        material: Material,
+        minimum: f64,
+        maximum: f64,
+        radius: f64,
+        closed: bool,
+        parts: CylinderParts,
+    },
+    Cone {
+        transform: Matrix,
+        material: Material,
+        minimum: f64,
+        maximum: f64,
+        radius: f64,
+        closed: bool,
+    },
+    Disk {
+        transform: Matrix,
+        material: Material,
+        inner_radius: f64,
+        outer_radius: f64,
+    },
+    Quadric {
+        transform: Matrix,
+        material: Material,
+        coefficients: [f64; 10],
+    },
+    Rectangle {
+        transform: Matrix,
+        material: Material,
+        width: f64,
+        depth: f64,
+    },
+    Torus {
+        transform: Matrix,
+        material: Material,
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    Triangle {
+        points: [Tuple; 3],
+        colors: Option<[Color; 3]>,
+        mesh: Option<usize>,
+        transform: Matrix,
+        material: Material,
+    },
+    SmoothTriangle {
+        points: [Tuple; 3],
+        normals: [Tuple; 3],
+        flip_normals: bool,
+        colors: Option<[Color; 3]>,
+        mesh: Option<usize>,
+        transform: Matrix,
+        material: Material,
+    },
+    Group {
+        transform: Matrix,
+        material: Material,
+        children: Vec<CachedShape>,
+    },
+}
+
+fn to_cached(shape: &dyn Intersectable) -> Result<CachedShape, String> {
+    let transform = shape.get_transform().clone();
+    let material = Material::from_material(shape.get_material());
+    let any = shape.as_any();
+
+    if any.is::<Sphere>() {
+        Ok(CachedShape::Sphere {
+            transform,
+            material,
+        })
+    } else if any.is::<Plane>() {
+        Ok(CachedShape::Plane {
+            transform,
+            material,
+        })
+    } else if any.is::<Cube>() {
+        Ok(CachedShape::Cube {
+            transform,
+            material,
+        })
+    } else if let Some(c) = any.downcast_ref::<Cylinder>() {
+        Ok(CachedShape::Cylinder {
+            transform,
+            material,
+            minimum: c.minimum,
+            maximum: c.maximum,
+            radius: c.radius,
+            closed: c.closed,
+            parts: c.parts,
+        })
+    } else if let Some(c) = any.downcast_ref::<Cone>() {
+        Ok(CachedShape::Cone {
+            transform,
+            material,
+            minimum: c.minimum,
+            maximum: c.maximum,
+            radius: c.radius,
+            closed: c.closed,
+        })
+    } else if let Some(d) = any.downcast_ref::<Disk>() {
+        Ok(CachedShape::Disk {
+            transform,
+            material,
+            inner_radius: d.inner_radius,
+            outer_radius: d.outer_radius,
+        })
+    } else if let Some(q) = any.downcast_ref::<Quadric>() {
+        Ok(CachedShape::Quadric {
+            transform,
+            material,
+            coefficients: q.coefficients,
+        })
+    } else if let Some(r) = any.downcast_ref::<Rectangle>() {
+        Ok(CachedShape::Rectangle {
+            transform,
+            material,
+            width: r.width,
+            depth: r.depth,
+        })
+    } else if let Some(t) = any.downcast_ref::<Torus>() {
+        Ok(CachedShape::Torus {
+            transform,
+            material,
+            major_radius: t.major_radius,
+            minor_radius: t.minor_radius,
+        })
+    } else if let Some(t) = any.downcast_ref::<Triangle>() {
+        Ok(CachedShape::Triangle {
+            points: [t.p1, t.p2, t.p3],
+            colors: t.vertex_colors,
+            mesh: t.mesh,
+            transform,
+            material,
+        })
+    } else if let Some(t) = any.downcast_ref::<SmoothTriangle>() {
+        Ok(CachedShape::SmoothTriangle {
+            points: [t.p1, t.p2, t.p3],
+            normals: [t.n1, t.n2, t.n3],
+            flip_normals: t.flip_normals,
+            colors: t.vertex_colors,
+            mesh: t.mesh,
+            transform,
+            material,
+        })
+    } else if let Some(g) = any.downcast_ref::<Group>() {
+        let children = g
+            .objects()
+            .iter()
+            .map(|o| to_cached(o.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(CachedShape::Group {
+            transform,
+            material,
+            children,
+        })
+    } else {
+        Err(format!(
+            "Shape {} can not be written to the cache",
+            shape.get_id()
+        ))
+    }
+}
+
+/*
+    Mesh ids are only unique within the run that saved them, so each one
+    is swapped for a fresh id the first time it is seen while loading
+*/
+fn fresh_mesh(mesh: Option<usize>, meshes: &mut HashMap<usize, usize>) -> Option<usize> {
+    mesh.map(|m| {
+        *meshes
+            .entry(m)
+            .or_insert_with(|| OBJECT_COUNTER.fetch_add(1, Ordering::SeqCst))
+    })
+}
+
+fn from_cached(cached: CachedShape, meshes: &mut HashMap<usize, usize>) -> Box<dyn Intersectable> {
+    match cached {
+        CachedShape::Sphere {
+            transform,
+            material,
+        } => {
+            let mut s = Sphere::new(Some(transform));
+            s.material = material;
+            Box::new(s)
+        }
+        CachedShape::Plane {
+            transform,
+            material,
+        } => {
+            let mut p = Plane::new(Some(transform));
+            p.material = material;
+            Box::new(p)
+        }
+        CachedShape::Cube {
+            transform,
+            material,
+        } => {
+            let mut c = Cube::new(Some(transform));
+            c.material = material;
+            Box::new(c)
+        }
+        CachedShape::Cylinder {
+            transform,
+            material,
+            minimum,
+            maximum,
+            radius,
+            closed,
+            parts,
+        } => {
+            let mut c = Cylinder::new(Some(transform));
+            c.material = material;
+            c.minimum = minimum;
+            c.maximum = maximum;
+            c.radius = radius;
+            c.closed = closed;
+            c.parts = parts;
+            Box::new(c)
+        }
+        CachedShape::Cone {
+            transform,
+            material,
+            minimum,
+            maximum,
+            radius,
+            closed,
+        } => {
+            let mut c = Cone::new(Some(transform));
+            c.material = material;
+            c.minimum = minimum;
+            c.maximum = maximum;
+            c.radius = radius;
+            c.closed = closed;
+            Box::new(c)
+        }
+        CachedShape::Disk {
+            transform,
+            material,
+            inner_radius,
+            outer_radius,
+        } => {
+            let mut d = Disk::new(Some(transform));
+            d.material = material;
+            d.inner_radius = inner_radius;
+            d.outer_radius = outer_radius;
+            Box::new(d)
+        }
+        CachedShape::Quadric {
+            transform,
+            material,
+            coefficients,
+        } => {
+            let mut q = Quadric::new(coefficients, Some(transform));
+            q.material = material;
+            Box::new(q)
+        }
+        CachedShape::Rectangle {
+            transform,
+            material,
+            width,
+            depth,
+        } => {
+            let mut r = Rectangle::new(width, depth, Some(transform));
+            r.material = material;
+            Box::new(r)
+        }
+        CachedShape::Torus {
+            transform,
+            material,
+            major_radius,
+            minor_radius,
+        } => {
+            let mut t = Torus::new(Some(transform));
+            t.material = material;
+            t.major_radius = major_radius;
+            t.minor_radius = minor_radius;
+            Box::new(t)
+        }
+        CachedShape::Triangle {
+            points,
+            colors,
+            mesh,
+            transform,
+            material,
+        } => {
+            let mut t = Triangle::new(points[0], points[1], points[2], Some(transform));
+            t.material = material;
+            t.vertex_colors = colors;
+            t.mesh = fresh_mesh(mesh, meshes);
+            Box::new(t)
+        }
+        CachedShape::SmoothTriangle {
+            points,
+            normals,
+            flip_normals,
+            colors,
+            mesh,
+            transform,
+            material,
+        } => {
+            let mut t = SmoothTriangle::new(
+                points[0],
+                points[1],
+                points[2],
+                normals[0],
+                normals[1],
+                normals[2],
+                Some(transform),
+            );
+            t.material = material;
+            t.vertex_colors = colors;
+            t.flip_normals = flip_normals;
+            t.mesh = fresh_mesh(mesh, meshes);
+            Box::new(t)
+        }
+        CachedShape::Group {
+            transform,
+            material,
+            children,
+        } => {
+            let mut g = Group::new(Some(transform), Some(material));
+            // children keep their own cached material rather than inheriting the groups
+            for child in children {
+                g.add_object_keep_material(from_cached(child, meshes));
+            }
+            Box::new(g)
+        }
+    }
+}
+
+// lights are trait objects as well, so they are stored by kind
+#[derive(Serialize, Deserialize)]
+enum CachedLight {
+    Point(PointLight),
+    Directional(DirectionalLight),
+    Spot(SpotLight),
+    Area(AreaLight),
+}
+
+fn to_cached_light(light: &dyn Light) -> Result<CachedLight, String> {
+    let any = light.as_any();
+    if let Some(l) = any.downcast_ref::<PointLight>() {
+        Ok(CachedLight::Point(l.clone()))
+    } else if let Some(l) = any.downcast_ref::<DirectionalLight>() {
+        Ok(CachedLight::Directional(l.clone()))
+    } else if let Some(l) = any.downcast_ref::<SpotLight>() {
+        Ok(CachedLight::Spot(l.clone()))
+    } else if let Some(l) = any.downcast_ref::<AreaLight>() {
+        Ok(CachedLight::Area(l.clone()))
+    } else {
+        Err("Light can not be written to the cache".to_string())
+    }
+}
+
+fn from_cached_light(cached: CachedLight) -> Box<dyn Light> {
+    match cached {
+        CachedLight::Point(l) => Box::new(l),
+        CachedLight::Directional(l) => Box::new(l),
+        CachedLight::Spot(l) => Box::new(l),
+        CachedLight::Area(l) => Box::new(l),
+    }
+}
+
+impl World {
+    /*
+        Write the built geometry and lights of this world to a binary file
+        so it can be loaded again without re-parsing any obj files
+    */
+    pub fn save_cache(&self, path: &str) -> Result<(), String> {
+        let objects = self
+            .objects
+            .iter()
+            .map(|o| to_cached(o.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let lights = self
+            .light_sources
+            .iter()
+            .map(|l| to_cached_light(l.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let bytes = bincode::serialize(&(&objects, &lights))
+            .map_err(|e| format!("Could not serialize world: {}", e))?;
+        fs::write(path, bytes).map_err(|e| format!("Could not write cache {}: {}", path, e))
+    }
+
+    pub fn load_cache(path: &str) -> Result<World, String> {
+        let bytes = fs::read(path).map_err(|e| format!("Could not read cache {}: {}", path, e))?;
+        let (objects, lights): (Vec<CachedShape>, Vec<CachedLight>) = bincode::deserialize(&bytes)
+            .map_err(|e| format!("Could not deserialize world: {}", e))?;
+
+        let mut world = World::new();
+        let mut meshes = HashMap::new();
+        world.objects = objects
+            .into_iter()
+            .map(|o| from_cached(o, &mut meshes))
+            .collect();
+        world.light_sources = lights.into_iter().map(from_cached_light).collect();
+        Ok(world)
+    }
+}
+
+/*
+    Patterns are stored behind a trait object so they go through
+    this enum when a material is serialized
+*/
+pub mod pattern {
+    use serde::{de::Error as DeError, ser::Error as SerError};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::draw::{
+        patterns::{
+            cylindrical_map, planar_map, spherical_map, Blend, Checkered, Gradient, Pattern,
+            PatternTransform, Perturbed, RadialGradient, Rings, Solid, Stripe, UvCheckers, UvMap,
+            UvPattern, UvTexture,
+        },
+        texture::TextureMap,
+    };
+
+    // uv maps are plain functions so only the ones the crate provides can be stored
+    #[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+    enum CachedUvMap {
+        Spherical,
+        Planar,
+        Cylindrical,
+    }
+
+    const UV_MAPS: [(CachedUvMap, UvMap); 3] = [
+        (CachedUvMap::Spherical, spherical_map),
+        (CachedUvMap::Planar, planar_map),
+        (CachedUvMap::Cylindrical, cylindrical_map),
+    ];
+
+    #[derive(Serialize, Deserialize)]
+    enum CachedUvTexture {
+        Checkers(UvCheckers),
+        Image(TextureMap),
+    }
+
+    #[derive(Serialize, Deserialize)]
+    enum CachedPattern {
+        Solid(Solid),
+        Stripe(Stripe),
+        Gradient(Gradient),
+        Rings(Rings),
+        Checkered(Checkered),
+        RadialGradient(RadialGradient),
+        // the noise is rebuilt from the seed rather than storing its permutation table
+        Perturbed {
+            pattern: Box<CachedPattern>,
+            seed: u64,
+            scale: f64,
+            octaves: usize,
+            transform: PatternTransform,
+        },
+        Blend {
+            a: Box<CachedPattern>,
+            b: Box<CachedPattern>,
+            ratio: f64,
+            transform: PatternTransform,
+        },
+        Uv {
+            map: CachedUvMap,
+            texture: CachedUvTexture,
+            transform: PatternTransform,
+        },
+    }
+
+    fn to_cached(pattern: &dyn Pattern) -> Result<CachedPattern, String> {
+        let any = pattern.as_any();
+        Ok(if let Some(p) = any.downcast_ref::<Solid>() {
+            CachedPattern::Solid(p.clone())
+        } else if let Some(p) = any.downcast_ref::<Stripe>() {
+            CachedPattern::Stripe(p.clone())
+        } else if let Some(p) = any.downcast_ref::<Gradient>() {
+            CachedPattern::Gradient(p.clone())
+        } else if let Some(p) = any.downcast_ref::<Rings>() {
+            CachedPattern::Rings(p.clone())
+        } else if let Some(p) = any.downcast_ref::<Checkered>() {
+            CachedPattern::Checkered(p.clone())
+        } else if let Some(p) = any.downcast_ref::<RadialGradient>() {
+            CachedPattern::RadialGradient(p.clone())
+        } else if let Some(p) = any.downcast_ref::<Perturbed>() {
+            CachedPattern::Perturbed {
+                pattern: Box::new(to_cached(p.pattern())?),
+                seed: p.seed(),
+                scale: p.scale,
+                octaves: p.octaves,
+                transform: p.pattern_transform().clone(),
+            }
+        } else if let Some(p) = any.downcast_ref::<Blend>() {
+            let (a, b) = p.patterns();
+            CachedPattern::Blend {
+                a: Box::new(to_cached(a)?),
+                b: Box::new(to_cached(b)?),
+                ratio: p.ratio(),
+                transform: p.pattern_transform().clone(),
+            }
+        } else if let Some(p) = any.downcast_ref::<UvPattern>() {
+            let map = UV_MAPS
+                .iter()
+                .find(|(_, f)| std::ptr::fn_addr_eq(*f, p.map()))
+                .map(|(m, _)| *m)
+                .ok_or("uv map can not be written to the cache")?;
+            let texture = p.texture().as_any();
+            let texture = if let Some(t) = texture.downcast_ref::<UvCheckers>() {
+                CachedUvTexture::Checkers(t.clone())
+            } else if let Some(t) = texture.downcast_ref::<TextureMap>() {
+                CachedUvTexture::Image(t.clone())
+            } else {
+                return Err("uv texture can not be written to the cache".to_string());
+            };
+            CachedPattern::Uv {
+                map,
+                texture,
+                transform: p.pattern_transform().clone(),
+            }
+        } else {
+            return Err("pattern can not be written to the cache".to_string());
+        })
+    }
+
+    fn from_cached(cached: CachedPattern) -> Box<dyn Pattern> {
+        match cached {
+            CachedPattern::Solid(p) => Box::new(p),
+            CachedPattern::Stripe(p) => Box::new(p),
+            CachedPattern::Gradient(p) => Box::new(p),
+            CachedPattern::Rings(p) => Box::new(p),
+            CachedPattern::Checkered(p) => Box::new(p),
+            CachedPattern::RadialGradient(p) => Box::new(p),
+            CachedPattern::Perturbed {
+                pattern,
+                seed,
+                scale,
+                octaves,
+                transform,
+            } => {
+                let mut p = Perturbed::new(from_cached(*pattern), seed);
+                p.scale = scale;
+                p.octaves = octaves;
+                *p.pattern_transform_mut() = transform;
+                Box::new(p)
+            }
+            CachedPattern::Blend {
+                a,
+                b,
+                ratio,
+                transform,
+            } => {
+                let mut p = Blend::new(from_cached(*a), from_cached(*b), ratio);
+                *p.pattern_transform_mut() = transform;
+                Box::new(p)
+            }
+            CachedPattern::Uv {
+                map,
+                texture,
+                transform,
+            } => {
+                let (_, map) = UV_MAPS.iter().find(|(m, _)| *m == map).unwrap();
+                let texture: Box<dyn UvTexture> = match texture {
+                    CachedUvTexture::Checkers(t) => Box::new(t),
+                    CachedUvTexture::Image(t) => Box::new(t),
+                };
+                let mut p = UvPattern::new(*map, texture);
+                *p.pattern_transform_mut() = transform;
+                Box::new(p)
+            }
+        }
+    }
+
+    #[allow(clippy::borrowed_box)]
+    pub fn serialize<S: Serializer>(pattern: &Box<dyn Pattern>, s: S) -> Result<S::Ok, S::Error> {
+        to_cached(pattern.as_ref())
+            .map_err(S::Error::custom)?
+            .serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Box<dyn Pattern>, D::Error> {
+        Ok(from_cached(
+            CachedPattern::deserialize(d).map_err(D::Error::custom)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        draw::{
+            color::Color,
+            patterns::{
+                cylindrical_map, planar_map, spherical_map, Blend, Checkered, Pattern, Perturbed,
+                Stripe, UvCheckers, UvPattern,
+            },
+        },
+        math::{ray::Ray, utils::f64_eq},
+        obj_parser::parse_obj_file,
+    };
+
+    use super::*;
+
+    #[test]
+    fn saving_and_loading_world_keeps_intersections() {
+        let mut w = World::new();
+        w.light_sources.push(Box::new(PointLight::new(
+            Color::white(),
+            Tuple::point(-10.0, 10.0, -10.0),
+        )));
+        w.light_sources.push(Box::new(DirectionalLight::new(
+            Color::new(0.2, 0.2, 0.3),
+            Tuple::vector(1.0, -1.0, 0.5),
+        )));
+        let mut area = AreaLight::new(
+            Color::new(0.3, 0.3, 0.3),
+            Tuple::point(-1.0, 4.0, -1.0),
+            Tuple::vector(2.0, 0.0, 0.0),
+            2,
+            Tuple::vector(0.0, 0.0, 2.0),
+            3,
+        );
+        area.jitter = true;
+        w.light_sources.push(Box::new(area));
+        w.light_sources.push(Box::new(SpotLight::new(
+            Color::new(0.5, 0.4, 0.2),
+            Tuple::point(0.0, 5.0, -2.0),
+            Tuple::vector(0.0, -1.0, 0.3),
+            0.2,
+            0.4,
+        )));
+
+        let mut floor = Plane::new(Some(Matrix::translation(0.0, -1.0, 0.0)));
+        floor.material.pattern = Box::new(Checkered::new(Color::black(), Color::white()));
+        w.objects.push(Box::new(floor));
+        w.objects
+            .push(Box::new(Sphere::new(Some(Matrix::scaling(0.5, 0.5, 0.5)))));
+        let mut ring = Torus::new(Some(Matrix::translation(0.0, 3.0, 0.0)));
+        ring.major_radius = 1.5;
+        ring.minor_radius = 0.4;
+        w.objects.push(Box::new(ring));
+        let mut washer = Disk::new(Some(Matrix::translation(0.0, 0.0, 3.0)));
+        washer.inner_radius = 0.3;
+        w.objects.push(Box::new(washer));
+        w.objects.push(Box::new(Rectangle::new(
+            3.0,
+            1.0,
+            Some(Matrix::translation(0.0, 4.5, 0.0)),
+        )));
+        // a hyperboloid of one sheet off to the side
+        w.objects.push(Box::new(Quadric::new(
+            [1.0, -1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -0.25],
+            Some(Matrix::translation(-4.0, 0.0, 0.0)),
+        )));
+        let mesh = "
+        v -1 1 2
+        v -1 -1 2
+        v 1 -1 2
+        v 1 1 2
+        f 1 2 3 4";
+        w.objects.push(Box::new(parse_obj_file(mesh, None, None)));
+
+        let path =
+            std::env::temp_dir().join(format!("ray_tracer_cache_test_{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        w.save_cache(path).unwrap();
+        let loaded = World::load_cache(path).unwrap();
+        assert_eq!(loaded.light_sources.len(), 4);
+        assert_eq!(loaded.objects.len(), 7);
+
+        let rays = [
+            Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0)),
+            Ray::new(Tuple::point(0.7, 0.5, -5.0), Tuple::vector(0.0, 0.0, 1.0)),
+            Ray::new(Tuple::point(0.0, 3.0, -5.0), Tuple::vector(0.0, -1.0, 1.0)),
+            Ray::new(Tuple::point(-5.0, 3.1, 0.0), Tuple::vector(1.0, 0.0, 0.0)),
+            Ray::new(Tuple::point(1.2, 6.0, 0.3), Tuple::vector(0.0, -1.0, 0.0)),
+            Ray::new(Tuple::point(-4.0, 1.0, -5.0), Tuple::vector(0.0, 0.0, 1.0)),
+        ];
+        for ray in &rays {
+            let expected = w.intersect_world(ray);
+            let actual = loaded.intersect_world(ray);
+            assert_eq!(expected.len(), actual.len());
+            for (e, a) in expected.iter().zip(actual.iter()) {
+                assert!(f64_eq(e.t, a.t));
+            }
+            assert_eq!(w.color_at(ray, 5), loaded.color_at(ray, 5));
+        }
+    }
+
+    // write the pattern out on a material and read it back
+    fn round_trip(pattern: &dyn Pattern) -> Box<dyn Pattern> {
+        let mut material = Material::default_material();
+        material.pattern = pattern.copy_pattern();
+        let bytes = bincode::serialize(&material).unwrap();
+        bincode::deserialize::<Material>(&bytes).unwrap().pattern
+    }
+
+    fn assert_same_colors(expected: &dyn Pattern, actual: &dyn Pattern) {
+        for i in 0..50 {
+            let point = Tuple::point(i as f64 * 0.17, i as f64 * -0.31, i as f64 * 0.23);
+            assert_eq!(
+                expected.color_at_object(&point, 0.0),
+                actual.color_at_object(&point, 0.0)
+            );
+        }
+    }
+
+    #[test]
+    fn perturbed_patterns_survive_the_cache() {
+        let mut stripes = Stripe::new(Color::white(), Color::black());
+        stripes.set_transform(Matrix::scaling(0.3, 1.0, 1.0));
+        let mut marble = Perturbed::new(Box::new(stripes), 7);
+        marble.scale = 0.6;
+        marble.octaves = 4;
+        marble.set_transform(Matrix::rotation_y(0.4));
+
+        assert_same_colors(&marble, round_trip(&marble).as_ref());
+    }
+
+    #[test]
+    fn blended_patterns_survive_the_cache() {
+        let mut across = Stripe::new(Color::white(), Color::black());
+        across.set_transform(Matrix::rotation_y(std::f64::consts::FRAC_PI_2));
+        let mut plaid = Blend::new(
+            Box::new(Stripe::new(Color::new(1.0, 0.0, 0.0), Color::black())),
+            Box::new(across),
+            0.3,
+        );
+        plaid.set_transform(Matrix::scaling(0.5, 0.5, 0.5));
+
+        assert_same_colors(&plaid, round_trip(&plaid).as_ref());
+    }
+
+    #[test]
+    fn uv_patterns_survive_the_cache() {
+        for map in [spherical_map, planar_map, cylindrical_map] {
+            let mut p = UvPattern::new(
+                map,
+                Box::new(UvCheckers::new(
+                    8,
+                    4,
+                    Color::new(0.2, 0.6, 0.1),
+                    Color::white(),
+                )),
+            );
+            p.set_transform(Matrix::scaling(2.0, 1.0, 2.0));
+            assert_same_colors(&p, round_trip(&p).as_ref());
+        }
+    }
+}
@@ -1,21 +1,42 @@
-use std::{sync::Arc, thread};
+use std::{fs::File, io::Write, sync::Arc, time::Instant};
 
-use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use rayon::{iter::IntoParallelIterator, iter::ParallelIterator, ThreadPoolBuilder};
 
 use crate::{
-    draw::canvas::{stitch_canvases, Canvas},
-    math::{matrix::Matrix, ray::Ray, tuples::Tuple},
+    draw::{canvas::Canvas, color::Color},
+    math::{matrix::Matrix, ray::Ray, rng::Rng, tuples::Tuple},
+    shapes::{
+        bounds::BoundingBox,
+        intersect::{hit, prepare_computations},
+    },
 };
 
-use super::world::World;
+use super::world::{FrozenWorld, World};
+
+// how many reflections and refractions deep a camera ray is followed
+const RECURSION_DEPTH: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    Perspective,  // every ray leaves the eye, distant things look smaller
+    Orthographic, // parallel rays leave the image plane, sizes do not change with distance
+}
+
 pub struct Camera {
     hsize: usize,
     vsize: usize,
     field_of_view: f64,
+    projection: Projection,
     transform: Matrix,
+    inverse_transform: Matrix,
     pixel_size: f64,
     half_width: f64,
     half_height: f64,
+    pub beam_tracing: bool, // give rays the width of a pixel so thin shapes are anti-aliased
+    pub samples: usize, // split each pixel into an n by n grid with a jittered ray in every cell, 1 for a single ray
+    pub aperture: f64, // radius of the lens, zero for a pinhole camera where everything is in focus
+    pub focal_distance: f64, // how far along each ray from the lens things are perfectly sharp
 }
 
 impl Camera {
@@ -26,13 +47,89 @@ impl Camera {
         transform: Matrix,
     ) -> Camera {
         let mut c = Camera::new(hsize, vsize, field_of_view);
-        c.transform = transform;
+        c.set_transform(transform);
         c
     }
 
+    pub fn set_transform(&mut self, transform: Matrix) {
+        self.inverse_transform = transform.inverse();
+        self.transform = transform;
+    }
+
+    /*
+        Point the camera from one point towards another, updating the view
+        transform in place so the camera can be moved between frames
+    */
+    pub fn look_at(&mut self, from: Tuple, to: Tuple, up: Tuple) {
+        self.set_transform(view_transform(from, to, up));
+    }
+
+    /*
+        Back the camera away along its current view direction until the sphere
+        around the box fits inside the narrower of the two fields of view,
+        an empty or unbounded box can not be framed
+    */
+    pub fn frame(&mut self, bounds: &BoundingBox) -> Result<(), String> {
+        if bounds.is_empty() || !bounds.is_finite() {
+            return Err("can only frame a box which is finite and not empty".to_string());
+        }
+
+        let center = bounds.center();
+        let radius = (bounds.max - center).magnitude();
+        let half_view = self.half_width.min(self.half_height).atan();
+        let distance = radius / half_view.sin();
+
+        let forward = (self.look_at_point() - self.eye()).normalize();
+        let up = &self.inverse_transform * &Tuple::vector(0.0, 1.0, 0.0);
+        self.look_at(center - forward * distance, center, up);
+        Ok(())
+    }
+
+    /*
+        A camera looking at the middle of the box from in front and a little above,
+        far enough back that the whole box is in view
+    */
+    pub fn auto_frame(
+        bounds: BoundingBox,
+        hsize: usize,
+        vsize: usize,
+        field_of_view: f64,
+    ) -> Result<Camera, String> {
+        let mut c = Camera::new(hsize, vsize, field_of_view);
+        let center = bounds.center();
+        c.look_at(
+            center + Tuple::vector(0.0, 1.0, -2.0),
+            center,
+            Tuple::vector(0.0, 1.0, 0.0),
+        );
+        c.frame(&bounds)?;
+        Ok(c)
+    }
+
     pub fn new(hsize: usize, vsize: usize, field_of_view: f64) -> Camera {
         // the length of half of the fov
         let half_view = (field_of_view / 2.0).tan();
+        Camera::with_half_view(hsize, vsize, field_of_view, half_view)
+    }
+
+    /*
+        A camera whose rays all travel down its -z axis, the image plane is
+        view_width units across in world space and centered on the eye
+    */
+    pub fn new_orthographic(
+        hsize: usize,
+        vsize: usize,
+        view_width: f64,
+        transform: Matrix,
+    ) -> Camera {
+        let mut c = Camera::with_half_view(hsize, vsize, 0.0, view_width / 2.0);
+        c.projection = Projection::Orthographic;
+        c.set_transform(transform);
+        c
+    }
+
+    // half_view is half the longer side of the image plane one unit in front of the eye
+    fn with_half_view(hsize: usize, vsize: usize, field_of_view: f64, half_view: f64) -> Camera {
         let aspect_ratio = hsize as f64 / vsize as f64;
         let half_width;
         let half_height;
@@ -51,136 +148,501 @@ impl Camera {
             hsize,
             vsize,
             field_of_view,
+            projection: Projection::Perspective,
             transform: Matrix::identity(4),
+            inverse_transform: Matrix::identity(4),
             pixel_size,
             half_width,
             half_height,
+            beam_tracing: false,
+            samples: 1,
+            aperture: 0.0,
+            focal_distance: 1.0,
         }
     }
+
+    pub fn projection(&self) -> Projection {
+        self.projection
+    }
+
+    // where the camera sits in world space
+    pub fn eye(&self) -> Tuple {
+        &self.inverse_transform * &Tuple::point(0.0, 0.0, 0.0)
+    }
+
+    // a point one unit in front of the eye along the view direction
+    pub fn look_at_point(&self) -> Tuple {
+        &self.inverse_transform * &Tuple::point(0.0, 0.0, -1.0)
+    }
+
+    /*
+        Whether any part of a box in world space could fall inside the view frustum,
+        conservative so boxes near the corners of the view are kept
+    */
+    pub fn can_see(&self, bounds: &BoundingBox) -> bool {
+        let view = bounds.transform(&self.transform);
+        if view.is_empty() {
+            return false;
+        }
+        if !view.is_finite() {
+            return true;
+        }
+        // lens rays cross at the focal distance and spread apart again behind it,
+        // so no fixed frustum holds them all and everything has to stay
+        if self.aperture > 0.0 && self.projection == Projection::Perspective {
+            return true;
+        }
+
+        let mut corners = vec![];
+        for &x in &[view.min.x, view.max.x] {
+            for &y in &[view.min.y, view.max.y] {
+                for &z in &[view.min.z, view.max.z] {
+                    corners.push((x, y, z));
+                }
+            }
+        }
+
+        // the camera looks down -z with the canvas one unit in front of it,
+        // the box is hidden if every corner is outside the same side of the frustum
+        let outside =
+            |side: &dyn Fn(f64, f64, f64) -> bool| corners.iter().all(|&(x, y, z)| side(x, y, z));
+        let hw = self.half_width;
+        let hh = self.half_height;
+        if self.projection == Projection::Orthographic {
+            // the view is a box stretching away from the image plane
+            return !(outside(&|_, _, z| z > 0.0)
+                || outside(&|x, _, _| x > hw)
+                || outside(&|x, _, _| x < -hw)
+                || outside(&|_, y, _| y > hh)
+                || outside(&|_, y, _| y < -hh));
+        }
+        !(outside(&|_, _, z| z > 0.0)
+            || outside(&|x, _, z| x + hw * z > 0.0)
+            || outside(&|x, _, z| -x + hw * z > 0.0)
+            || outside(&|_, y, z| y + hh * z > 0.0)
+            || outside(&|_, y, z| -y + hh * z > 0.0))
+    }
+
     /*
         For any pixel in the scene calculate a ray which
         would intersect that pixel
     */
-    fn ray_for_pixel(&self, px: usize, py: usize) -> Ray {
-        // the offset from the edge of the canvas to the center of the pixel we are targeting
-        let x_offset = (px as f64 + 0.5) * self.pixel_size;
-        let y_offset = (py as f64 + 0.5) * self.pixel_size;
+    pub fn ray_for_pixel(&self, px: usize, py: usize) -> Ray {
+        self.ray_through_pixel(px, py, 0.5, 0.5)
+    }
+
+    // a ray through the given fraction of the way across and down the pixel
+    fn ray_through_pixel(&self, px: usize, py: usize, dx: f64, dy: f64) -> Ray {
+        // the offset from the edge of the canvas to the point in the pixel we are targeting
+        let x_offset = (px as f64 + dx) * self.pixel_size;
+        let y_offset = (py as f64 + dy) * self.pixel_size;
 
         // the coordinates of the pixel in world space
         let world_x = self.half_width - x_offset;
         let world_y = self.half_height - y_offset;
 
-        let inv = self.transform.inverse();
+        let inv = &self.inverse_transform;
+
+        if self.projection == Projection::Orthographic {
+            // the ray leaves the pixel itself, parallel to every other ray so a beam never spreads
+            let origin = inv * &Tuple::point(world_x, world_y, 0.0);
+            let direction = (inv * &Tuple::vector(0.0, 0.0, -1.0)).normalize();
+            return Ray::new_unchecked(origin, direction);
+        }
 
-        let pixel = &inv * &Tuple::point(world_x, world_y, -1.0);
-        let origin = &inv * &Tuple::point(0.0, 0.0, 0.0);
+        let pixel = inv * &Tuple::point(world_x, world_y, -1.0);
+        let origin = inv * &Tuple::point(0.0, 0.0, 0.0);
         let direction = (pixel - origin).normalize();
 
-        Ray::new(origin, direction)
+        self.camera_ray(origin, direction)
+    }
+
+    /*
+        A ray through the pixel leaving from a point on the lens, (u, v) in the unit
+        square picks the point on the disk and every ray through the same part of
+        the pixel meets at the focal distance along the ray from the center of the lens
+    */
+    fn ray_through_lens(&self, px: usize, py: usize, dx: f64, dy: f64, u: f64, v: f64) -> Ray {
+        if self.aperture <= 0.0 || self.projection == Projection::Orthographic {
+            return self.ray_through_pixel(px, py, dx, dy);
+        }
+
+        let world_x = self.half_width - (px as f64 + dx) * self.pixel_size;
+        let world_y = self.half_height - (py as f64 + dy) * self.pixel_size;
+        let focus = Tuple::vector(world_x, world_y, -1.0).normalize() * self.focal_distance;
+
+        // the square root spreads the samples evenly over the area of the disk
+        let radius = self.aperture * u.sqrt();
+        let angle = 2.0 * std::f64::consts::PI * v;
+        let lens = Tuple::point(radius * angle.cos(), radius * angle.sin(), 0.0);
+
+        let inv = &self.inverse_transform;
+        let origin = inv * &lens;
+        let focus = inv * &(Tuple::point(0.0, 0.0, 0.0) + focus);
+        let direction = (focus - origin).normalize();
+
+        self.camera_ray(origin, direction)
+    }
+
+    fn camera_ray(&self, origin: Tuple, direction: Tuple) -> Ray {
+        if self.beam_tracing {
+            Ray::new_unchecked(origin, direction).with_width(self.pixel_size / 2.0)
+        } else {
+            Ray::new_unchecked(origin, direction)
+        }
     }
 }
 
 pub fn render(camera: Camera, world: World, thread_count: usize) -> Canvas {
-    assert!(thread_count >= 1);
+    render_shared(camera, Arc::new(world), thread_count)
+}
 
-    let vsize_per_thread = camera.vsize / thread_count;
-    let last_thread_offset = camera.vsize % thread_count;
-    let mut children = vec![];
-
-    let multi_progress_bar = MultiProgress::new();
-    let progress_style = ProgressStyle::default_bar()
-        .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
-        .progress_chars("##-");
-    multi_progress_bar.set_draw_target(ProgressDrawTarget::stdout());
-    let c = Arc::new(camera);
-    let w = Arc::new(world);
-
-    for thread_num in 0..thread_count {
-        let cc = c.clone();
-        let wc = w.clone();
-        let progress_bar = if thread_num < thread_count - 1 {
-            multi_progress_bar.add(ProgressBar::new(vsize_per_thread as u64))
-        } else {
-            multi_progress_bar.add(ProgressBar::new(
-                (vsize_per_thread + last_thread_offset) as u64,
-            ))
+/*
+    Render the world and write a json sidecar next to the image describing
+    the camera, the size of the scene and how long the render took
+*/
+pub fn render_with_metadata(
+    camera: Camera,
+    world: World,
+    thread_count: usize,
+    metadata_file: &str,
+) -> Canvas {
+    let metadata = RenderMetadata {
+        hsize: camera.hsize,
+        vsize: camera.vsize,
+        field_of_view: camera.field_of_view,
+        eye: camera.eye(),
+        look_at: camera.look_at_point(),
+        light_count: world.light_sources.len(),
+        object_count: world.objects.len(),
+        render_seconds: 0.0,
+    };
+
+    let start = Instant::now();
+    let image = render(camera, world, thread_count);
+    let metadata = RenderMetadata {
+        render_seconds: start.elapsed().as_secs_f64(),
+        ..metadata
+    };
+
+    metadata.write_to_json(metadata_file);
+    image
+}
+
+pub struct RenderMetadata {
+    pub hsize: usize,
+    pub vsize: usize,
+    pub field_of_view: f64,
+    pub eye: Tuple,
+    pub look_at: Tuple,
+    pub light_count: usize,
+    pub object_count: usize,
+    pub render_seconds: f64,
+}
+
+// json has no way to write NaN or infinity so those become null
+fn json_number(n: f64) -> String {
+    if n.is_finite() {
+        n.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+impl RenderMetadata {
+    pub fn to_json(&self) -> String {
+        let point = |p: &Tuple| {
+            format!(
+                "[{}, {}, {}]",
+                json_number(p.x),
+                json_number(p.y),
+                json_number(p.z)
+            )
         };
-        progress_bar.set_style(progress_style.clone());
+        format!(
+            concat!(
+                "{{\n",
+                "  \"camera\": {{\n",
+                "    \"hsize\": {},\n",
+                "    \"vsize\": {},\n",
+                "    \"field_of_view\": {},\n",
+                "    \"eye\": {},\n",
+                "    \"look_at\": {}\n",
+                "  }},\n",
+                "  \"lights\": {},\n",
+                "  \"objects\": {},\n",
+                "  \"render_seconds\": {}\n",
+                "}}\n"
+            ),
+            self.hsize,
+            self.vsize,
+            json_number(self.field_of_view),
+            point(&self.eye),
+            point(&self.look_at),
+            self.light_count,
+            self.object_count,
+            json_number(self.render_seconds)
+        )
+    }
+
+    pub fn write_to_json(&self, file_name: &str) {
+        let mut file = File::create(file_name).expect("could not create file");
+        write!(&mut file, "{}", self.to_json()).unwrap();
+    }
+}
+
+/*
+    Render a frozen world, the world is shared rather than consumed
+    so it can be rendered again from another camera
+*/
+pub fn render_frozen(camera: Camera, world: &FrozenWorld, thread_count: usize) -> Canvas {
+    render_shared(camera, world.shared(), thread_count)
+}
+
+/*
+    Render with every random choice drawn from the given seed, the same seed
+    gives the same image no matter how many threads are used
+*/
+pub fn render_seeded(camera: Camera, mut world: World, thread_count: usize, seed: u64) -> Canvas {
+    world.seed = seed;
+    let [image] = render_layers(camera, Arc::new(world), thread_count, BEAUTY);
+    image
+}
+
+fn render_shared(camera: Camera, w: Arc<World>, thread_count: usize) -> Canvas {
+    let [image] = render_layers(camera, w, thread_count, BEAUTY);
+    image
+}
+
+const BEAUTY: Layers<1> = Layers {
+    shade: shade_beauty,
+    averaged: [true],
+};
+
+fn shade_beauty(world: &World, ray: &Ray, culled: &[bool]) -> [Color; 1] {
+    [world.color_at_culled(ray, RECURSION_DEPTH, culled)]
+}
+
+/*
+    Separate images of the same render, produced in a single pass so they
+    line up exactly when composited
+*/
+pub struct Aovs {
+    pub beauty: Canvas,
+    pub normal: Canvas,    // world space normals mapped from [-1, 1] to [0, 1]
+    pub depth: Canvas,     // distance to the hit divided by max_depth, white where nothing was hit
+    pub object_id: Canvas, // a flat color for each object
+    pub ray_depth: Canvas, // surfaces shaded along the deepest bounce path, divided by the recursion limit
+    pub alpha: Canvas, // opacity of each pixel, shadow catchers are only as opaque as their shadows
+    pub max_depth: f64,
+}
 
-        if thread_num == 0 {
-            progress_bar.println(format!("Rendering image on {} thread(s)", thread_count));
+pub fn render_aovs(camera: Camera, world: World, thread_count: usize) -> Aovs {
+    let [beauty, normal, raw_depth, object_id, ray_depth, alpha] =
+        render_layers(camera, Arc::new(world), thread_count, AOVS);
+
+    let mut max_depth: f64 = 0.0;
+    for y in 0..raw_depth.height {
+        for x in 0..raw_depth.width {
+            let t = raw_depth.get_pixel(x, y).red();
+            if t.is_finite() {
+                max_depth = max_depth.max(t);
+            }
         }
+    }
 
-        if thread_num < thread_count - 1 {
-            children.push(thread::spawn(move || {
-                (
-                    render_thread(
-                        cc,
-                        wc,
-                        vsize_per_thread * thread_num,
-                        vsize_per_thread * (thread_num + 1),
-                        thread_num,
-                        progress_bar,
-                    ),
-                    thread_num,
-                )
-            }));
-        } else {
-            // we need to add the offset to the last thread in case the number of threads does not evenly divide the height
-            children.push(thread::spawn(move || {
-                (
-                    render_thread(
-                        cc,
-                        wc,
-                        vsize_per_thread * thread_num,
-                        vsize_per_thread * (thread_num + 1) + last_thread_offset,
-                        thread_num,
-                        progress_bar,
-                    ),
-                    thread_num,
-                )
-            }));
+    let mut depth = Canvas::new(raw_depth.width, raw_depth.height);
+    for y in 0..raw_depth.height {
+        for x in 0..raw_depth.width {
+            let t = raw_depth.get_pixel(x, y).red();
+            let d = if t.is_finite() { t / max_depth } else { 1.0 };
+            depth.write_pixel(x, y, Color::new(d, d, d));
         }
     }
 
-    // must join progress bar handle before threads or nothing will render
-    multi_progress_bar.join().unwrap();
+    Aovs {
+        beauty,
+        normal,
+        depth,
+        object_id,
+        ray_depth,
+        alpha,
+        max_depth,
+    }
+}
+
+// averaging ids, normals or distances over the edge of an object gives meaningless values
+const AOVS: Layers<6> = Layers {
+    shade: shade_aovs,
+    averaged: [true, false, false, false, false, true],
+};
 
-    let mut result = vec![];
-    for child in children {
-        // Wait for the thread to finish. Returns a result.
-        let handle = child.join().unwrap();
-        result.push(handle);
+// the depth layer holds the raw distance until the whole image is known
+fn shade_aovs(world: &World, ray: &Ray, culled: &[bool]) -> [Color; 6] {
+    let intersections = world.intersect_world_culled(ray, culled);
+    match hit(&intersections) {
+        Some(hit) => {
+            let comps = prepare_computations(&hit, ray, &intersections, Some(world));
+            let n = comps.normalv;
+            let bounces = world.hit_depth(&comps, RECURSION_DEPTH) as f64 / RECURSION_DEPTH as f64;
+            let alpha = world.alpha_at(ray);
+            [
+                world.shade_hit(&comps, RECURSION_DEPTH),
+                Color::new((n.x + 1.0) / 2.0, (n.y + 1.0) / 2.0, (n.z + 1.0) / 2.0),
+                Color::new(hit.t, hit.t, hit.t),
+                id_color(hit.shape.get_id()),
+                Color::new(bounces, bounces, bounces),
+                Color::new(alpha, alpha, alpha),
+            ]
+        }
+        None => [
+            world.background_color(ray.direction),
+            Color::black(),
+            Color::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            Color::black(),
+            Color::black(),
+            Color::black(),
+        ],
     }
+}
+
+// scatter ids over the color cube so neighbouring objects are easy to tell apart
+fn id_color(id: usize) -> Color {
+    let h = (id as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    let channel = |shift: u32| ((h >> shift) & 0xff) as f64 / 255.0;
+    Color::new(channel(40), channel(48), channel(56))
+}
+
+type PixelShader<const N: usize> = fn(&World, &Ray, &[bool]) -> [Color; N];
 
-    // stitch the resulting images together
-    result.sort_by(|c1, c2| c1.1.cmp(&c2.1));
-    let mut canvases = vec![];
-    for c in result {
-        canvases.push(c.0);
+/*
+    The images shaded for each pixel, layers which are not averaged over the
+    samples of a pixel are taken from a single ray through its center
+*/
+#[derive(Clone, Copy)]
+struct Layers<const N: usize> {
+    shade: PixelShader<N>,
+    averaged: [bool; N],
+}
+
+/*
+    Shade every row of the image on a pool of threads, each pixel is shaded into
+    N images at once. Rows are handed out one at a time so expensive parts of the
+    image do not hold up the rest. Every random choice is drawn from the world's seed
+*/
+fn render_layers<const N: usize>(
+    camera: Camera,
+    w: Arc<World>,
+    thread_count: usize,
+    layers: Layers<N>,
+) -> [Canvas; N] {
+    assert!(thread_count >= 1);
+    let pool = ThreadPoolBuilder::new()
+        .num_threads(thread_count)
+        .build()
+        .expect("could not start the render threads");
+
+    let progress_bar = ProgressBar::new(camera.vsize as u64);
+    progress_bar.set_style(
+        ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
+            .progress_chars("##-"),
+    );
+    // progress goes to stderr so the image can be written to stdout
+    progress_bar.set_draw_target(ProgressDrawTarget::stderr());
+    progress_bar.println(format!("Rendering image on {} thread(s)", thread_count));
+
+    let culled = w.cull_to_camera(&camera);
+    // collecting keeps the rows in order whichever thread shaded them
+    let rows: Vec<Vec<[Color; N]>> = pool.install(|| {
+        (0..camera.vsize)
+            .into_par_iter()
+            .map(|y| {
+                let row = render_row(&camera, &w, &culled, w.seed, layers, y);
+                progress_bar.inc(1);
+                row
+            })
+            .collect()
+    });
+    progress_bar.finish_with_message("done");
+
+    let mut images: [Canvas; N] = std::array::from_fn(|_| Canvas::new(camera.hsize, camera.vsize));
+    for (y, row) in rows.into_iter().enumerate() {
+        for (x, colors) in row.into_iter().enumerate() {
+            for (image, color) in images.iter_mut().zip(colors) {
+                image.write_pixel(x, y, color);
+            }
+        }
     }
-    stitch_canvases(canvases)
+    images
 }
 
-fn render_thread(
-    camera: Arc<Camera>,
-    world: Arc<World>,
-    thread_y_start: usize,
-    thread_y_end: usize,
-    thread_number: usize,
-    progress_bar: ProgressBar,
-) -> Canvas {
-    let mut image = Canvas::new(camera.hsize, thread_y_end - thread_y_start);
-    for y in thread_y_start..thread_y_end {
-        progress_bar.inc(1);
-        for x in 0..camera.hsize {
-            let ray = camera.ray_for_pixel(x, y);
-            let color = world.color_at(&ray, 5);
-            image.write_pixel(x, y - thread_y_start, color);
+/*
+    Average the rays through every cell of the pixel's grid, with more than one
+    cell each ray passes through a random point in its cell rather than the center
+    and a camera with an aperture picks a random point on the lens for every ray
+*/
+fn shade_pixel_samples<const N: usize>(
+    camera: &Camera,
+    world: &World,
+    culled: &[bool],
+    layers: Layers<N>,
+    (x, y): (usize, usize),
+    mut rng: Rng,
+) -> [Color; N] {
+    let n = camera.samples.max(1);
+
+    let mut total = [Color::black(); N];
+    for cell_y in 0..n {
+        for cell_x in 0..n {
+            let (jx, jy) = if n > 1 {
+                (rng.next_f64(), rng.next_f64())
+            } else {
+                (0.5, 0.5)
+            };
+            let dx = (cell_x as f64 + jx) / n as f64;
+            let dy = (cell_y as f64 + jy) / n as f64;
+            let ray = if camera.aperture > 0.0 {
+                camera.ray_through_lens(x, y, dx, dy, rng.next_f64(), rng.next_f64())
+            } else {
+                camera.ray_through_pixel(x, y, dx, dy)
+            };
+            for (sum, color) in total.iter_mut().zip((layers.shade)(world, &ray, culled)) {
+                *sum += color;
+            }
         }
     }
-    progress_bar.finish_with_message(format!("Thread {} done", thread_number));
-    image
+    let mut colors = total.map(|c| c / (n * n) as f64);
+
+    if layers.averaged.contains(&false) {
+        let center = (layers.shade)(world, &camera.ray_for_pixel(x, y), culled);
+        for ((color, averaged), c) in colors.iter_mut().zip(layers.averaged).zip(center) {
+            if !averaged {
+                *color = c;
+            }
+        }
+    }
+    colors
+}
+
+fn render_row<const N: usize>(
+    camera: &Camera,
+    world: &World,
+    culled: &[bool],
+    seed: u64,
+    layers: Layers<N>,
+    y: usize,
+) -> Vec<[Color; N]> {
+    (0..camera.hsize)
+        .map(|x| {
+            if camera.samples <= 1 && camera.aperture <= 0.0 {
+                (layers.shade)(world, &camera.ray_for_pixel(x, y), culled)
+            } else {
+                let rng = Rng::for_pixel(seed, x, y);
+                shade_pixel_samples(camera, world, culled, layers, (x, y), rng)
+            }
+        })
+        .collect()
 }
 
 /*
@@ -212,7 +674,12 @@ pub fn view_transform(from: Tuple, to: Tuple, up: Tuple) -> Matrix {
 mod test {
     use std::f64::consts::PI;
 
-    use crate::math::utils::f64_eq;
+    use crate::{
+        examples::single_shape_scene,
+        math::utils::{f64_eq, EPSILON},
+        scene::light::AreaLight,
+        shapes::{cylinder::Cylinder, intersect::Intersectable, plane::Plane, sphere::Sphere},
+    };
 
     use super::*;
 
@@ -229,6 +696,108 @@ mod test {
         assert_eq!(r, expected);
     }
 
+    #[test]
+    fn look_at_matches_new_camera() {
+        let from = Tuple::point(1.0, 3.0, 2.0);
+        let to = Tuple::point(4.0, -2.0, 8.0);
+        let up = Tuple::vector(1.0, 1.0, 0.0);
+
+        let mut moved = Camera::new(201, 101, PI / 2.0);
+        moved.look_at(from, to, up);
+        let fresh = Camera::new_with_transform(201, 101, PI / 2.0, view_transform(from, to, up));
+
+        for (x, y) in [(0, 0), (100, 50), (200, 100)] {
+            assert_eq!(moved.ray_for_pixel(x, y), fresh.ray_for_pixel(x, y));
+        }
+    }
+
+    // whether every corner of the box lands on the canvas of the camera
+    fn fits_in_view(c: &Camera, bounds: &BoundingBox) -> bool {
+        let view = bounds.transform(&c.transform);
+        [view.min.x, view.max.x].iter().all(|&x| {
+            [view.min.y, view.max.y].iter().all(|&y| {
+                [view.min.z, view.max.z].iter().all(|&z| {
+                    z < 0.0 && (x / -z).abs() <= c.half_width && (y / -z).abs() <= c.half_height
+                })
+            })
+        })
+    }
+
+    #[test]
+    fn framing_a_box_keeps_the_view_direction() {
+        let bounds = BoundingBox::new(Tuple::point(-1.0, -1.0, -1.0), Tuple::point(6.0, 1.0, 1.0));
+        let mut c = Camera::new(100, 50, PI / 3.0);
+        c.look_at(
+            Tuple::point(0.0, 1.0, 0.0),
+            Tuple::point(0.0, 1.0, 1.0),
+            Tuple::vector(0.0, 1.0, 0.0),
+        );
+        assert!(!fits_in_view(&c, &bounds));
+
+        c.frame(&bounds).unwrap();
+        assert!(fits_in_view(&c, &bounds));
+        // still looking down +z, now straight at the middle of the box
+        let forward = Tuple::vector(0.0, 0.0, 1.0);
+        assert_eq!((c.look_at_point() - c.eye()).normalize(), forward);
+        assert_eq!((bounds.center() - c.eye()).normalize(), forward);
+    }
+
+    #[test]
+    fn auto_framing_a_model() {
+        let bounds = BoundingBox::new(Tuple::point(-3.0, 0.0, -2.0), Tuple::point(5.0, 12.0, 1.0));
+        for (hsize, vsize) in [(200, 100), (100, 200)] {
+            let c = Camera::auto_frame(bounds, hsize, vsize, PI / 3.0).unwrap();
+            assert!(fits_in_view(&c, &bounds));
+
+            // the rays through the corners of the image point further out than any part of the box
+            let to_center = bounds.center() - c.eye();
+            let radius = (bounds.max - bounds.center()).magnitude();
+            let box_angle = (radius / to_center.magnitude()).asin();
+            for (x, y) in [
+                (0, 0),
+                (hsize - 1, 0),
+                (0, vsize - 1),
+                (hsize - 1, vsize - 1),
+            ] {
+                let corner = c.ray_for_pixel(x, y).direction;
+                assert!(corner.angle_between(&to_center) > box_angle);
+            }
+        }
+
+        assert!(Camera::auto_frame(BoundingBox::empty(), 10, 10, PI / 3.0).is_err());
+        assert!(Camera::auto_frame(BoundingBox::infinite(), 10, 10, PI / 3.0).is_err());
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let transform = view_transform(
+            Tuple::point(0.0, 0.0, -5.0),
+            Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0),
+        );
+        let c = Camera::new_orthographic(200, 100, 4.0, transform);
+        assert!(f64_eq(c.pixel_size, 0.02));
+
+        let center = c.ray_for_pixel(100, 50);
+        let corner = c.ray_for_pixel(0, 0);
+        assert_eq!(center.direction, Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(corner.direction, center.direction);
+        assert_eq!(center.origin, Tuple::point(0.01, -0.01, -5.0));
+        assert_eq!(corner.origin, Tuple::point(-1.99, 0.99, -5.0));
+
+        // the camera faces +z so its x axis is mirrored, only what lies inside
+        // the box swept by the image plane is visible
+        let unit = |x: f64, y: f64| {
+            BoundingBox::new(
+                Tuple::point(x - 0.5, y - 0.5, 5.0),
+                Tuple::point(x + 0.5, y + 0.5, 6.0),
+            )
+        };
+        assert!(c.can_see(&unit(2.3, 0.0)));
+        assert!(!c.can_see(&unit(2.6, 0.0)));
+        assert!(!c.can_see(&unit(0.0, -1.6)));
+    }
+
     #[test]
     fn pixel_size_calculated_correctly_horizontal() {
         let c = Camera::new(200, 125, PI / 2.0);
@@ -293,4 +862,279 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn supersampling_averages_a_grid_of_rays() {
+        let scene =
+            || single_shape_scene(Box::new(Sphere::new(Some(Matrix::scaling(1.3, 1.3, 1.3)))));
+        let (camera, world) = scene();
+        let single = render(scene().0, scene().1, 1);
+        for (x, y) in [(0, 0), (5, 5), (2, 8)] {
+            let center = world.color_at(&camera.ray_for_pixel(x, y), RECURSION_DEPTH);
+            assert_eq!(single.get_pixel(x, y), center);
+        }
+
+        // each ray lands somewhere in its own quarter of the pixel, drawn from the render seed
+        let mut supersampled = scene().0;
+        supersampled.samples = 2;
+        let image = render(supersampled, scene().1, 2);
+        for (x, y) in [(0, 0), (5, 5), (2, 8), (3, 2)] {
+            let mut rng = Rng::for_pixel(world.seed, x, y);
+            let mut cells = vec![];
+            for (cell_x, cell_y) in [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)] {
+                let dx = (cell_x + rng.next_f64()) / 2.0;
+                let dy = (cell_y + rng.next_f64()) / 2.0;
+                cells
+                    .push(world.color_at(&camera.ray_through_pixel(x, y, dx, dy), RECURSION_DEPTH));
+            }
+            assert_eq!(image.get_pixel(x, y), Color::average(&cells));
+        }
+    }
+
+    #[test]
+    fn supersampled_aovs_match_the_render() {
+        let scene = || {
+            let (mut camera, world) =
+                single_shape_scene(Box::new(Sphere::new(Some(Matrix::scaling(1.3, 1.3, 1.3)))));
+            camera.samples = 3;
+            (camera, world)
+        };
+        let image = render(scene().0, scene().1, 2);
+        let aovs = render_aovs(scene().0, scene().1, 2);
+        let (camera, world) = scene();
+
+        for y in 0..image.height {
+            for x in 0..image.width {
+                assert_eq!(aovs.beauty.get_pixel(x, y), image.get_pixel(x, y));
+
+                // the other layers come from the center of the pixel, never a blend across an edge
+                let ray = camera.ray_for_pixel(x, y);
+                let d = aovs.depth.get_pixel(x, y).red();
+                match hit(&world.intersect_world(&ray)) {
+                    Some(hit) => assert!(f64_eq(d, hit.t / aovs.max_depth)),
+                    None => assert_eq!(d, 1.0),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn aovs_see_the_background() {
+        let scene = || {
+            let (camera, mut world) = single_shape_scene(Box::new(Sphere::new(None)));
+            world.background = Color::new(0.2, 0.4, 0.9);
+            (camera, world)
+        };
+        let image = render(scene().0, scene().1, 2);
+        let aovs = render_aovs(scene().0, scene().1, 2);
+        assert_eq!(aovs.beauty.get_pixel(0, 0), Color::new(0.2, 0.4, 0.9));
+        for y in 0..image.height {
+            for x in 0..image.width {
+                assert_eq!(aovs.beauty.get_pixel(x, y), image.get_pixel(x, y));
+            }
+        }
+
+        // and the environment map which takes over from it
+        let scene = || {
+            let (camera, mut world) = scene();
+            let mut environment = Canvas::new(8, 4);
+            for y in 0..4 {
+                for x in 0..8 {
+                    environment.write_pixel(x, y, Color::new(x as f64 / 8.0, y as f64 / 4.0, 0.5));
+                }
+            }
+            world.environment = Some(environment);
+            (camera, world)
+        };
+        let image = render(scene().0, scene().1, 2);
+        let aovs = render_aovs(scene().0, scene().1, 2);
+        let corners = [(0, 0), (image.width - 1, image.height - 1)];
+        assert_ne!(
+            image.get_pixel(corners[0].0, corners[0].1),
+            Color::new(0.2, 0.4, 0.9)
+        );
+        assert_ne!(
+            image.get_pixel(corners[0].0, corners[0].1),
+            image.get_pixel(corners[1].0, corners[1].1)
+        );
+        for y in 0..image.height {
+            for x in 0..image.width {
+                assert_eq!(aovs.beauty.get_pixel(x, y), image.get_pixel(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn lens_rays_meet_at_the_focal_distance() {
+        let transform = &Matrix::rotation_y(PI / 4.0) * &Matrix::translation(0.0, -2.0, 5.0);
+        let mut c = Camera::new_with_transform(201, 101, PI / 2.0, transform);
+        let pinhole = c.ray_for_pixel(30, 70);
+        assert_eq!(c.ray_through_lens(30, 70, 0.5, 0.5, 0.9, 0.3), pinhole);
+
+        c.aperture = 0.5;
+        c.focal_distance = 4.0;
+        let focus = pinhole.position(4.0);
+        for (u, v) in [(0.0, 0.0), (1.0, 0.25), (0.3, 0.8)] {
+            let r = c.ray_through_lens(30, 70, 0.5, 0.5, u, v);
+            assert!((r.origin - pinhole.origin).magnitude() <= 0.5 + EPSILON);
+            let t = (focus - r.origin).magnitude();
+            assert_eq!(r.position(t), focus);
+        }
+        assert_ne!(
+            c.ray_through_lens(30, 70, 0.5, 0.5, 1.0, 0.25).origin,
+            pinhole.origin
+        );
+    }
+
+    #[test]
+    fn metadata_writes_non_finite_numbers_as_null() {
+        let metadata = RenderMetadata {
+            hsize: 10,
+            vsize: 5,
+            field_of_view: f64::NAN,
+            eye: Tuple::point(f64::INFINITY, 0.5, -5.0),
+            look_at: Tuple::point(0.0, 0.0, 0.0),
+            light_count: 1,
+            object_count: 2,
+            render_seconds: 1.5,
+        };
+        let json = metadata.to_json();
+        assert!(json.contains("\"field_of_view\": null,"));
+        assert!(json.contains("\"eye\": [null, 0.5, -5],"));
+        assert!(json.contains("\"render_seconds\": 1.5"));
+        assert!(!json.contains("NaN") && !json.contains("inf"));
+    }
+
+    #[test]
+    fn lens_cameras_see_past_the_pinhole_frustum() {
+        let mut c = Camera::new(100, 50, PI / 2.0);
+        let sphere = Sphere::new(Some(Matrix::translation(0.0, 4.5, -5.0)));
+        assert!(!c.can_see(&sphere.parent_space_bounds()));
+
+        // a ray from the bottom of the lens through the top row climbs into the sphere
+        c.aperture = 0.5;
+        c.focal_distance = 1.0;
+        let r = c.ray_through_lens(50, 0, 0.5, 0.5, 1.0, 0.75);
+        assert!(!sphere.intersect(&r).is_empty());
+        assert!(c.can_see(&sphere.parent_space_bounds()));
+    }
+
+    #[test]
+    fn beam_tracing_catches_wires_between_pixel_centers() {
+        // a wire far thinner than a pixel, off to the side of the middle pixel's center
+        let middle_row = |beam_tracing: bool| -> Vec<Color> {
+            let wire = Cylinder::new(Some(
+                &Matrix::translation(0.2, 0.0, 0.0) * &Matrix::scaling(0.01, 1.0, 0.01),
+            ));
+            let (mut camera, world) = single_shape_scene(Box::new(wire));
+            camera.beam_tracing = beam_tracing;
+            let image = render(camera, world, 1);
+            (0..image.width).map(|x| image.get_pixel(x, 5)).collect()
+        };
+
+        // every pixel ray misses the wire
+        assert!(middle_row(false).iter().all(|&c| c == Color::black()));
+        // but a ray as wide as its pixel still sees part of it
+        assert!(middle_row(true).iter().any(|&c| c != Color::black()));
+    }
+
+    #[test]
+    fn depth_of_field_is_seeded() {
+        let scene = || {
+            single_shape_scene(Box::new(Sphere::new(Some(Matrix::translation(
+                0.0, 0.0, 3.0,
+            )))))
+        };
+        let render_with = |aperture: f64, seed: u64| {
+            let (mut camera, world) = scene();
+            camera.aperture = aperture;
+            camera.focal_distance = 2.0;
+            render_seeded(camera, world, 2, seed)
+        };
+
+        // a closed aperture is exactly the pinhole camera
+        let pinhole = render_with(0.0, 1);
+        assert!(pinhole == render(scene().0, scene().1, 1));
+
+        let blurred = render_with(0.3, 1);
+        assert!(blurred == render_with(0.3, 1));
+        assert!(blurred != pinhole);
+    }
+
+    #[test]
+    fn every_row_is_rendered_once() {
+        let scene = || {
+            let (camera, world) = single_shape_scene(Box::new(Sphere::new(None)));
+            let camera = Camera::new_with_transform(10, 10, PI / 3.0, camera.transform.clone());
+            (camera, world)
+        };
+        let split = render(scene().0, scene().1, 3);
+        let whole = render(scene().0, scene().1, 1);
+        assert_eq!((split.width, split.height), (10, 10));
+        for y in 0..10 {
+            for x in 0..10 {
+                assert_eq!(split.get_pixel(x, y), whole.get_pixel(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn more_threads_than_rows() {
+        let scene = || {
+            let (camera, world) = single_shape_scene(Box::new(Sphere::new(None)));
+            let camera = Camera::new_with_transform(6, 4, PI / 3.0, camera.transform.clone());
+            (camera, world)
+        };
+        let split = render(scene().0, scene().1, 16);
+        let whole = render(scene().0, scene().1, 1);
+        assert_eq!((split.width, split.height), (6, 4));
+        for y in 0..4 {
+            for x in 0..6 {
+                assert_eq!(split.get_pixel(x, y), whole.get_pixel(x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn seeded_renders_are_reproducible() {
+        let render_with = |threads: usize, seed: u64| {
+            let (mut camera, world) =
+                single_shape_scene(Box::new(Sphere::new(Some(Matrix::scaling(1.3, 1.3, 1.3)))));
+            camera.samples = 4;
+            render_seeded(camera, world, threads, seed)
+        };
+
+        let first = render_with(1, 7);
+        assert!(first == render_with(1, 7));
+        assert!(first == render_with(3, 7));
+        // only the pixels along the silhouette change with the jitter
+        assert!(first != render_with(1, 8));
+    }
+
+    #[test]
+    fn area_light_jitter_follows_the_render_seed() {
+        let render_with = |seed: u64| {
+            let (camera, mut world) = single_shape_scene(Box::new(Sphere::new(None)));
+            // a wall behind the sphere for its soft shadow to fall on
+            world.objects.push(Box::new(Plane::new(Some(
+                &Matrix::translation(0.0, 0.0, 3.0) * &Matrix::rotation_x(PI / 2.0),
+            ))));
+            let mut light = AreaLight::new(
+                Color::white(),
+                Tuple::point(-2.0, -2.0, -6.0),
+                Tuple::vector(4.0, 0.0, 0.0),
+                4,
+                Tuple::vector(0.0, 4.0, 0.0),
+                4,
+            );
+            light.jitter = true;
+            world.light_sources = vec![Box::new(light)];
+            render_seeded(camera, world, 2, seed)
+        };
+
+        // one ray per pixel through a pinhole, so only the light samples move
+        let first = render_with(1);
+        assert!(first == render_with(1));
+        assert!(first != render_with(2));
+    }
 }
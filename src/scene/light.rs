@@ -1,41 +1,72 @@
+use std::any::Any;
+
 use crate::{
     draw::{color::Color, material::Material},
-    math::tuples::Tuple,
+    math::{rng::Rng, tuples::Tuple},
 };
 
-pub struct PointLight {
-    intensity: Color,
-    pub position: Tuple,
-}
+/*
+    Anything which lights the scene, a light is seen from a point on a surface
+    through the direction it arrives from and how far away it is
+*/
+pub trait Light: Sync + Send {
+    fn intensity(&self) -> Color;
+    fn is_enabled(&self) -> bool;
+    fn set_enabled(&mut self, enabled: bool);
+    fn direction_from(&self, point: Tuple) -> Tuple; // unit vector from the point towards the light
+    fn distance_from(&self, point: Tuple) -> f64; // infinite for lights without a position
+    fn as_any(&self) -> &dyn Any;
 
-impl PointLight {
-    pub fn new(intensity: Color, position: Tuple) -> PointLight {
-        assert!(position.is_point());
-        PointLight {
-            intensity,
-            position,
-        }
+    // the share of the intensity which reaches the point, lights that shine evenly in every direction reach all of it
+    fn falloff(&self, _point: Tuple) -> f64 {
+        1.0
     }
 
     /*
-        Implementation of the Phong reflection model
+        The directions shadow rays are cast in from a point, each paired with how far
+        the light is along it, lights spread over an area return one for each sample.
+        Any random placement of the samples is drawn from the seed of the render
     */
-    pub fn lighting(
+    fn shadow_samples(&self, point: Tuple, _seed: u64) -> Vec<(Tuple, f64)> {
+        vec![(self.direction_from(point), self.distance_from(point))]
+    }
+
+    /*
+        Implementation of the Phong reflection model, in_shadow is how much of the
+        light is blocked from 0.0 for fully lit to 1.0 for fully shadowed
+    */
+    fn lighting(
         &self,
         material: &Material,
         position: Tuple,
         eyev: Tuple,
         normalv: Tuple,
-        is_shadow: bool,
+        in_shadow: f64,
         object_point: Tuple,
+    ) -> Color {
+        // the color of the pattern as it falls on the shape
+        let surface_color = material.pattern.color_at_object(&object_point, 0.0);
+        self.lighting_with_color(material, surface_color, position, eyev, normalv, in_shadow)
+    }
+
+    /*
+        The Phong reflection model for a surface whose color is already known,
+        used when the shape supplies its own color in place of the material pattern
+    */
+    fn lighting_with_color(
+        &self,
+        material: &Material,
+        surface_color: Color,
+        position: Tuple,
+        eyev: Tuple,
+        normalv: Tuple,
+        in_shadow: f64,
     ) -> Color {
         // combine the surface color with the lights color/intensity
-        // first convert to pattern space so we can get the color as it falls on the pattern
-        let pattern_point = material.pattern.inverse_transform() * &object_point;
-        let effective_color = material.pattern.color_at(&pattern_point) * self.intensity;
+        let effective_color = surface_color * self.intensity();
 
         // find the direction to the light source
-        let lightv = (self.position - position).normalize();
+        let lightv = self.direction_from(position);
 
         /*
            Compute the ambient contribution which is light from other objects
@@ -48,17 +79,25 @@ impl PointLight {
             light vector and the normal vector. A negative number means the
             light is on the other side of the surface
         */
-        let light_dot_normal = lightv.dot(&normalv);
+        let mut light_dot_normal = lightv.dot(&normalv);
+        // a double sided surface takes light on either face, mirroring the normal
+        // leaves the reflection of the light the same so specular needs no change
+        if material.double_sided {
+            light_dot_normal = light_dot_normal.abs();
+        }
         let diffuse;
         let specular;
 
-        if is_shadow || light_dot_normal < 0.0 {
+        // the share of the light which makes it past anything between the source and the surface
+        let visible = (1.0 - in_shadow.clamp(0.0, 1.0)) * self.falloff(position);
+
+        if visible <= 0.0 || light_dot_normal < 0.0 {
             // light is behind shape or there is another object between it and the source, no contribution to final color
             diffuse = Color::black();
             specular = Color::black();
         } else {
             // compute the diffuse contribution, the light spreading over the surface
-            diffuse = effective_color * material.diffuse * light_dot_normal;
+            diffuse = effective_color * material.diffuse * light_dot_normal * visible;
 
             /*
                 reflect_dot_eye represents the cosine of th angle between the
@@ -76,7 +115,7 @@ impl PointLight {
                     reflection on the shape from the light itself
                 */
                 let factor = reflect_dot_eye.powf(material.shininess);
-                specular = self.intensity * material.specular * factor;
+                specular = self.intensity() * material.specular * factor * visible;
             }
         }
 
@@ -85,13 +124,329 @@ impl PointLight {
     }
 }
 
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone)]
+pub struct PointLight {
+    intensity: Color,
+    pub position: Tuple,
+    pub enabled: bool, // a disabled light only contributes its ambient term, as if everything was in its shadow
+    pub attenuation: (f64, f64, f64), // constant, linear and quadratic terms of the distance the light is divided by
+}
+
+impl PointLight {
+    pub fn new(intensity: Color, position: Tuple) -> PointLight {
+        assert!(position.is_point());
+        PointLight {
+            intensity,
+            position,
+            enabled: true,
+            attenuation: (1.0, 0.0, 0.0),
+        }
+    }
+
+    /*
+        Fade the light with distance d, the diffuse and specular terms are
+        divided by constant + linear * d + quadratic * d * d
+    */
+    pub fn with_attenuation(mut self, constant: f64, linear: f64, quadratic: f64) -> PointLight {
+        self.attenuation = (constant, linear, quadratic);
+        self
+    }
+}
+
+impl Light for PointLight {
+    fn intensity(&self) -> Color {
+        self.intensity
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn direction_from(&self, point: Tuple) -> Tuple {
+        (self.position - point).normalize()
+    }
+
+    fn distance_from(&self, point: Tuple) -> f64 {
+        (self.position - point).magnitude()
+    }
+
+    fn falloff(&self, point: Tuple) -> f64 {
+        let (constant, linear, quadratic) = self.attenuation;
+        if (constant, linear, quadratic) == (1.0, 0.0, 0.0) {
+            return 1.0;
+        }
+        let d = self.distance_from(point);
+        1.0 / (constant + linear * d + quadratic * d * d)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/*
+    Parallel light from very far away such as the sun, it has no position so it
+    lights every point from the same direction and shadow rays never reach it
+*/
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone)]
+pub struct DirectionalLight {
+    intensity: Color,
+    pub direction: Tuple, // the way the light travels, from the sky towards the scene
+    pub enabled: bool,
+}
+
+impl DirectionalLight {
+    pub fn new(intensity: Color, direction: Tuple) -> DirectionalLight {
+        assert!(direction.is_vector());
+        DirectionalLight {
+            intensity,
+            direction: direction.normalize(),
+            enabled: true,
+        }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn intensity(&self) -> Color {
+        self.intensity
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn direction_from(&self, _point: Tuple) -> Tuple {
+        -self.direction
+    }
+
+    fn distance_from(&self, _point: Tuple) -> f64 {
+        f64::INFINITY
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/*
+    A point light which only shines inside a cone, full strength within inner_angle
+    of its axis and fading smoothly to nothing at outer_angle, both in radians
+*/
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone)]
+pub struct SpotLight {
+    intensity: Color,
+    pub position: Tuple,
+    pub direction: Tuple, // the axis of the cone, the way the light is pointing
+    pub inner_angle: f64,
+    pub outer_angle: f64,
+    pub enabled: bool,
+}
+
+impl SpotLight {
+    pub fn new(
+        intensity: Color,
+        position: Tuple,
+        direction: Tuple,
+        inner_angle: f64,
+        outer_angle: f64,
+    ) -> SpotLight {
+        assert!(position.is_point());
+        assert!(direction.is_vector());
+        assert!(0.0 <= inner_angle && inner_angle <= outer_angle);
+        SpotLight {
+            intensity,
+            position,
+            direction: direction.normalize(),
+            inner_angle,
+            outer_angle,
+            enabled: true,
+        }
+    }
+}
+
+impl Light for SpotLight {
+    fn intensity(&self) -> Color {
+        self.intensity
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn direction_from(&self, point: Tuple) -> Tuple {
+        (self.position - point).normalize()
+    }
+
+    fn distance_from(&self, point: Tuple) -> f64 {
+        (self.position - point).magnitude()
+    }
+
+    fn falloff(&self, point: Tuple) -> f64 {
+        let cos = (point - self.position)
+            .normalize()
+            .dot(&self.direction)
+            .clamp(-1.0, 1.0);
+        let angle = cos.acos();
+        if angle <= self.inner_angle {
+            1.0
+        } else if angle >= self.outer_angle {
+            0.0
+        } else {
+            // smoothstep so the edge of the pool of light has no visible ring
+            let t = (self.outer_angle - angle) / (self.outer_angle - self.inner_angle);
+            t * t * (3.0 - 2.0 * t)
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/*
+    A flat rectangle of light spanning corner to corner + uvec + vvec, split into
+    a usteps by vsteps grid with a shadow ray cast to each cell so the edges of
+    shadows are soft. Shading treats it as a point light at its center
+*/
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone)]
+pub struct AreaLight {
+    intensity: Color,
+    pub corner: Tuple,
+    pub uvec: Tuple, // the full length of one edge
+    pub vvec: Tuple,
+    pub usteps: usize,
+    pub vsteps: usize,
+    pub jitter: bool, // place each sample randomly in its cell using the render seed, false samples the centers
+    pub enabled: bool,
+}
+
+impl AreaLight {
+    pub fn new(
+        intensity: Color,
+        corner: Tuple,
+        uvec: Tuple,
+        usteps: usize,
+        vvec: Tuple,
+        vsteps: usize,
+    ) -> AreaLight {
+        assert!(corner.is_point());
+        assert!(uvec.is_vector() && vvec.is_vector());
+        assert!(usteps > 0 && vsteps > 0);
+        AreaLight {
+            intensity,
+            corner,
+            uvec,
+            vvec,
+            usteps,
+            vsteps,
+            jitter: false,
+            enabled: true,
+        }
+    }
+
+    pub fn center(&self) -> Tuple {
+        self.corner + self.uvec * 0.5 + self.vvec * 0.5
+    }
+
+    /*
+        The point on the light in cell (u, v), the same shaded point always draws
+        the same jitter so a seeded render gives the same image every time
+    */
+    fn sample_points(&self, point: Tuple, seed: u64) -> Vec<Tuple> {
+        let mut rng = self.jitter.then(|| {
+            let mut rng = Rng::new(
+                seed ^ point.x.to_bits()
+                    ^ point.y.to_bits().rotate_left(21)
+                    ^ point.z.to_bits().rotate_left(42),
+            );
+            rng.next_u64();
+            rng
+        });
+
+        let mut points = Vec::with_capacity(self.usteps * self.vsteps);
+        for v in 0..self.vsteps {
+            for u in 0..self.usteps {
+                let (ju, jv) = match rng.as_mut() {
+                    Some(rng) => (rng.next_f64(), rng.next_f64()),
+                    None => (0.5, 0.5),
+                };
+                points.push(
+                    self.corner
+                        + self.uvec * ((u as f64 + ju) / self.usteps as f64)
+                        + self.vvec * ((v as f64 + jv) / self.vsteps as f64),
+                );
+            }
+        }
+        points
+    }
+}
+
+impl Light for AreaLight {
+    fn intensity(&self) -> Color {
+        self.intensity
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    fn direction_from(&self, point: Tuple) -> Tuple {
+        (self.center() - point).normalize()
+    }
+
+    fn distance_from(&self, point: Tuple) -> f64 {
+        (self.center() - point).magnitude()
+    }
+
+    fn shadow_samples(&self, point: Tuple, seed: u64) -> Vec<(Tuple, f64)> {
+        self.sample_points(point, seed)
+            .into_iter()
+            .map(|p| {
+                let to_light = p - point;
+                (to_light.normalize(), to_light.magnitude())
+            })
+            .collect()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use std::f64::consts::PI;
 
-    
+    use crate::math::utils::f64_eq;
 
     use super::*;
 
+    #[test]
+    fn reading_light_intensity() {
+        let light = PointLight::new(Color::new(0.5, 0.25, 1.0), Tuple::point(0.0, 0.0, 0.0));
+        assert_eq!(light.intensity(), Color::new(0.5, 0.25, 1.0));
+    }
+
     #[test]
     fn eye_between_light_and_surface() {
         let position = Tuple::point(0.0, 0.0, 0.0);
@@ -100,7 +455,7 @@ mod test {
         let eyev = Tuple::vector(0.0, 0.0, -1.0);
         let normalv = Tuple::vector(0.0, 0.0, -1.0);
         let light = PointLight::new(Color::new(1.0, 1.0, 1.0), Tuple::point(0.0, 0.0, -10.0));
-        let res = light.lighting(&m, position, eyev, normalv, false, normalv);
+        let res = light.lighting(&m, position, eyev, normalv, 0.0, normalv);
         assert!(res == Color::new(1.9, 1.9, 1.9));
     }
 
@@ -112,7 +467,7 @@ mod test {
         let eyev = Tuple::vector(0.0, (2.0_f64).sqrt() / 2.0, (2.0_f64).sqrt() / -2.0);
         let normalv = Tuple::vector(0.0, 0.0, -1.0);
         let light = PointLight::new(Color::new(1.0, 1.0, 1.0), Tuple::point(0.0, 0.0, -10.0));
-        let res = light.lighting(&m, position, eyev, normalv, false, normalv);
+        let res = light.lighting(&m, position, eyev, normalv, 0.0, normalv);
         assert!(res == Color::new(1.0, 1.0, 1.0));
     }
 
@@ -124,7 +479,7 @@ mod test {
         let eyev = Tuple::vector(0.0, 0.0, -1.0);
         let normalv = Tuple::vector(0.0, 0.0, -1.0);
         let light = PointLight::new(Color::new(1.0, 1.0, 1.0), Tuple::point(0.0, 10.0, -10.0));
-        let res = light.lighting(&m, position, eyev, normalv, false, normalv);
+        let res = light.lighting(&m, position, eyev, normalv, 0.0, normalv);
         assert!(res == Color::new(0.7364, 0.7364, 0.7364));
     }
 
@@ -136,7 +491,103 @@ mod test {
         let eyev = Tuple::vector(0.0, 0.0, -1.0);
         let normalv = Tuple::vector(0.0, 0.0, -1.0);
         let light = PointLight::new(Color::new(1.0, 1.0, 1.0), Tuple::point(0.0, 0.0, -10.0));
-        let res = light.lighting(&m, position, eyev, normalv, true, normalv);
+        let res = light.lighting(&m, position, eyev, normalv, 1.0, normalv);
         assert!(res == Color::new(0.1, 0.1, 0.1));
     }
+
+    #[test]
+    fn lighting_partially_shadowed() {
+        let position = Tuple::point(0.0, 0.0, 0.0);
+        let m = Material::default_material();
+
+        let eyev = Tuple::vector(0.0, 0.0, -1.0);
+        let normalv = Tuple::vector(0.0, 0.0, -1.0);
+        let light = PointLight::new(Color::new(1.0, 1.0, 1.0), Tuple::point(0.0, 0.0, -10.0));
+
+        // ambient 0.1 is untouched, diffuse 0.9 and specular 0.9 are halved
+        let res = light.lighting(&m, position, eyev, normalv, 0.5, normalv);
+        assert!(res == Color::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn sunlight_is_the_same_everywhere() {
+        let m = Material::default_material();
+        let sun = DirectionalLight::new(Color::white(), Tuple::vector(0.0, -2.0, 0.0));
+        let up = Tuple::vector(0.0, 1.0, 0.0);
+
+        for position in [
+            Tuple::point(0.0, 0.0, 0.0),
+            Tuple::point(500.0, -30.0, 12.0),
+            Tuple::point(-1e5, 1e5, 1e5),
+        ] {
+            // facing the sun gets the full diffuse and specular terms
+            let lit = sun.lighting(&m, position, up, up, 0.0, position);
+            assert_eq!(lit, Color::new(1.9, 1.9, 1.9));
+
+            // facing away only the ambient term is left
+            let away = sun.lighting(&m, position, -up, -up, 0.0, position);
+            assert_eq!(away, Color::new(0.1, 0.1, 0.1));
+
+            assert_eq!(sun.direction_from(position), up);
+            assert_eq!(sun.distance_from(position), f64::INFINITY);
+        }
+    }
+
+    #[test]
+    fn spotlights_only_light_their_cone() {
+        let m = Material::default_material();
+        let spot = SpotLight::new(
+            Color::white(),
+            Tuple::point(0.0, 10.0, 0.0),
+            Tuple::vector(0.0, -1.0, 0.0),
+            PI / 8.0,
+            PI / 4.0,
+        );
+        let up = Tuple::vector(0.0, 1.0, 0.0);
+        let lit = |x: f64| {
+            let position = Tuple::point(x, 0.0, 0.0);
+            let eyev = spot.direction_from(position);
+            spot.lighting(&m, position, eyev, up, 0.0, position)
+        };
+
+        // on the axis the light is exactly a point light
+        let point = PointLight::new(Color::white(), spot.position);
+        let p = Tuple::point(0.0, 0.0, 0.0);
+        assert_eq!(lit(0.0), point.lighting(&m, p, up, up, 0.0, p));
+        assert_eq!(lit(0.0), Color::new(1.9, 1.9, 1.9));
+
+        // inside the inner angle nothing is lost, past the outer angle only ambient is left
+        assert_eq!(spot.falloff(Tuple::point(4.0, 0.0, 0.0)), 1.0);
+        assert_eq!(lit(10.5), Color::new(0.1, 0.1, 0.1));
+        assert_eq!(lit(50.0), Color::new(0.1, 0.1, 0.1));
+
+        // halfway between the two angles half of the light gets through
+        let halfway = 10.0 * (3.0 * PI / 16.0).tan();
+        assert!(f64_eq(spot.falloff(Tuple::point(halfway, 0.0, 0.0)), 0.5));
+        let between = lit(7.0);
+        assert!(between.red() > 0.1 && between.red() < lit(4.0).red());
+    }
+
+    #[test]
+    fn point_lights_fade_with_distance() {
+        let m = Material::default_material();
+        let up = Tuple::vector(0.0, 1.0, 0.0);
+        let lit = |light: &PointLight, height: f64| {
+            let position = Tuple::point(0.0, -height, 0.0);
+            light.lighting(&m, position, up, up, 0.0, position)
+        };
+
+        // without attenuation the distance makes no difference
+        let plain = PointLight::new(Color::white(), Tuple::point(0.0, 0.0, 0.0));
+        assert_eq!(lit(&plain, 2.0), lit(&plain, 200.0));
+
+        let faded = plain.clone().with_attenuation(1.0, 0.5, 0.25);
+        // 1 + 0.5 * 2 + 0.25 * 4 = 3, the ambient term is left alone
+        assert_eq!(
+            lit(&faded, 2.0),
+            Color::new(0.1 + 1.8 / 3.0, 0.1 + 1.8 / 3.0, 0.1 + 1.8 / 3.0)
+        );
+        assert!(lit(&faded, 200.0).red() < lit(&faded, 2.0).red());
+        assert!(f64_eq(lit(&faded, 200.0).red(), 0.1 + 1.8 / 10101.0));
+    }
 }
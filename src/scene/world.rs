@@ -1,14 +1,64 @@
+use std::{collections::HashMap, f64::consts::PI, ops::Deref, sync::Arc};
+
 use crate::{
-    draw::color::Color,
-    math::{ray::Ray, tuples::Tuple, utils::f64_eq},
-    shapes::intersect::{hit, prepare_computations, Computations, Intersectable, Intersection},
+    draw::{canvas::Canvas, color::Color, material::Material},
+    math::{
+        ray::Ray,
+        tuples::Tuple,
+        utils::{f64_eq, EPSILON},
+    },
+    shapes::{
+        bounds::BoundingBox,
+        group::{Group, SplitMethod},
+        intersect::{hit, prepare_computations, Computations, Intersectable, Intersection},
+    },
 };
 
-use super::light::PointLight;
+use super::{camera::Camera, light::Light};
 
 pub struct World {
     pub objects: Vec<Box<dyn Intersectable>>,
-    pub light_sources: Vec<PointLight>,
+    pub light_sources: Vec<Box<dyn Light>>,
+    pub clamp_lighting: bool, // cap the summed contribution of all lights at white
+    pub surface_offset: f64, // how far over and under points sit from a surface, should grow with the scene scale
+    pub ambient_occlusion: Option<AoConfig>, // darken the ambient term where nearby geometry blocks the sky
+    pub min_importance: f64, // secondary rays contributing less than this fraction of a pixel are not traced
+    pub background: Color,   // seen by any ray which misses everything
+    pub environment: Option<Canvas>, // an equirectangular image around the scene, seen in place of the background
+    pub ambient_color: Color, // tints the ambient term of every light, white leaves it as the light's own color
+    pub seed: u64, // every random choice in a render is drawn from this, the same seed gives the same image
+    group_paths: HashMap<usize, Vec<usize>>, // child indices leading to each group, only filled in once frozen
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AoConfig {
+    pub samples: usize, // rays cast over the hemisphere at each hit
+    pub radius: f64,    // geometry further away than this does not occlude
+}
+
+// groups with at least this many children are split when a world is frozen
+const BVH_THRESHOLD: usize = 4;
+
+/*
+    A world whose geometry can no longer change, so the bounding volume hierarchy
+    and the lookup of parent groups are built once and stay valid for every render
+*/
+pub struct FrozenWorld {
+    world: Arc<World>,
+}
+
+impl FrozenWorld {
+    pub fn shared(&self) -> Arc<World> {
+        self.world.clone()
+    }
+}
+
+impl Deref for FrozenWorld {
+    type Target = World;
+
+    fn deref(&self) -> &World {
+        &self.world
+    }
 }
 
 impl World {
@@ -16,13 +66,80 @@ impl World {
         World {
             objects: vec![],
             light_sources: vec![],
+            clamp_lighting: false,
+            surface_offset: EPSILON,
+            ambient_occlusion: None,
+            min_importance: 0.0,
+            background: Color::black(),
+            environment: None,
+            ambient_color: Color::white(),
+            seed: 0,
+            group_paths: HashMap::new(),
         }
     }
 
-    pub fn intersect_world(&self, ray: &Ray) -> Vec<Intersection> {
+    pub fn freeze(mut self) -> FrozenWorld {
+        for o in self.objects.iter_mut() {
+            o.subdivide(BVH_THRESHOLD, SplitMethod::SurfaceAreaHeuristic);
+        }
+
+        let mut group_paths = HashMap::new();
+        for (i, o) in self.objects.iter().enumerate() {
+            index_groups(o.as_ref(), vec![i], &mut group_paths);
+        }
+        self.group_paths = group_paths;
+
+        FrozenWorld {
+            world: Arc::new(self),
+        }
+    }
+
+    // switch a light off or back on without rebuilding the world
+    pub fn set_light_enabled(&mut self, index: usize, enabled: bool) {
+        self.light_sources[index].set_enabled(enabled);
+    }
+
+    pub fn remove_light(&mut self, index: usize) -> Box<dyn Light> {
+        self.light_sources.remove(index)
+    }
+
+    /*
+        The box around every top level object in world space, objects without
+        finite bounds such as planes are left out so the result can frame the scene
+    */
+    pub fn bounds(&self) -> BoundingBox {
+        let mut bounds = BoundingBox::empty();
+        for o in &self.objects {
+            let b = o.parent_space_bounds();
+            if b.is_finite() {
+                bounds.merge(&b);
+            }
+        }
+        bounds
+    }
+
+    pub fn intersect_world(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        self.intersect_world_culled(ray, &[])
+    }
+
+    /*
+        Mark the objects whose bounds fall entirely outside of the camera's view,
+        only primary rays may skip these since shadows and reflections can still reach them
+    */
+    pub fn cull_to_camera(&self, camera: &Camera) -> Vec<bool> {
+        self.objects
+            .iter()
+            .map(|o| !camera.can_see(&o.parent_space_bounds()))
+            .collect()
+    }
+
+    pub fn intersect_world_culled(&self, ray: &Ray, culled: &[bool]) -> Vec<Intersection<'_>> {
         let mut intersections = vec![];
 
-        for s in &self.objects {
+        for (i, s) in self.objects.iter().enumerate() {
+            if culled.get(i).copied().unwrap_or(false) {
+                continue;
+            }
             intersections.append(&mut s.intersect(ray));
         }
 
@@ -32,108 +149,399 @@ impl World {
     }
 
     pub fn shade_hit(&self, comps: &Computations, depth: usize) -> Color {
+        self.shade_hit_weighted(comps, depth, 1.0)
+    }
+
+    /*
+        Shade a hit whose color is scaled by throughput before it reaches the pixel,
+        reflections and refractions carry their share of it so dim ones can be dropped
+    */
+    fn shade_hit_weighted(&self, comps: &Computations, depth: usize, throughput: f64) -> Color {
         // its possible for a perfectly reflected ray to bounce forever
         // need to terminate it once we hit a certain depth
         if depth == 0 {
             return Color::black();
         }
 
+        let mut material = comps.object.get_material();
+
+        // only the shadow is drawn, darkening whatever is seen through the surface
+        if material.shadow_catcher {
+            let shadow = self.catcher_shadow(comps);
+            let behind = Ray::new_unchecked(comps.under_point, -comps.eyev);
+            return self.trace(&behind, depth, &[], throughput * (1.0 - shadow)) * (1.0 - shadow);
+        }
+
         let mut surface = Color::black();
 
+        // the ambient term stands in for light arriving from everywhere, so it is scaled by how much of that is blocked
+        let occluded_material;
+        if let Some(config) = self.ambient_occlusion {
+            let occlusion = self.occlusion_at(comps.over_point, comps.normalv, config);
+            let mut m = Material::from_material(material);
+            m.ambient *= 1.0 - occlusion;
+            occluded_material = m;
+            material = &occluded_material;
+        }
+
+        // a tinted ambient term is added here, so the lights only give the diffuse and specular terms
+        let ambient = material.ambient;
+        let tinted_ambient = self.ambient_color != Color::white();
+        let unlit_material;
+        if tinted_ambient {
+            let mut m = Material::from_material(material);
+            m.ambient = 0.0;
+            unlit_material = m;
+            material = &unlit_material;
+        }
+
+        // the same for every light, so the pattern is only looked up once
+        let surface_color = match comps.surface_color {
+            Some(color) => color,
+            None => {
+                let object_point = comps.object.world_to_object(comps.over_point, self);
+                let radius = self.footprint_in_object_space(comps, object_point);
+                material.pattern.color_at_object(&object_point, radius)
+            }
+        };
+
         for light in &self.light_sources {
-            surface += light.lighting(
-                comps.object.get_material(),
-                comps.over_point,
+            if tinted_ambient {
+                surface += surface_color * light.intensity() * self.ambient_color * ambient;
+            }
+
+            // a double sided surface can be lit from behind, the shadow ray then has to
+            // start on the far side so it does not hit the surface itself
+            let behind = light.direction_from(comps.point).dot(&comps.normalv) < 0.0;
+            let point = if material.double_sided && behind {
+                comps.under_point
+            } else {
+                comps.over_point
+            };
+
+            let in_shadow = if light.is_enabled() {
+                self.shadow_amount(light.as_ref(), &point)
+            } else {
+                1.0
+            };
+            surface += light.lighting_with_color(
+                material,
+                surface_color,
+                point,
                 comps.eyev,
                 comps.normalv,
-                self.is_shadowed(light, &comps.over_point),
-                comps.object.world_to_object(comps.over_point, self),
+                in_shadow,
             );
         }
 
-        let reflected = self.reflected_color(comps, depth);
-        let refracted = self.refracted_color(comps, depth);
+        if self.clamp_lighting {
+            surface = surface.clamp_to_white();
+        }
 
-        if comps.object.get_material().reflective > 0.
+        let color = if comps.object.get_material().reflective > 0.
             && comps.object.get_material().transparency > 0.
         {
             let reflectance = schlick(comps);
-            return surface + reflected * reflectance + refracted * (1. - reflectance);
+            let reflected = self.reflected_color(comps, depth, throughput * reflectance);
+            let refracted = self.refracted_color(comps, depth, throughput * (1. - reflectance));
+            surface + reflected * reflectance + refracted * (1. - reflectance)
+        } else {
+            let reflected = self.reflected_color(comps, depth, throughput);
+            let refracted = self.refracted_color(comps, depth, throughput);
+            surface + reflected + refracted
+        };
+
+        if comps.coverage < 1.0 {
+            // the shape only covered part of a wide ray, blend in whatever is behind it
+            let behind = Ray::new_unchecked(comps.exit_point, -comps.eyev);
+            let behind_weight = throughput * (1.0 - comps.coverage);
+            return color * comps.coverage
+                + self.trace(&behind, depth, &[], behind_weight) * (1.0 - comps.coverage);
         }
 
-        surface + reflected + refracted
+        color
     }
 
     pub fn color_at(&self, ray: &Ray, depth: usize) -> Color {
-        let intersections = self.intersect_world(ray);
+        self.color_at_culled(ray, depth, &[])
+    }
+
+    // the color seen by a primary ray, which can ignore objects culled for the camera
+    pub fn color_at_culled(&self, ray: &Ray, depth: usize, culled: &[bool]) -> Color {
+        self.trace(ray, depth, culled, 1.0)
+    }
+
+    fn trace(&self, ray: &Ray, depth: usize, culled: &[bool], throughput: f64) -> Color {
+        if throughput < self.min_importance {
+            return Color::black();
+        }
+        let intersections = self.intersect_world_culled(ray, culled);
         match hit(&intersections) {
             Some(hit) => {
                 let comps = prepare_computations(&hit, ray, &intersections, Some(self));
-                self.shade_hit(&comps, depth)
+                self.shade_hit_weighted(&comps, depth, throughput)
             }
-            None => Color::black(),
+            None => self.background_color(ray.direction),
         }
     }
 
-    pub fn reflected_color(&self, comps: &Computations, depth: usize) -> Color {
-        if f64_eq(comps.object.get_material().reflective, 0.0) {
+    /*
+        What a ray which misses everything sees. The environment wraps around the
+        scene with u = 0.5 + atan2(x, -z) / 2pi across the image, so looking down -z
+        sees the middle of it, and v = acos(y) / pi down from the top row
+    */
+    pub fn background_color(&self, direction: Tuple) -> Color {
+        let environment = match &self.environment {
+            Some(environment) => environment,
+            None => return self.background,
+        };
+
+        let d = direction.normalize();
+        let u = 0.5 + d.x.atan2(-d.z) / (2.0 * PI);
+        let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+        let x = ((u * environment.width as f64) as usize).min(environment.width - 1);
+        let y = ((v * environment.height as f64) as usize).min(environment.height - 1);
+        environment.get_pixel(x, y)
+    }
+
+    /*
+        The id of the object seen through a pixel of the camera,
+        the innermost shape is returned for objects inside of groups
+    */
+    pub fn pick(&self, camera: &Camera, px: usize, py: usize) -> Option<usize> {
+        let ray = camera.ray_for_pixel(px, py);
+        let intersections = self.intersect_world(&ray);
+        hit(&intersections).map(|h| h.shape.get_id())
+    }
+
+    /*
+        The radius of the ray's footprint measured in the space of the object it hit,
+        found by stepping away from the hit so parent group transforms are included
+    */
+    fn footprint_in_object_space(&self, comps: &Computations, object_point: Tuple) -> f64 {
+        if comps.footprint <= 0.0 {
+            return 0.0;
+        }
+        let r = comps.footprint;
+        [
+            Tuple::vector(r, 0.0, 0.0),
+            Tuple::vector(0.0, r, 0.0),
+            Tuple::vector(0.0, 0.0, r),
+        ]
+        .iter()
+        .map(|&step| {
+            let moved = comps.object.world_to_object(comps.over_point + step, self);
+            (moved - object_point).magnitude()
+        })
+        .fold(0.0, f64::max)
+    }
+
+    // throughput is how much of the pixel the surface at comps contributes
+    pub fn reflected_color(&self, comps: &Computations, depth: usize, throughput: f64) -> Color {
+        match reflect_ray(comps) {
+            Some(reflect_ray) => {
+                let reflective = comps.object.get_material().reflective;
+                let color = self.trace(&reflect_ray, depth - 1, &[], throughput * reflective);
+                color * reflective
+            }
             // surface isn't reflective
-            Color::black()
-        } else {
-            let reflect_ray = Ray::new(comps.over_point, comps.reflectv);
-            let color = self.color_at(&reflect_ray, depth - 1);
-            color * comps.object.get_material().reflective
+            None => Color::black(),
         }
     }
 
-    pub fn refracted_color(&self, comps: &Computations, depth: usize) -> Color {
-        if f64_eq(comps.object.get_material().transparency, 0.0) || depth == 0 {
-            Color::black()
-        } else {
-            // apply Snell's law //
-            let n_ratio = comps.n1 / comps.n2;
-            // the dot product is the same as the cosine of the angle between the points
-            let cos_i = comps.eyev.dot(&comps.normalv);
-            // use a trig identity to solve for angle of refraction
-            let sin2_t = n_ratio.powi(2) * (1. - cos_i.powi(2));
-
-            // total internal refraction
-            if sin2_t > 1. {
-                return Color::black();
+    pub fn refracted_color(&self, comps: &Computations, depth: usize, throughput: f64) -> Color {
+        let transparency = comps.object.get_material().transparency;
+        if depth == 0 || throughput * transparency < self.min_importance {
+            return Color::black();
+        }
+        let refract_ray = match refract_ray(comps) {
+            Some(ray) => ray,
+            None => return Color::black(),
+        };
+
+        let intersections = self.intersect_world(&refract_ray);
+        let hit = match hit(&intersections) {
+            Some(hit) => hit,
+            None => return self.background_color(refract_ray.direction) * transparency,
+        };
+        let refract_comps = prepare_computations(&hit, &refract_ray, &intersections, Some(self));
+        let mut color =
+            self.shade_hit_weighted(&refract_comps, depth - 1, throughput * transparency);
+
+        // entering the object the ray travels through it until the next hit,
+        // losing some of each channel along the way (Beer-Lambert)
+        let absorption = comps.object.get_material().absorption;
+        if !comps.inside && absorption != Color::black() {
+            color *= Color::new(
+                (-absorption.red() * hit.t).exp(),
+                (-absorption.green() * hit.t).exp(),
+                (-absorption.blue() * hit.t).exp(),
+            );
+        }
+
+        // find the color of the refracted ray accounting for transparency
+        color * transparency
+    }
+
+    /*
+        How many surfaces are shaded along the deepest path a ray takes through
+        reflections and refractions before it stops, 0 if it hits nothing
+    */
+    pub fn ray_depth(&self, ray: &Ray, depth: usize) -> usize {
+        if depth == 0 {
+            return 0;
+        }
+        let intersections = self.intersect_world(ray);
+        match hit(&intersections) {
+            Some(hit) => {
+                let comps = prepare_computations(&hit, ray, &intersections, Some(self));
+                self.hit_depth(&comps, depth)
             }
+            None => 0,
+        }
+    }
 
-            // general refraction case
+    // the same as ray_depth but starting from a hit which has already been found
+    pub fn hit_depth(&self, comps: &Computations, depth: usize) -> usize {
+        if depth == 0 {
+            return 0;
+        }
 
-            // find cos(theta_t) using another identity
-            let cos_t = (1. - sin2_t).sqrt();
-            let direction = (comps.normalv * (n_ratio * cos_i - cos_t)) - (comps.eyev * n_ratio);
-            let refract_ray = Ray::new(comps.under_point, direction);
-            // find the color of the refracted ray accounting for transparency
-            self.color_at(&refract_ray, depth - 1) * comps.object.get_material().transparency
+        let bounces = [reflect_ray(comps), refract_ray(comps)]
+            .iter()
+            .flatten()
+            .map(|r| self.ray_depth(r, depth - 1))
+            .max()
+            .unwrap_or(0);
+
+        if comps.coverage < 1.0 {
+            let behind = Ray::new_unchecked(comps.exit_point, -comps.eyev);
+            return (1 + bounces).max(self.ray_depth(&behind, depth));
         }
+        1 + bounces
     }
 
-    fn is_shadowed(&self, light_source: &PointLight, point: &Tuple) -> bool {
-        assert!(point.is_point());
+    // how dark the shadow on a shadow catcher is, averaged over the lights which are on
+    fn catcher_shadow(&self, comps: &Computations) -> f64 {
+        let lights: Vec<&dyn Light> = self
+            .light_sources
+            .iter()
+            .filter(|l| l.is_enabled())
+            .map(|l| l.as_ref())
+            .collect();
+        if lights.is_empty() {
+            return 0.0;
+        }
+        let total: f64 = lights
+            .iter()
+            .map(|l| self.shadow_amount(*l, &comps.over_point))
+            .sum();
+        total / lights.len() as f64
+    }
 
-        // get the vector from the point to the light source
-        let v = light_source.position - *point;
-        let distance = v.magnitude();
-        let direction = v.normalize();
+    /*
+        How opaque the first thing a ray sees is, for compositing a render over another
+        image. Misses are 0, ordinary surfaces are 1 and a shadow catcher is only as
+        opaque as the shadow on it, letting through the alpha of whatever is behind
+    */
+    pub fn alpha_at(&self, ray: &Ray) -> f64 {
+        let intersections = self.intersect_world(ray);
+        match hit(&intersections) {
+            Some(hit) => {
+                let comps = prepare_computations(&hit, ray, &intersections, Some(self));
+                if comps.object.get_material().shadow_catcher {
+                    let shadow = self.catcher_shadow(&comps);
+                    let behind = Ray::new_unchecked(comps.under_point, -comps.eyev);
+                    shadow + (1.0 - shadow) * self.alpha_at(&behind)
+                } else {
+                    1.0
+                }
+            }
+            None => 0.0,
+        }
+    }
 
-        // cast a ray from that point towards the source of light
-        let r = Ray::new(*point, direction);
-        let intersections = self.intersect_world(&r);
-        let h = hit(&intersections);
+    /*
+        The fraction of rays over the hemisphere about the normal which hit something
+        within the radius. The directions follow a fibonacci spiral so renders are repeatable
+    */
+    pub fn occlusion_at(&self, point: Tuple, normal: Tuple, config: AoConfig) -> f64 {
+        if config.samples == 0 {
+            return 0.0;
+        }
 
-        // if this ray collided with an object on it way to the light, return true otherwise false
-        match h {
-            Some(hit) => hit.t < distance,
-            None => false,
+        // any vector not parallel to the normal gives a basis for the hemisphere
+        let helper = if normal.x.abs() < 0.9 {
+            Tuple::vector(1.0, 0.0, 0.0)
+        } else {
+            Tuple::vector(0.0, 1.0, 0.0)
+        };
+        let tangent = normal.cross(&helper).normalize();
+        let bitangent = normal.cross(&tangent);
+        let golden_angle = PI * (3.0 - 5.0_f64.sqrt());
+
+        let mut hits = 0;
+        for i in 0..config.samples {
+            let height = 1.0 - (i as f64 + 0.5) / config.samples as f64;
+            let radius = (1.0 - height * height).sqrt();
+            let angle = golden_angle * i as f64;
+            let direction = tangent * (radius * angle.cos())
+                + bitangent * (radius * angle.sin())
+                + normal * height;
+
+            if self.any_hit_before(&Ray::new_unchecked(point, direction), config.radius) {
+                hits += 1;
+            }
         }
+
+        hits as f64 / config.samples as f64
+    }
+
+    fn is_shadowed(&self, light_source: &dyn Light, point: &Tuple) -> bool {
+        self.shadow_amount(light_source, point) > 0.0
+    }
+
+    /*
+        How much of a light is blocked at a point, from 0.0 when every sample
+        of the light can be seen to 1.0 when none of them can
+    */
+    fn shadow_amount(&self, light_source: &dyn Light, point: &Tuple) -> f64 {
+        assert!(point.is_point());
+
+        let samples = light_source.shadow_samples(*point, self.seed);
+        if samples.is_empty() {
+            return 0.0;
+        }
+
+        // cast a ray from that point towards each sample of the light
+        // if this ray collides with an object on it way to the light that sample is in shadow
+        let blocked = samples
+            .iter()
+            .filter(|(direction, distance)| {
+                let r = Ray::new_unchecked(*point, *direction);
+                self.any_hit_before(&r, *distance)
+            })
+            .count();
+        blocked as f64 / samples.len() as f64
+    }
+
+    /*
+        Check if anything is hit in front of the ray before max_t, stops at the
+        first object found rather than collecting and sorting every intersection
+    */
+    pub fn any_hit_before(&self, ray: &Ray, max_t: f64) -> bool {
+        self.objects.iter().any(|s| s.any_hit_before(ray, max_t))
     }
 
     pub fn get_object_by_id(&self, id: usize) -> Option<&dyn Intersectable> {
+        if let Some(path) = self.group_paths.get(&id) {
+            let mut shape = self.objects[path[0]].as_ref();
+            for &i in &path[1..] {
+                shape = shape.as_any().downcast_ref::<Group>()?.get_object(i)?;
+            }
+            return Some(shape);
+        }
+
         for s in &self.objects {
             if s.get_id() == id {
                 return Some(s.as_ref());
@@ -148,6 +556,59 @@ impl World {
     }
 }
 
+fn index_groups(
+    shape: &dyn Intersectable,
+    path: Vec<usize>,
+    paths: &mut HashMap<usize, Vec<usize>>,
+) {
+    if let Some(g) = shape.as_any().downcast_ref::<Group>() {
+        for (i, o) in g.objects().iter().enumerate() {
+            let mut child_path = path.clone();
+            child_path.push(i);
+            index_groups(o.as_ref(), child_path, paths);
+        }
+        paths.insert(g.get_id(), path);
+    }
+}
+
+// the ray leaving the surface in the mirror direction, None when the surface is not reflective
+fn reflect_ray(comps: &Computations) -> Option<Ray> {
+    if f64_eq(comps.object.get_material().reflective, 0.0) {
+        None
+    } else {
+        Some(Ray::new_unchecked(comps.over_point, comps.reflectv))
+    }
+}
+
+/*
+    The ray bent through the surface, None when the surface is opaque or
+    the light is totally internally reflected
+*/
+fn refract_ray(comps: &Computations) -> Option<Ray> {
+    if f64_eq(comps.object.get_material().transparency, 0.0) {
+        return None;
+    }
+
+    // apply Snell's law //
+    let n_ratio = comps.n1 / comps.n2;
+    // the dot product is the same as the cosine of the angle between the points
+    let cos_i = comps.eyev.dot(&comps.normalv);
+    // use a trig identity to solve for angle of refraction
+    let sin2_t = n_ratio.powi(2) * (1. - cos_i.powi(2));
+
+    // total internal refraction
+    if sin2_t > 1. {
+        return None;
+    }
+
+    // general refraction case
+
+    // find cos(theta_t) using another identity
+    let cos_t = (1. - sin2_t).sqrt();
+    let direction = (comps.normalv * (n_ratio * cos_i - cos_t)) - (comps.eyev * n_ratio);
+    Some(Ray::new_unchecked(comps.under_point, direction))
+}
+
 fn schlick(comps: &Computations) -> f64 {
     let mut cos = comps.eyev.dot(&comps.normalv);
     if comps.n1 > comps.n2 {
@@ -166,13 +627,31 @@ fn schlick(comps: &Computations) -> f64 {
 
 #[cfg(test)]
 mod test {
-    use std::f64::consts::PI;
+    use std::{
+        any::Any,
+        f64::consts::PI,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
 
     use crate::{
-        draw::{color::Color, material::Material, patterns::Solid},
+        draw::{
+            color::Color,
+            material::Material,
+            patterns::{planar_map, Solid, UvPattern},
+            texture::TextureMap,
+        },
         math::{matrix::Matrix, tuples::Tuple, utils::f64_eq},
-        scene::camera::{render, view_transform, Camera},
-        shapes::{intersect::prepare_computations, plane::Plane, sphere::Sphere},
+        obj_parser::parse_obj_file,
+        scene::{
+            camera::{
+                render, render_aovs, render_frozen, render_with_metadata, view_transform, Camera,
+            },
+            light::{AreaLight, DirectionalLight, PointLight},
+        },
+        shapes::{
+            bounds::BoundingBox, cylinder::Cylinder, intersect::prepare_computations, plane::Plane,
+            sphere::Sphere, triangle::Triangle,
+        },
     };
 
     use super::*;
@@ -180,10 +659,10 @@ mod test {
     fn populated_world() -> World {
         let mut w = World::new();
 
-        w.light_sources.push(PointLight::new(
+        w.light_sources.push(Box::new(PointLight::new(
             Color::new(1.0, 1.0, 1.0),
             Tuple::point(-10.0, 10.0, -10.0),
-        ));
+        )));
 
         let mut s1 = Sphere::new(None);
         s1.material = Material::default_material();
@@ -203,6 +682,65 @@ mod test {
         w
     }
 
+    #[test]
+    fn sky_and_ambient_colors() {
+        let mut w = populated_world();
+        let sky = Color::new(0.5, 0.7, 1.0);
+        let miss = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 1.0, 0.0));
+        assert_eq!(w.color_at(&miss, 5), Color::black());
+        w.background = sky;
+        assert_eq!(w.color_at(&miss, 5), sky);
+
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let white_ambient = w.color_at(&r, 5);
+        w.light_sources[0].set_enabled(false);
+        assert_eq!(w.color_at(&r, 5), Color::new(0.08, 0.1, 0.06));
+
+        // only the ambient term picks up the tint
+        w.ambient_color = Color::new(0.5, 0.5, 1.0);
+        assert_eq!(w.color_at(&r, 5), Color::new(0.04, 0.05, 0.06));
+        w.light_sources[0].set_enabled(true);
+        assert_eq!(
+            white_ambient - w.color_at(&r, 5),
+            Color::new(0.04, 0.05, 0.0)
+        );
+    }
+
+    #[test]
+    fn missed_rays_see_the_environment() {
+        let mut w = World::new();
+        // a 4x2 image, one color per quarter of the horizon above and below it
+        let mut environment = Canvas::new(4, 2);
+        for y in 0..2 {
+            for x in 0..4 {
+                environment.write_pixel(x, y, Color::new(x as f64 / 4.0, y as f64, 0.5));
+            }
+        }
+        w.environment = Some(environment);
+
+        let looking = |x: f64, y: f64, z: f64| {
+            w.color_at(
+                &Ray::new(
+                    Tuple::point(0.0, 0.0, 0.0),
+                    Tuple::vector(x, y, z).normalize(),
+                ),
+                5,
+            )
+        };
+        // -z is the middle of the image and turning right towards +x moves right across it
+        assert_eq!(looking(0.1, 0.5, -1.0), Color::new(0.5, 0.0, 0.5));
+        assert_eq!(looking(-0.1, 0.5, -1.0), Color::new(0.25, 0.0, 0.5));
+        assert_eq!(looking(1.0, -0.5, 0.1), Color::new(0.75, 1.0, 0.5));
+        assert_eq!(looking(-1.0, -0.5, 0.1), Color::new(0.0, 1.0, 0.5));
+        // straight up is the top row, straight down the bottom
+        assert_eq!(looking(0.0, 1.0, 0.0).green(), 0.0);
+        assert_eq!(looking(0.0, -1.0, 0.0).green(), 1.0);
+
+        w.environment = None;
+        let r = Ray::new(Tuple::point(0.0, 0.0, 0.0), Tuple::vector(0.0, 0.0, -1.0));
+        assert_eq!(w.color_at(&r, 5), Color::black());
+    }
+
     #[test]
     fn rendering_a_world_with_a_camera() {
         let w = populated_world();
@@ -215,6 +753,295 @@ mod test {
         assert_eq!(image.get_pixel(5, 5), Color::new(0.38066, 0.47583, 0.2855));
     }
 
+    #[test]
+    fn rendering_writes_metadata_sidecar() {
+        let w = populated_world();
+        let mut c = Camera::new(16, 12, PI / 3.0);
+        c.look_at(
+            Tuple::point(0.0, 1.5, -5.0),
+            Tuple::point(0.0, 1.0, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0),
+        );
+
+        let path = std::env::temp_dir().join(format!(
+            "ray_tracer_metadata_sidecar_{}.json",
+            std::process::id()
+        ));
+        let path = path.to_str().unwrap();
+        let image = render_with_metadata(c, w, 2, path);
+        assert_eq!((image.width, image.height), (16, 12));
+
+        let contents = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&contents).unwrap();
+
+        assert_eq!(json["camera"]["hsize"], 16);
+        assert_eq!(json["camera"]["vsize"], 12);
+        assert!(f64_eq(
+            json["camera"]["field_of_view"].as_f64().unwrap(),
+            PI / 3.0
+        ));
+        assert!(f64_eq(json["camera"]["eye"][1].as_f64().unwrap(), 1.5));
+        assert!(f64_eq(json["camera"]["eye"][2].as_f64().unwrap(), -5.0));
+        assert_eq!(json["lights"], 1);
+        assert_eq!(json["objects"], 2);
+        assert!(json["render_seconds"].as_f64().unwrap() >= 0.0);
+    }
+
+    #[test]
+    fn scene_bounds_enclose_every_finite_object() {
+        let mut w = World::new();
+        assert!(w.bounds().is_empty());
+
+        let left = Sphere::unit();
+        let right = Sphere::new(Some(Matrix::translation(5.0, 0.0, 0.0)));
+        let (left_bounds, right_bounds) = (left.parent_space_bounds(), right.parent_space_bounds());
+        w.objects.push(Box::new(left));
+        w.objects.push(Box::new(right));
+        w.objects.push(Box::new(Plane::new(None)));
+
+        let bounds = w.bounds();
+        assert!(bounds.contains_box(&left_bounds) && bounds.contains_box(&right_bounds));
+        assert_eq!(bounds.min, Tuple::point(-1.0, -1.0, -1.0));
+        assert_eq!(bounds.max, Tuple::point(6.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn objects_outside_the_frustum_are_culled() {
+        let mut w = populated_world();
+        // behind the camera, far off to the side, and above the top of the view
+        w.objects
+            .push(Box::new(Sphere::new(Some(Matrix::translation(
+                0.0, 0.0, -50.0,
+            )))));
+        w.objects
+            .push(Box::new(Sphere::new(Some(Matrix::translation(
+                1000.0, 0.0, 0.0,
+            )))));
+        w.objects
+            .push(Box::new(Sphere::new(Some(Matrix::translation(
+                0.0, 20.0, 0.0,
+            )))));
+        // partly on screen, and infinite
+        w.objects
+            .push(Box::new(Sphere::new(Some(Matrix::translation(
+                4.5, 0.0, 0.0,
+            )))));
+        w.objects.push(Box::new(Plane::new(Some(Matrix::translation(
+            0.0, -1.0, 0.0,
+        )))));
+
+        let mut c = Camera::new(100, 50, PI / 2.0);
+        c.look_at(
+            Tuple::point(0.0, 0.0, -5.0),
+            Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0),
+        );
+
+        assert_eq!(
+            w.cull_to_camera(&c),
+            vec![false, false, true, true, true, false, false]
+        );
+
+        // shadow and reflection rays still see culled objects
+        let ray = Ray::new(Tuple::point(0.0, 0.0, -60.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(w.intersect_world(&ray).len(), 6);
+        assert_eq!(
+            w.intersect_world_culled(&ray, &w.cull_to_camera(&c)).len(),
+            4
+        );
+    }
+
+    #[test]
+    fn picking_objects_through_pixels() {
+        let w = populated_world();
+        let c = Camera::new_with_transform(
+            11,
+            11,
+            PI / 2.0,
+            view_transform(
+                Tuple::point(0.0, 0.0, -5.0),
+                Tuple::point(0.0, 0.0, 0.0),
+                Tuple::vector(0.0, 1.0, 0.0),
+            ),
+        );
+
+        // the outer sphere hides the inner one
+        assert_eq!(w.pick(&c, 5, 5), Some(w.objects[0].get_id()));
+        assert_eq!(w.pick(&c, 0, 0), None);
+    }
+
+    #[test]
+    fn rendering_aovs_in_one_pass() {
+        let camera = || {
+            Camera::new_with_transform(
+                21,
+                11,
+                PI / 2.0,
+                view_transform(
+                    Tuple::point(0.0, 0.0, -5.0),
+                    Tuple::point(0.0, 0.0, 0.0),
+                    Tuple::vector(0.0, 1.0, 0.0),
+                ),
+            )
+        };
+
+        let beauty = render(camera(), populated_world(), 3);
+        let aovs = render_aovs(camera(), populated_world(), 3);
+        for y in 0..11 {
+            for x in 0..21 {
+                assert_eq!(aovs.beauty.get_pixel(x, y), beauty.get_pixel(x, y));
+            }
+        }
+
+        // the center pixel looks straight at the outer sphere, which is 4 units away
+        let ray = camera().ray_for_pixel(10, 5);
+        let t = hit(&populated_world().intersect_world(&ray)).unwrap().t;
+        assert!(f64_eq(t, 4.0));
+        let d = t / aovs.max_depth;
+        assert_eq!(aovs.depth.get_pixel(10, 5), Color::new(d, d, d));
+        assert!(aovs.max_depth >= 4.0);
+
+        // facing the camera the normal is -z
+        assert_eq!(aovs.normal.get_pixel(10, 5), Color::new(0.5, 0.5, 0.0));
+
+        // nothing in the corner, and the sphere has the same id color everywhere
+        assert_eq!(aovs.depth.get_pixel(0, 0), Color::white());
+        assert_eq!(aovs.object_id.get_pixel(0, 0), Color::black());
+        assert_eq!(aovs.alpha.get_pixel(0, 0), Color::black());
+        assert_eq!(aovs.alpha.get_pixel(10, 5), Color::white());
+        assert_eq!(
+            aovs.object_id.get_pixel(10, 5),
+            aovs.object_id.get_pixel(11, 5)
+        );
+        assert_ne!(aovs.object_id.get_pixel(10, 5), Color::black());
+    }
+
+    #[test]
+    fn shadow_catchers_only_show_shadows() {
+        // the second light clears the ball, so the shadow under it is half dark
+        let scene = |catcher: bool| {
+            let mut w = World::new();
+            w.light_sources.push(Box::new(PointLight::new(
+                Color::white(),
+                Tuple::point(0.0, 10.0, 0.0),
+            )));
+            w.light_sources.push(Box::new(PointLight::new(
+                Color::white(),
+                Tuple::point(10.0, 10.0, 0.0),
+            )));
+            w.objects.push(Box::new(Sphere::new(Some(
+                &Matrix::translation(0.0, 1.0, 0.0) * &Matrix::scaling(0.5, 0.5, 0.5),
+            ))));
+            if catcher {
+                let mut catcher = Plane::new(None);
+                catcher.material = Material::builder().shadow_catcher(true).build();
+                w.objects.push(Box::new(catcher));
+            }
+            w
+        };
+
+        let looking_at = |x: f64| {
+            Ray::new(
+                Tuple::point(x, 5.0, -5.0),
+                Tuple::vector(0.0, -1.0, 1.0).normalize(),
+            )
+        };
+        let (shadowed, clear) = (looking_at(0.0), looking_at(5.0));
+
+        let mut w = scene(true);
+        assert_eq!(w.alpha_at(&clear), 0.0);
+        assert_eq!(w.color_at(&clear, 5), Color::black());
+        assert_eq!(w.alpha_at(&shadowed), 0.5);
+
+        // a lit backdrop under the catcher is only darkened where the shadow falls,
+        // the catcher itself never blocks the light reaching it
+        let backdrop = || Box::new(Plane::new(Some(Matrix::translation(0.0, -1.0, 0.0))));
+        w.objects.push(backdrop());
+        let mut reference = scene(false);
+        reference.objects.push(backdrop());
+
+        let lit = reference.color_at(&clear, 5);
+        assert!(lit.red() > 0.5);
+        assert_eq!(w.color_at(&clear, 5), lit);
+        assert_eq!(
+            w.color_at(&shadowed, 5),
+            reference.color_at(&shadowed, 5) * 0.5
+        );
+        assert_eq!(w.alpha_at(&shadowed), 1.0);
+    }
+
+    #[test]
+    fn ray_depth_counts_bounces() {
+        let mut w = World::new();
+        w.light_sources.push(Box::new(PointLight::new(
+            Color::white(),
+            Tuple::point(-10.0, 10.0, -10.0),
+        )));
+        // an opaque sphere to the left and two panes of glass in front of a wall to the right
+        w.objects
+            .push(Box::new(Sphere::new(Some(Matrix::translation(
+                -3.0, 0.0, 0.0,
+            )))));
+        for z in [0.0, 2.0] {
+            let mut pane = Sphere::new_glass_sphere(Some(
+                &Matrix::translation(3.0, 0.0, z) * &Matrix::scaling(1.0, 1.0, 0.2),
+            ));
+            pane.material.refractive_index = 1.0;
+            w.objects.push(Box::new(pane));
+        }
+        w.objects.push(Box::new(Plane::new(Some(
+            &Matrix::translation(0.0, 0.0, 10.0) * &Matrix::rotation_x(PI / 2.0),
+        ))));
+
+        let forward = Tuple::vector(0.0, 0.0, 1.0);
+        let opaque = Ray::new(Tuple::point(-3.0, 0.0, -5.0), forward);
+        let glass = Ray::new(Tuple::point(3.0, 0.0, -5.0), forward);
+        assert_eq!(w.ray_depth(&opaque, 5), 1);
+        // two surfaces for each pane and then the wall, which is cut off by the limit
+        assert_eq!(w.ray_depth(&glass, 5), 5);
+        assert_eq!(w.ray_depth(&glass, 10), 5);
+        assert_eq!(
+            w.ray_depth(&Ray::new(Tuple::point(0.0, 0.0, -5.0), -forward), 5),
+            0
+        );
+
+        let mut camera = Camera::new(3, 1, PI / 2.0);
+        camera.look_at(
+            Tuple::point(0.0, 0.0, -3.0),
+            Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 1.0, 0.0),
+        );
+        let aovs = render_aovs(camera, w, 1);
+        assert_eq!(aovs.ray_depth.get_pixel(0, 0), Color::new(0.2, 0.2, 0.2));
+        assert_eq!(aovs.ray_depth.get_pixel(2, 0), Color::white());
+    }
+
+    #[test]
+    fn render_is_independent_of_thread_count() {
+        let render_with = |threads| {
+            let transform = view_transform(
+                Tuple::point(0.0, 0.0, -5.0),
+                Tuple::point(0.0, 0.0, 0.0),
+                Tuple::vector(0.0, 1.0, 0.0),
+            );
+            let c = Camera::new_with_transform(11, 11, PI / 2.0, transform);
+            render(c, populated_world(), threads)
+        };
+
+        let single = render_with(1);
+        for threads in [3, 8] {
+            let image = render_with(threads);
+            assert_eq!(image.width, single.width);
+            assert_eq!(image.height, single.height);
+            for y in 0..single.height {
+                for x in 0..single.width {
+                    assert_eq!(image.get_pixel(x, y), single.get_pixel(x, y));
+                }
+            }
+        }
+    }
+
     #[test]
     fn default_world_intersection() {
         let world = populated_world();
@@ -237,6 +1064,149 @@ mod test {
         assert_eq!(c, Color::new(0.38066, 0.47583, 0.2855));
     }
 
+    #[test]
+    fn surface_offset_follows_scene_scale() {
+        let offset_with = |surface_offset| {
+            let mut w = World::new();
+            w.surface_offset = surface_offset;
+            w.objects.push(Box::new(Sphere::new(Some(Matrix::scaling(
+                1000.0, 1000.0, 1000.0,
+            )))));
+            let ray = Ray::new(
+                Tuple::point(0.0, 0.0, -5000.0),
+                Tuple::vector(0.0, 0.0, 1.0),
+            );
+            let xs = w.intersect_world(&ray);
+            let comps = prepare_computations(&xs[0], &ray, &xs, Some(&w));
+            (
+                comps.point.z - comps.over_point.z,
+                comps.under_point.z - comps.point.z,
+            )
+        };
+
+        let (over, _) = offset_with(World::new().surface_offset);
+        assert!(f64_eq(over, EPSILON));
+
+        let (over, under) = offset_with(0.01);
+        assert!(f64_eq(over, 0.01));
+        assert!(f64_eq(under, 0.01));
+    }
+
+    #[test]
+    fn crevices_are_more_occluded() {
+        let mut w = World::new();
+        w.light_sources.push(Box::new(PointLight::new(
+            Color::white(),
+            Tuple::point(0.0, 10.0, -10.0),
+        )));
+        w.objects
+            .push(Box::new(Sphere::new(Some(Matrix::translation(
+                -1.05, 0.0, 0.0,
+            )))));
+        w.objects
+            .push(Box::new(Sphere::new(Some(Matrix::translation(
+                1.05, 0.0, 0.0,
+            )))));
+        let config = AoConfig {
+            samples: 64,
+            radius: 2.0,
+        };
+
+        // a point on the left sphere facing into the gap, and one facing away from everything
+        let angle = PI / 9.0;
+        let crevice_normal = Tuple::vector(angle.cos(), angle.sin(), 0.0);
+        let crevice = Tuple::point(-1.05, 0.0, 0.0) + crevice_normal * (1.0 + EPSILON);
+        let exposed_normal = Tuple::vector(-1.0, 0.0, 0.0);
+        let exposed = Tuple::point(-2.05 - EPSILON, 0.0, 0.0);
+
+        let crevice_occlusion = w.occlusion_at(crevice, crevice_normal, config);
+        let exposed_occlusion = w.occlusion_at(exposed, exposed_normal, config);
+        assert!(f64_eq(exposed_occlusion, 0.0));
+        assert!(crevice_occlusion > 0.3);
+
+        // only the ambient term of shading changes
+        let ray = Ray::new(
+            Tuple::point(-1.05, 0.0, 0.0) + crevice_normal * 1.05,
+            -crevice_normal,
+        );
+        let shade = |w: &World| {
+            let xs = w.intersect_world(&ray);
+            let h = hit(&xs).unwrap();
+            let comps = prepare_computations(&h, &ray, &xs, Some(w));
+            w.shade_hit(&comps, 5)
+        };
+        let plain = shade(&w);
+        w.ambient_occlusion = Some(config);
+        let occluded = shade(&w);
+        let ambient = Color::white() * Material::default_material().ambient;
+        assert_eq!(plain - occluded, ambient * crevice_occlusion);
+    }
+
+    #[test]
+    fn disabling_lights() {
+        let mut w = populated_world();
+        let ray = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let lit = w.color_at(&ray, 5);
+
+        // with the only light off just the ambient term is left
+        w.set_light_enabled(0, false);
+        assert_eq!(w.color_at(&ray, 5), Color::new(0.8, 1.0, 0.6) * 0.1);
+
+        w.set_light_enabled(0, true);
+        assert_eq!(w.color_at(&ray, 5), lit);
+
+        let light = w.remove_light(0);
+        assert!(w.light_sources.is_empty());
+        let light = light.as_any().downcast_ref::<PointLight>().unwrap();
+        assert_eq!(light.position, Tuple::point(-10.0, 10.0, -10.0));
+        assert_eq!(w.color_at(&ray, 5), Color::black());
+    }
+
+    #[test]
+    fn thick_colored_glass_absorbs_more() {
+        let through_glass = |radius: f64, absorption: Color| {
+            let mut w = World::new();
+            w.light_sources.push(Box::new(PointLight::new(
+                Color::white(),
+                Tuple::point(0.0, 0.0, -10.0),
+            )));
+
+            let mut glass = Sphere::new(Some(Matrix::scaling(radius, radius, radius)));
+            glass.material = Material::builder()
+                .ambient(0.0)
+                .diffuse(0.0)
+                .specular(0.0)
+                .transparent(1.0, 1.0)
+                .absorption(absorption)
+                .build();
+            w.objects.push(Box::new(glass));
+
+            let mut backdrop = Plane::new(Some(
+                &Matrix::translation(0.0, 0.0, 5.0) * &Matrix::rotation_x(PI / 2.0),
+            ));
+            backdrop.material = Material::builder().ambient(1.0).diffuse(0.0).build();
+            w.objects.push(Box::new(backdrop));
+
+            let ray = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+            w.color_at(&ray, 5)
+        };
+
+        // clear glass lets everything through
+        assert_eq!(through_glass(1.0, Color::black()), Color::white());
+
+        let tint = Color::new(0.1, 0.5, 1.0);
+        let thin = through_glass(1.0, tint);
+        let thick = through_glass(2.0, tint);
+        assert_eq!(
+            thin,
+            Color::new((-0.2_f64).exp(), (-1.0_f64).exp(), (-2.0_f64).exp())
+        );
+        assert!(thick.red() < thin.red());
+        assert!(thick.green() < thin.green());
+        assert!(thick.blue() < thin.blue());
+        assert!(thick.blue() / thick.red() < thin.blue() / thin.red());
+    }
+
     #[test]
     fn ray_miss() {
         let w = populated_world();
@@ -272,28 +1242,143 @@ mod test {
     fn no_shadow() {
         let w = populated_world();
         let p = Tuple::point(0.0, 10.0, 0.0);
-        assert!(!w.is_shadowed(&w.light_sources[0], &p));
+        assert!(!w.is_shadowed(w.light_sources[0].as_ref(), &p));
     }
 
     #[test]
     fn is_shadow_behind_object() {
         let w = populated_world();
         let p = Tuple::point(10.0, -10.0, 10.0);
-        assert!(w.is_shadowed(&w.light_sources[0], &p));
+        assert!(w.is_shadowed(w.light_sources[0].as_ref(), &p));
     }
 
     #[test]
     fn no_shadow_point_behind_light() {
         let w = populated_world();
         let p = Tuple::point(-20.0, 20.0, -20.0);
-        assert!(!w.is_shadowed(&w.light_sources[0], &p));
+        assert!(!w.is_shadowed(w.light_sources[0].as_ref(), &p));
     }
 
     #[test]
     fn no_shadow_object_behind_point() {
         let w = populated_world();
         let p = Tuple::point(-2.0, 2.0, -2.0);
-        assert!(!w.is_shadowed(&w.light_sources[0], &p));
+        assert!(!w.is_shadowed(w.light_sources[0].as_ref(), &p));
+    }
+
+    // light arriving from fixed directions with no position, like the sun
+    struct DistantLight {
+        directions: Vec<Tuple>, // towards the light
+    }
+
+    impl Light for DistantLight {
+        fn intensity(&self) -> Color {
+            Color::white()
+        }
+        fn is_enabled(&self) -> bool {
+            true
+        }
+        fn set_enabled(&mut self, _: bool) {}
+        fn direction_from(&self, _: Tuple) -> Tuple {
+            self.directions[0]
+        }
+        fn distance_from(&self, _: Tuple) -> f64 {
+            f64::INFINITY
+        }
+        fn shadow_samples(&self, _: Tuple, _: u64) -> Vec<(Tuple, f64)> {
+            self.directions
+                .iter()
+                .map(|d| (*d, f64::INFINITY))
+                .collect()
+        }
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+    }
+
+    #[test]
+    fn shadows_from_a_light_without_a_position() {
+        let mut w = World::new();
+        w.objects.push(Box::new(Sphere::new(None)));
+        let sun = DistantLight {
+            directions: vec![Tuple::vector(0.0, 1.0, 0.0)],
+        };
+
+        // however far below the sphere a point is, the sun is still behind it
+        for depth in [2.0, 100.0, 1e6] {
+            assert!(w.is_shadowed(&sun, &Tuple::point(0.0, -depth, 0.0)));
+        }
+        assert!(!w.is_shadowed(&sun, &Tuple::point(0.0, 1e6, 0.0)));
+        assert!(!w.is_shadowed(&sun, &Tuple::point(2.0, -1e6, 0.0)));
+
+        // one of two samples is blocked
+        let spread = DistantLight {
+            directions: vec![Tuple::vector(0.0, 1.0, 0.0), Tuple::vector(1.0, 0.0, 0.0)],
+        };
+        let p = Tuple::point(0.0, -5.0, 0.0);
+        assert_eq!(w.shadow_amount(&spread, &p), 0.5);
+        assert_eq!(w.shadow_amount(&sun, &p), 1.0);
+    }
+
+    #[test]
+    fn sunlight_casts_parallel_shadows() {
+        let mut w = World::new();
+        w.objects.push(Box::new(Sphere::new(None)));
+        w.objects.push(Box::new(Plane::new(Some(Matrix::translation(
+            0.0, -1e4, 0.0,
+        )))));
+        w.light_sources.push(Box::new(DirectionalLight::new(
+            Color::white(),
+            Tuple::vector(0.0, -1.0, 0.0),
+        )));
+
+        // the shadow on a floor far below is still exactly as wide as the sphere
+        let sun = w.light_sources[0].as_ref();
+        assert!(w.is_shadowed(sun, &Tuple::point(0.9, -9999.0, 0.0)));
+        assert!(!w.is_shadowed(sun, &Tuple::point(1.1, -9999.0, 0.0)));
+
+        let down = Tuple::vector(0.0, -1.0, 0.0);
+        let lit = w.color_at(&Ray::new(Tuple::point(0.0, -9990.0, 5.0), down), 1);
+        let shadowed = w.color_at(&Ray::new(Tuple::point(0.0, -9990.0, 0.0), down), 1);
+        assert_eq!(lit, Color::new(1.9, 1.9, 1.9));
+        assert_eq!(shadowed, Color::new(0.1, 0.1, 0.1));
+    }
+
+    #[test]
+    fn area_lights_cast_soft_shadows() {
+        let mut w = World::new();
+        w.objects.push(Box::new(Sphere::new(None)));
+        let mut light = AreaLight::new(
+            Color::white(),
+            Tuple::point(-2.0, 5.0, -2.0),
+            Tuple::vector(4.0, 0.0, 0.0),
+            4,
+            Tuple::vector(0.0, 0.0, 4.0),
+            4,
+        );
+        assert_eq!(
+            light.shadow_samples(Tuple::point(0.0, 0.0, 0.0), 0).len(),
+            16
+        );
+
+        // straight under the sphere every sample is hidden, far to the side none are
+        let umbra = Tuple::point(0.0, -2.0, 0.0);
+        let outside = Tuple::point(6.0, -2.0, 0.0);
+        let penumbra = Tuple::point(1.5, -2.0, 0.0);
+        assert_eq!(w.shadow_amount(&light, &umbra), 1.0);
+        assert_eq!(w.shadow_amount(&light, &outside), 0.0);
+        let partial = w.shadow_amount(&light, &penumbra);
+        assert!(partial > 0.0 && partial < 1.0);
+
+        // jittered samples stay in their cells and repeat for the same render seed
+        light.jitter = true;
+        w.seed = 3;
+        let first = w.shadow_amount(&light, &penumbra);
+        assert!(first > 0.0 && first < 1.0);
+        assert_eq!(first, w.shadow_amount(&light, &penumbra));
+        let samples = light.shadow_samples(penumbra, 3);
+        assert_eq!(samples, light.shadow_samples(penumbra, 3));
+        assert_ne!(samples, light.shadow_samples(penumbra, 4));
     }
 
     #[test]
@@ -309,10 +1394,38 @@ mod test {
         w.objects[1] = Box::new(s2);
         let intersections = w.objects[1].intersect(&r);
         let comps = prepare_computations(&intersections[0], &r, &intersections, None);
-        let color = w.reflected_color(&comps, 5);
+        let color = w.reflected_color(&comps, 5, 1.0);
         assert_eq!(color, Color::black());
     }
 
+    #[test]
+    fn mutating_material_in_place() {
+        let mut w = populated_world();
+        w.objects.push(Box::new(Plane::new(Some(Matrix::translation(
+            0.0, -1.0, 0.0,
+        )))));
+        let r = Ray::new(
+            Tuple::point(0.0, 0.0, -3.0),
+            Tuple::vector(0.0, (2.0_f64).sqrt() / -2.0, (2.0_f64).sqrt() / 2.0),
+        );
+        let reflected = |w: &World| {
+            let intersections = w.objects.last().unwrap().intersect(&r);
+            let comps = prepare_computations(&intersections[0], &r, &intersections, None);
+            w.reflected_color(&comps, 5, 1.0)
+        };
+        assert_eq!(reflected(&w), Color::black());
+
+        w.objects.last_mut().unwrap().get_material_mut().reflective = 0.5;
+        assert_eq!(
+            reflected(&w),
+            Color::new(
+                0.19033220149513302,
+                0.23791525186891627,
+                0.14274915112134978
+            )
+        );
+    }
+
     #[test]
     fn reflected_color_for_a_reflective_material() {
         let mut w = populated_world();
@@ -326,7 +1439,7 @@ mod test {
         let intersections = w.objects.last().unwrap().intersect(&r);
         let comps = prepare_computations(&intersections[0], &r, &intersections, None);
         assert_eq!(
-            w.reflected_color(&comps, 5),
+            w.reflected_color(&comps, 5, 1.0),
             Color::new(
                 0.19033220149513302,
                 0.23791525186891627,
@@ -335,6 +1448,43 @@ mod test {
         );
     }
 
+    #[test]
+    fn dim_reflections_are_pruned() {
+        // a floor and ceiling facing each other, a ray bouncing between them
+        // halves in importance with every reflection
+        let mut w = World::new();
+        w.light_sources.push(Box::new(PointLight::new(
+            Color::white(),
+            Tuple::point(0.0, 1.0, -10.0),
+        )));
+        for height in [0.0, 2.0] {
+            let mut mirror = Plane::new(Some(Matrix::translation(0.0, height, 0.0)));
+            mirror.material = Material::builder()
+                .ambient(0.2)
+                .diffuse(0.0)
+                .specular(0.0)
+                .reflective(0.5)
+                .build();
+            w.objects.push(Box::new(mirror));
+        }
+        let r = Ray::new(Tuple::point(0.0, 1.0, 0.0), Tuple::vector(0.0, -1.0, 0.0));
+
+        let full = w.color_at(&r, 5);
+        let g = 0.2 * (1.0 + 0.5 + 0.25 + 0.125 + 0.0625);
+        assert_eq!(full, Color::new(g, g, g));
+
+        // the default threshold changes nothing
+        w.min_importance = 0.0;
+        assert_eq!(w.color_at(&r, 5), full);
+
+        // the third reflection only carries an eighth of the pixel
+        w.min_importance = 0.2;
+        let pruned = w.color_at(&r, 5);
+        assert_eq!(pruned, w.color_at(&r, 3));
+        let g = 0.2 * (1.0 + 0.5 + 0.25);
+        assert_eq!(pruned, Color::new(g, g, g));
+    }
+
     #[test]
     fn shade_hit_with_reflective_material() {
         let mut w = populated_world();
@@ -359,7 +1509,7 @@ mod test {
         let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0., 0., 1.));
         let xs = w.objects.first().unwrap().intersect(&r);
         let comps = prepare_computations(&xs[0], &r, &xs, None);
-        let c = w.refracted_color(&comps, 5);
+        let c = w.refracted_color(&comps, 5, 1.0);
         assert_eq!(c, Color::black());
     }
 
@@ -378,7 +1528,7 @@ mod test {
 
         let xs = w.objects[0].intersect(&r);
         let comps = prepare_computations(&xs[1], &r, &xs, None);
-        let c = w.refracted_color(&comps, 5);
+        let c = w.refracted_color(&comps, 5, 1.0);
         assert_eq!(c, Color::black());
     }
 
@@ -406,6 +1556,287 @@ mod test {
         assert_eq!(color, Color::new(0.93642, 0.68642, 0.68642));
     }
 
+    #[test]
+    fn double_sided_triangle_lit_from_behind() {
+        let shade_back_face = |double_sided| {
+            let mut t = Triangle::new(
+                Tuple::point(0.0, 1.0, 0.0),
+                Tuple::point(-1.0, 0.0, 0.0),
+                Tuple::point(1.0, 0.0, 0.0),
+                None,
+            );
+            t.material.double_sided = double_sided;
+
+            // the light faces the front of the triangle while the eye looks at its back
+            let mut w = World::new();
+            w.light_sources.push(Box::new(PointLight::new(
+                Color::white(),
+                Tuple::point(0.0, 0.5, -10.0),
+            )));
+            w.objects.push(Box::new(t));
+
+            let r = Ray::new(Tuple::point(0.0, 0.5, 5.0), Tuple::vector(0.0, 0.0, -1.0));
+            w.color_at(&r, 5)
+        };
+
+        assert_eq!(shade_back_face(false), Color::new(0.1, 0.1, 0.1));
+        assert_eq!(shade_back_face(true), Color::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn clamping_summed_lighting() {
+        let mut w = populated_world();
+        w.light_sources.push(Box::new(PointLight::new(
+            Color::new(3.0, 3.0, 3.0),
+            Tuple::point(-10.0, 10.0, -10.0),
+        )));
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+
+        // one white light gives (0.38066, 0.47583, 0.2855) so four times that overflows
+        assert_eq!(w.color_at(&r, 5), Color::new(1.52264, 1.9033, 1.14198));
+
+        w.clamp_lighting = true;
+        assert_eq!(w.color_at(&r, 5), Color::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn frozen_world_matches_unfrozen() {
+        let rays = [
+            Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0)),
+            Ray::new(Tuple::point(0.0, 0.0, 0.75), Tuple::vector(0.0, 0.0, -1.0)),
+            Ray::new(Tuple::point(0.5, 0.9, -5.0), Tuple::vector(0.0, 0.0, 1.0)),
+            Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 1.0, 0.0)),
+        ];
+
+        let frozen = populated_world().freeze();
+        let w = populated_world();
+        for r in &rays {
+            assert_eq!(frozen.color_at(r, 5), w.color_at(r, 5));
+        }
+    }
+
+    #[test]
+    fn frozen_world_with_nested_groups() {
+        let build = || {
+            let mut w = populated_world();
+            let mut mesh = String::new();
+            for i in 0..10 {
+                let x = i as f64 * 0.3 - 1.5;
+                mesh += &format!("v {} -1 -2\nv {} -1 -2\nv {} 1 -2\n", x, x + 0.25, x);
+                mesh += &format!("f {} {} {}\n", i * 3 + 1, i * 3 + 2, i * 3 + 3);
+            }
+            w.objects.push(Box::new(parse_obj_file(
+                &mesh,
+                Some(Matrix::rotation_y(PI / 6.0)),
+                None,
+            )));
+            w
+        };
+
+        let w = build();
+        let frozen = build().freeze();
+
+        let mut mesh_hits = 0;
+        for i in 0..60 {
+            let r = Ray::new(
+                Tuple::point(i as f64 * 0.05 - 1.5, 0.1, -5.0),
+                Tuple::vector(0.0, 0.0, 1.0),
+            );
+            let xs = frozen.intersect_world(&r);
+            if xs.iter().any(|i| i.shape.as_any().is::<Triangle>()) {
+                mesh_hits += 1;
+            }
+            assert_eq!(frozen.color_at(&r, 5), w.color_at(&r, 5));
+        }
+        assert!(mesh_hits > 0);
+
+        let camera = || {
+            Camera::new_with_transform(
+                11,
+                11,
+                PI / 2.0,
+                view_transform(
+                    Tuple::point(0.0, 0.0, -5.0),
+                    Tuple::point(0.0, 0.0, 0.0),
+                    Tuple::vector(0.0, 1.0, 0.0),
+                ),
+            )
+        };
+        let expected = render(camera(), w, 1);
+        // a frozen world can be rendered more than once
+        for _ in 0..2 {
+            let image = render_frozen(camera(), &frozen, 2);
+            for y in 0..11 {
+                for x in 0..11 {
+                    assert_eq!(image.get_pixel(x, y), expected.get_pixel(x, y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn thin_cylinder_is_partly_covered_by_wide_ray() {
+        let mut w = World::new();
+        w.light_sources.push(Box::new(PointLight::new(
+            Color::white(),
+            Tuple::point(-10.0, 10.0, -10.0),
+        )));
+        let mut wire = Cylinder::new(Some(Matrix::scaling(0.001, 1.0, 0.001)));
+        wire.material.ambient = 1.0;
+        wire.material.diffuse = 0.0;
+        wire.material.specular = 0.0;
+        w.objects.push(Box::new(wire));
+
+        let center = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let beside = Ray::new(Tuple::point(0.02, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+
+        // an ideal ray either hits the wire or misses it
+        assert_eq!(w.color_at(&center, 5), Color::white());
+        assert_eq!(w.color_at(&beside, 5), Color::black());
+
+        // after 5 units a ray growing 0.01 per unit has a radius of 0.05, so the wire
+        // 0.002 across fills 0.002 / 0.1 = 0.02 of its footprint and the rest is black
+        let c = w.color_at(&center.with_width(0.01), 5);
+        assert_eq!(c, Color::new(0.02, 0.02, 0.02));
+
+        // a wide ray whose center misses the wire still has it inside its footprint
+        let beside = beside.with_width(0.01);
+        let xs = w.objects[0].intersect(&beside);
+        assert_eq!(xs.len(), 1);
+        assert!(f64_eq(xs[0].coverage, 0.02));
+        assert_eq!(w.color_at(&beside, 5), Color::new(0.02, 0.02, 0.02));
+    }
+
+    #[test]
+    fn rendering_an_empty_world() {
+        let check_background = |w: World| {
+            let image = render(Camera::new(160, 120, PI / 3.0), w, 6);
+            assert_eq!(image.width, 160);
+            assert_eq!(image.height, 120);
+            for y in 0..image.height {
+                for x in 0..image.width {
+                    assert_eq!(image.get_pixel(x, y), Color::black());
+                }
+            }
+        };
+
+        check_background(World::new());
+
+        let mut w = World::new();
+        w.light_sources.push(Box::new(PointLight::new(
+            Color::white(),
+            Tuple::point(-10.0, 10.0, -10.0),
+        )));
+        check_background(w);
+    }
+
+    // a sphere which counts how often it is intersected
+    struct CountingSphere {
+        sphere: Sphere,
+        calls: AtomicUsize,
+    }
+
+    impl Intersectable for CountingSphere {
+        fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            self.sphere
+                .local_intersect(ray)
+                .iter()
+                .map(|i| Intersection::new(self, i.t))
+                .collect()
+        }
+        fn local_normal_at(&self, point: Tuple, hit: Intersection) -> Tuple {
+            self.sphere.local_normal_at(point, hit)
+        }
+        fn get_material(&self) -> &Material {
+            self.sphere.get_material()
+        }
+        fn get_material_mut(&mut self) -> &mut Material {
+            self.sphere.get_material_mut()
+        }
+        fn set_material(&mut self, mat: Material) {
+            self.sphere.set_material(mat)
+        }
+        fn get_transform(&self) -> &Matrix {
+            self.sphere.get_transform()
+        }
+        fn get_inverse_transform(&self) -> &Matrix {
+            self.sphere.get_inverse_transform()
+        }
+        fn get_inverse_transform_transpose(&self) -> &Matrix {
+            self.sphere.get_inverse_transform_transpose()
+        }
+        fn get_id(&self) -> usize {
+            self.sphere.get_id()
+        }
+        fn get_parent_id(&self) -> Option<usize> {
+            self.sphere.get_parent_id()
+        }
+        fn set_parent_id(&mut self, id: usize) {
+            self.sphere.set_parent_id(id)
+        }
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+        fn bounds(&self) -> BoundingBox {
+            self.sphere.bounds()
+        }
+    }
+
+    #[test]
+    fn any_hit_before_matches_closest_hit() {
+        let w = populated_world();
+        let light = &w.light_sources[0];
+        let points = [
+            Tuple::point(0.0, 10.0, 0.0),
+            Tuple::point(10.0, -10.0, 10.0),
+            Tuple::point(-20.0, 20.0, -20.0),
+            Tuple::point(-2.0, 2.0, -2.0),
+            Tuple::point(0.0, 0.0, 5.0),
+            Tuple::point(0.5, 0.5, 0.5),
+        ];
+
+        for point in points {
+            let (direction, distance) = light.shadow_samples(point, w.seed)[0];
+            let r = Ray::new(point, direction);
+            let closest = match hit(&w.intersect_world(&r)) {
+                Some(h) => h.t < distance,
+                None => false,
+            };
+            assert_eq!(w.any_hit_before(&r, distance), closest);
+            assert_eq!(w.is_shadowed(light.as_ref(), &point), closest);
+        }
+    }
+
+    #[test]
+    fn any_hit_before_stops_at_first_hit() {
+        let mut w = World::new();
+        for z in [0.0, 3.0, 6.0] {
+            w.objects.push(Box::new(CountingSphere {
+                sphere: Sphere::new(Some(Matrix::translation(0.0, 0.0, z))),
+                calls: AtomicUsize::new(0),
+            }));
+        }
+        let calls = |w: &World, i: usize| {
+            w.objects[i]
+                .as_any()
+                .downcast_ref::<CountingSphere>()
+                .unwrap()
+                .calls
+                .load(Ordering::SeqCst)
+        };
+
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert!(w.any_hit_before(&r, 100.0));
+        assert_eq!(calls(&w, 0), 1);
+        assert_eq!(calls(&w, 1), 0);
+        assert_eq!(calls(&w, 2), 0);
+
+        // nothing in front of the first sphere so every object is checked
+        assert!(!w.any_hit_before(&r, 3.0));
+        assert_eq!(calls(&w, 2), 1);
+    }
+
     #[test]
     fn schlick_test() {
         let s = Sphere::new_glass_sphere(None);
@@ -439,4 +1870,58 @@ mod test {
         let color = w.shade_hit(&comps, 5);
         assert_eq!(color, Color::new(0.93391, 0.69643, 0.69243));
     }
+
+    #[test]
+    fn distant_textures_sample_coarser_mips() {
+        // a checker board one texel per square, any averaging turns it grey
+        let mut ppm = b"P3 64 64 1".to_vec();
+        for i in 0..64 * 64 {
+            ppm.extend(if (i % 64 + i / 64) % 2 == 0 {
+                b" 0 0 0"
+            } else {
+                b" 1 1 1"
+            });
+        }
+        let texture = TextureMap::from_ppm(&ppm).unwrap();
+
+        let looking_down_from = |height: f64, texture: &TextureMap| {
+            let mut w = World::new();
+            w.light_sources.push(Box::new(PointLight::new(
+                Color::white(),
+                Tuple::point(0.0, 10.0, 0.0),
+            )));
+            let mut floor = Plane::new(None);
+            floor.material = Material::builder()
+                .ambient(1.0)
+                .diffuse(0.0)
+                .specular(0.0)
+                .pattern(Box::new(UvPattern::new(
+                    planar_map,
+                    Box::new(texture.clone()),
+                )))
+                .build();
+            w.objects.push(Box::new(floor));
+
+            let ray = Ray::new(
+                Tuple::point(0.3, height, 0.3),
+                Tuple::vector(0.0, -1.0, 0.0),
+            )
+            .with_width(0.001);
+            w.color_at(&ray, 5)
+        };
+        let is_a_texel = |c: Color| c == Color::black() || c == Color::white();
+        let mipmapped = texture.clone().with_mipmaps();
+
+        assert!(is_a_texel(looking_down_from(1.0, &mipmapped)));
+        assert_eq!(
+            looking_down_from(100.0, &mipmapped),
+            Color::new(0.5, 0.5, 0.5)
+        );
+
+        // without mipmaps the far ray still lands on a single texel
+        assert_eq!(
+            looking_down_from(100.0, &texture),
+            looking_down_from(1.0, &texture)
+        );
+    }
 }
@@ -0,0 +1,351 @@
+use crate::math::{matrix::Matrix, ray::Ray, tuples::Tuple, utils::EPSILON};
+
+/*
+    An axis aligned box, shapes report one of these in their own object space
+    so groups can skip testing their children when a ray misses them all
+*/
+#[derive(Clone, Copy, Debug)]
+pub struct BoundingBox {
+    pub min: Tuple,
+    pub max: Tuple,
+}
+
+impl BoundingBox {
+    pub fn new(min: Tuple, max: Tuple) -> BoundingBox {
+        assert!(min.is_point());
+        assert!(max.is_point());
+        BoundingBox { min, max }
+    }
+
+    // a box containing nothing, adding anything to it gives back the added bounds
+    pub fn empty() -> BoundingBox {
+        BoundingBox {
+            min: Tuple::point(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Tuple::point(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn infinite() -> BoundingBox {
+        BoundingBox {
+            min: Tuple::point(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+            max: Tuple::point(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn is_finite(&self) -> bool {
+        [
+            self.min.x, self.min.y, self.min.z, self.max.x, self.max.y, self.max.z,
+        ]
+        .iter()
+        .all(|v| v.is_finite())
+    }
+
+    pub fn add_point(&mut self, point: Tuple) {
+        self.min.x = self.min.x.min(point.x);
+        self.min.y = self.min.y.min(point.y);
+        self.min.z = self.min.z.min(point.z);
+        self.max.x = self.max.x.max(point.x);
+        self.max.y = self.max.y.max(point.y);
+        self.max.z = self.max.z.max(point.z);
+    }
+
+    pub fn merge(&mut self, other: &BoundingBox) {
+        if !other.is_empty() {
+            self.add_point(other.min);
+            self.add_point(other.max);
+        }
+    }
+
+    pub fn contains_point(&self, point: Tuple) -> bool {
+        point.x >= self.min.x
+            && point.x <= self.max.x
+            && point.y >= self.min.y
+            && point.y <= self.max.y
+            && point.z >= self.min.z
+            && point.z <= self.max.z
+    }
+
+    pub fn contains_box(&self, other: &BoundingBox) -> bool {
+        self.contains_point(other.min) && self.contains_point(other.max)
+    }
+
+    pub fn center(&self) -> Tuple {
+        Tuple::point(
+            (self.min.x + self.max.x) / 2.0,
+            (self.min.y + self.max.y) / 2.0,
+            (self.min.z + self.max.z) / 2.0,
+        )
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    /*
+        The box containing all eight corners of this box after being transformed,
+        an axis which picks up an infinite extent through a rotation becomes unbounded
+    */
+    pub fn transform(&self, m: &Matrix) -> BoundingBox {
+        if self.is_empty() {
+            return *self;
+        }
+
+        let mut result = BoundingBox::empty();
+        for &x in &[self.min.x, self.max.x] {
+            for &y in &[self.min.y, self.max.y] {
+                for &z in &[self.min.z, self.max.z] {
+                    let corner = [x, y, z, 1.0];
+                    let mut transformed = [0.0; 3];
+                    for (i, value) in transformed.iter_mut().enumerate() {
+                        // skip zero entries so an infinite extent is not multiplied by zero
+                        *value = (0..4)
+                            .filter(|&j| m.get(i, j) != 0.0)
+                            .map(|j| m.get(i, j) * corner[j])
+                            .sum();
+                    }
+
+                    let mut point = Tuple::point(transformed[0], transformed[1], transformed[2]);
+                    if point.x.is_nan() {
+                        result.min.x = f64::NEG_INFINITY;
+                        point.x = f64::INFINITY;
+                    }
+                    if point.y.is_nan() {
+                        result.min.y = f64::NEG_INFINITY;
+                        point.y = f64::INFINITY;
+                    }
+                    if point.z.is_nan() {
+                        result.min.z = f64::NEG_INFINITY;
+                        point.z = f64::INFINITY;
+                    }
+                    result.add_point(point);
+                }
+            }
+        }
+
+        result
+    }
+
+    /*
+        Split the box in half along its longest axis
+    */
+    pub fn split(&self) -> (BoundingBox, BoundingBox) {
+        let d = self.max - self.min;
+        let mut mid_min = self.min;
+        let mut mid_max = self.max;
+
+        if d.x >= d.y && d.x >= d.z {
+            mid_min.x = self.min.x + d.x / 2.0;
+            mid_max.x = mid_min.x;
+        } else if d.y >= d.z {
+            mid_min.y = self.min.y + d.y / 2.0;
+            mid_max.y = mid_min.y;
+        } else {
+            mid_min.z = self.min.z + d.z / 2.0;
+            mid_max.z = mid_min.z;
+        }
+
+        (
+            BoundingBox::new(self.min, mid_max),
+            BoundingBox::new(mid_min, self.max),
+        )
+    }
+
+    /*
+        Slab test, the same approach the cube uses but against arbitrary extents
+    */
+    pub fn intersects(&self, ray: &Ray) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        let x = check_axis(ray.origin.x, ray.direction.x, self.min.x, self.max.x);
+        let y = check_axis(ray.origin.y, ray.direction.y, self.min.y, self.max.y);
+        let z = check_axis(ray.origin.z, ray.direction.z, self.min.z, self.max.z);
+
+        let tmin = [x.0, y.0, z.0].iter().copied().fold(f64::NAN, f64::max);
+        let tmax = [x.1, y.1, z.1].iter().copied().fold(f64::NAN, f64::min);
+
+        // flat boxes around triangles need a little slack for rays through their edges
+        tmin <= tmax + EPSILON
+    }
+}
+
+fn check_axis(origin: f64, direction: f64, min: f64, max: f64) -> (f64, f64) {
+    let tmin_numerator = min - origin;
+    let tmax_numerator = max - origin;
+
+    let mut tmin;
+    let mut tmax;
+    if direction.abs() >= EPSILON {
+        tmin = tmin_numerator / direction;
+        tmax = tmax_numerator / direction;
+    } else {
+        tmin = tmin_numerator * f64::INFINITY;
+        tmax = tmax_numerator * f64::INFINITY;
+    }
+
+    if tmin > tmax {
+        std::mem::swap(&mut tmin, &mut tmax);
+    }
+    (tmin, tmax)
+}
+
+#[cfg(test)]
+mod test {
+    use std::f64::consts::{FRAC_PI_4, SQRT_2};
+
+    use crate::{
+        math::utils::f64_eq,
+        shapes::{
+            cone::Cone, cube::Cube, cylinder::Cylinder, intersect::Intersectable, plane::Plane,
+            sphere::Sphere, triangle::Triangle,
+        },
+    };
+
+    use super::*;
+
+    #[test]
+    fn adding_points_and_boxes() {
+        let mut b = BoundingBox::empty();
+        assert!(b.is_empty());
+        b.add_point(Tuple::point(-5.0, 2.0, 0.0));
+        b.add_point(Tuple::point(7.0, 0.0, -3.0));
+        assert_eq!(b.min, Tuple::point(-5.0, 0.0, -3.0));
+        assert_eq!(b.max, Tuple::point(7.0, 2.0, 0.0));
+
+        let mut other =
+            BoundingBox::new(Tuple::point(8.0, -7.0, -2.0), Tuple::point(14.0, 4.0, 8.0));
+        other.merge(&b);
+        assert_eq!(other.min, Tuple::point(-5.0, -7.0, -3.0));
+        assert_eq!(other.max, Tuple::point(14.0, 4.0, 8.0));
+        assert!(other.contains_box(&b));
+        assert!(!b.contains_box(&other));
+    }
+
+    #[test]
+    fn bounds_of_each_shape() {
+        let unit = |b: BoundingBox, min: Tuple, max: Tuple| {
+            assert_eq!(b.min, min);
+            assert_eq!(b.max, max);
+        };
+        let (lo, hi) = (Tuple::point(-1.0, -1.0, -1.0), Tuple::point(1.0, 1.0, 1.0));
+        unit(Sphere::new(None).bounds(), lo, hi);
+        unit(Cube::new(None).bounds(), lo, hi);
+
+        let plane = Plane::new(None).bounds();
+        assert!(plane.min.x.is_infinite() && plane.max.z.is_infinite());
+        assert_eq!((plane.min.y, plane.max.y), (0.0, 0.0));
+
+        // open ended cylinders and cones go on forever
+        assert!(!Cylinder::new(None).bounds().is_finite());
+        assert!(!Cone::new(None).bounds().is_finite());
+        let mut cylinder = Cylinder::new(None);
+        cylinder.minimum = -5.0;
+        cylinder.maximum = 3.0;
+        unit(
+            cylinder.bounds(),
+            Tuple::point(-1.0, -5.0, -1.0),
+            Tuple::point(1.0, 3.0, 1.0),
+        );
+        let mut cone = Cone::new(None);
+        cone.minimum = -5.0;
+        cone.maximum = 3.0;
+        unit(
+            cone.bounds(),
+            Tuple::point(-5.0, -5.0, -5.0),
+            Tuple::point(5.0, 3.0, 5.0),
+        );
+
+        let triangle = Triangle::new(
+            Tuple::point(-3.0, 7.0, 2.0),
+            Tuple::point(6.0, 2.0, -4.0),
+            Tuple::point(2.0, -1.0, -1.0),
+            None,
+        );
+        unit(
+            triangle.bounds(),
+            Tuple::point(-3.0, -1.0, -4.0),
+            Tuple::point(6.0, 7.0, 2.0),
+        );
+
+        // the corners are carried through the shape's own transform
+        let sphere = Sphere::new(Some(
+            &Matrix::translation(1.0, -3.0, 5.0) * &Matrix::scaling(0.5, 2.0, 4.0),
+        ));
+        unit(
+            sphere.parent_space_bounds(),
+            Tuple::point(0.5, -5.0, 1.0),
+            Tuple::point(1.5, -1.0, 9.0),
+        );
+    }
+
+    #[test]
+    fn transforming_a_box() {
+        let b = BoundingBox::new(Tuple::point(-1.0, -1.0, -1.0), Tuple::point(1.0, 1.0, 1.0));
+        let m = &Matrix::rotation_x(FRAC_PI_4) * &Matrix::rotation_y(FRAC_PI_4);
+        let t = b.transform(&m);
+        assert_eq!(t.min, Tuple::point(-SQRT_2, -1.70711, -1.70711));
+        assert_eq!(t.max, Tuple::point(SQRT_2, 1.70711, 1.70711));
+    }
+
+    #[test]
+    fn transforming_an_infinite_box() {
+        let plane = BoundingBox::new(
+            Tuple::point(f64::NEG_INFINITY, 0.0, f64::NEG_INFINITY),
+            Tuple::point(f64::INFINITY, 0.0, f64::INFINITY),
+        );
+
+        let moved = plane.transform(&Matrix::translation(0.0, -1.0, 0.0));
+        assert!(f64_eq(moved.min.y, -1.0) && f64_eq(moved.max.y, -1.0));
+        assert!(moved.min.x.is_infinite() && moved.max.z.is_infinite());
+
+        let tilted = plane.transform(&Matrix::rotation_z(FRAC_PI_4));
+        assert!(!tilted.is_finite());
+        assert!(!tilted.min.x.is_nan() && !tilted.max.y.is_nan());
+    }
+
+    #[test]
+    fn splitting_a_box() {
+        let b = BoundingBox::new(Tuple::point(-1.0, -2.0, -3.0), Tuple::point(9.0, 5.5, 3.0));
+        let (left, right) = b.split();
+        assert_eq!(left.min, Tuple::point(-1.0, -2.0, -3.0));
+        assert_eq!(left.max, Tuple::point(4.0, 5.5, 3.0));
+        assert_eq!(right.min, Tuple::point(4.0, -2.0, -3.0));
+        assert_eq!(right.max, Tuple::point(9.0, 5.5, 3.0));
+    }
+
+    #[test]
+    fn intersecting_a_ray_with_a_box() {
+        let b = BoundingBox::new(Tuple::point(5.0, -2.0, 0.0), Tuple::point(11.0, 4.0, 7.0));
+        let hits = [
+            (Tuple::point(15.0, 1.0, 2.0), Tuple::vector(-1.0, 0.0, 0.0)),
+            (Tuple::point(8.0, 6.0, 1.0), Tuple::vector(0.0, -1.0, 0.0)),
+            (Tuple::point(8.0, -1.0, -8.0), Tuple::vector(0.1, 0.2, 1.0)),
+            (Tuple::point(8.0, 2.0, 12.0), Tuple::vector(0.0, 0.0, -1.0)),
+        ];
+        let misses = [
+            (Tuple::point(9.0, -1.0, -8.0), Tuple::vector(-2.0, 0.0, 0.0)),
+            (Tuple::point(18.0, 0.0, 12.0), Tuple::vector(0.0, 0.0, -1.0)),
+            (Tuple::point(12.0, 5.0, 4.0), Tuple::vector(0.0, 1.0, 0.0)),
+        ];
+
+        for (origin, direction) in hits {
+            assert!(b.intersects(&Ray::new(origin, direction.normalize())));
+        }
+        for (origin, direction) in misses {
+            assert!(!b.intersects(&Ray::new(origin, direction.normalize())));
+        }
+        assert!(!BoundingBox::empty().intersects(&Ray::new(
+            Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 0.0, 1.0)
+        )));
+    }
+}
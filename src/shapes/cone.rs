@@ -1,11 +1,14 @@
-use std::sync::atomic::Ordering;
+use std::{any::Any, sync::atomic::Ordering};
 
 use crate::{
     draw::material::Material,
     math::{matrix::Matrix, ray::Ray, tuples::Tuple, utils::EPSILON},
 };
 
-use super::intersect::{Intersectable, Intersection, OBJECT_COUNTER};
+use super::{
+    bounds::BoundingBox,
+    intersect::{Intersectable, Intersection, OBJECT_COUNTER},
+};
 
 pub struct Cone {
     id: usize,
@@ -16,6 +19,7 @@ pub struct Cone {
     pub material: Material,
     pub minimum: f64, // bottom cone cutoff
     pub maximum: f64, // top cone cutoff
+    pub radius: f64,  // radius at a height of one, how quickly the cone widens
     pub closed: bool, // wether not not to cap the cone
 }
 
@@ -25,9 +29,7 @@ impl Cone {
         let matrices = match transform {
             Some(matrix) => {
                 assert_eq!(matrix.size, 4);
-                let inverse = matrix.inverse();
-                let mut inv_transpose = matrix.inverse();
-                inv_transpose.transpose();
+                let (inverse, inv_transpose) = matrix.inverse_and_transpose();
                 (matrix, inverse, inv_transpose)
             }
             None => (
@@ -45,12 +47,13 @@ impl Cone {
             id,
             minimum: f64::NEG_INFINITY,
             maximum: f64::INFINITY,
+            radius: 1.0,
             closed: false,
             parent: None,
         }
     }
 
-    fn intersect_caps(&self, ray: &Ray) -> Vec<Intersection> {
+    fn intersect_caps(&self, ray: &Ray) -> Vec<Intersection<'_>> {
         // if there are not caps to intersect or the ray is vertical, we have nothing to do
         if !self.closed || ray.direction.y.abs() < EPSILON {
             return vec![];
@@ -60,13 +63,13 @@ impl Cone {
 
         // check for an intersection at the bottom cap
         let t0 = (self.minimum - ray.origin.y) / ray.direction.y;
-        if check_cap(ray, t0, self.minimum) {
+        if check_cap(ray, t0, self.radius * self.minimum) {
             xs.push(Intersection::new(self, t0));
         }
 
         // check for an intersection at the top cap
         let t1 = (self.maximum - ray.origin.y) / ray.direction.y;
-        if check_cap(ray, t1, self.maximum) {
+        if check_cap(ray, t1, self.radius * self.maximum) {
             xs.push(Intersection::new(self, t1));
         }
 
@@ -82,11 +85,12 @@ fn check_cap(ray: &Ray, t: f64, radius: f64) -> bool {
 }
 
 impl Intersectable for Cone {
-    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection> {
-        let a = ray.direction.x.powi(2) - ray.direction.y.powi(2) + ray.direction.z.powi(2);
-        let b = 2.0 * ray.direction.x * ray.origin.x - 2.0 * ray.direction.y * ray.origin.y
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        let k = self.radius.powi(2);
+        let a = ray.direction.x.powi(2) - k * ray.direction.y.powi(2) + ray.direction.z.powi(2);
+        let b = 2.0 * ray.direction.x * ray.origin.x - 2.0 * k * ray.direction.y * ray.origin.y
             + 2.0 * ray.direction.z * ray.origin.z;
-        let c = ray.origin.x.powi(2) - ray.origin.y.powi(2) + ray.origin.z.powi(2);
+        let c = ray.origin.x.powi(2) - k * ray.origin.y.powi(2) + ray.origin.z.powi(2);
 
         let mut intersects = vec![];
 
@@ -124,12 +128,14 @@ impl Intersectable for Cone {
     fn local_normal_at(&self, object_point: Tuple, _: Intersection) -> Tuple {
         let dist = object_point.x.powi(2) + object_point.z.powi(2);
 
-        if dist < 1.0 && object_point.y >= self.maximum - EPSILON {
+        let radius2 = self.radius.powi(2);
+
+        if dist < radius2 && object_point.y >= self.maximum - EPSILON {
             Tuple::vector(0.0, 1.0, 0.0)
-        } else if dist < 1.0 && object_point.y <= self.minimum + EPSILON {
+        } else if dist < radius2 && object_point.y <= self.minimum + EPSILON {
             Tuple::vector(0.0, -1.0, 0.0)
         } else {
-            let mut y = dist.sqrt();
+            let mut y = dist.sqrt() * self.radius;
             if object_point.y > 0.0 {
                 y *= -1.0;
             }
@@ -141,6 +147,10 @@ impl Intersectable for Cone {
         &self.material
     }
 
+    fn get_material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
     fn get_transform(&self) -> &Matrix {
         &self.transform
     }
@@ -168,6 +178,18 @@ impl Intersectable for Cone {
     fn set_material(&mut self, mat: Material) {
         self.material = mat;
     }
+
+    fn bounds(&self) -> BoundingBox {
+        let limit = self.minimum.abs().max(self.maximum.abs()) * self.radius;
+        BoundingBox::new(
+            Tuple::point(-limit, self.minimum, -limit),
+            Tuple::point(limit, self.maximum, limit),
+        )
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 #[cfg(test)]
@@ -178,19 +200,19 @@ mod test {
 
     #[test]
     fn intersecting_cone() {
-        let origins = vec![
+        let origins = [
             Tuple::point(0.0, 0.0, -5.0),
             Tuple::point(0.0, 0.0, -5.0),
             Tuple::point(1.0, 1.0, -5.0),
         ];
 
-        let direction = vec![
+        let direction = [
             Tuple::vector(0.0, 0.0, 1.0),
             Tuple::vector(1.0, 1.0, 1.0),
             Tuple::vector(-0.5, -1.0, 1.0),
         ];
 
-        let ans = vec![(5.0, 5.0), (8.66025, 8.66025), (4.55006, 49.449944)];
+        let ans = [(5.0, 5.0), (8.66025, 8.66025), (4.55006, 49.449944)];
 
         let cone = Cone::new(None);
 
@@ -215,19 +237,19 @@ mod test {
 
     #[test]
     fn intersecting_end_caps() {
-        let origins = vec![
+        let origins = [
             Tuple::point(0.0, 0.0, -5.0),
             Tuple::point(0.0, 0.0, -0.25),
             Tuple::point(0.0, 0.0, -0.25),
         ];
 
-        let direction = vec![
+        let direction = [
             Tuple::vector(0.0, 1.0, 0.0),
             Tuple::vector(0.0, 1.0, 1.0),
             Tuple::vector(0.0, 1.0, 0.0),
         ];
 
-        let ans = vec![0, 2, 4];
+        let ans = [0, 2, 4];
 
         let mut cone = Cone::new(None);
         cone.closed = true;
@@ -242,15 +264,38 @@ mod test {
         }
     }
 
+    #[test]
+    fn wider_cone() {
+        let mut cone = Cone::new(None);
+        cone.radius = 2.0;
+
+        // at a height of one the cone is now two wide
+        let ray = Ray::new(Tuple::point(0.0, 1.0, 0.0), Tuple::vector(1.0, 0.0, 0.0));
+        let xs = cone.intersect(&ray);
+        assert_eq!(xs.len(), 2);
+        assert!(f64_eq(xs[0].t, -2.0) && f64_eq(xs[1].t, 2.0));
+
+        let n = cone.local_normal_at(Tuple::point(2.0, 1.0, 0.0), xs[1]);
+        assert_eq!(n, Tuple::vector(2.0, -4.0, 0.0));
+
+        cone.minimum = -1.0;
+        cone.maximum = 1.0;
+        cone.closed = true;
+        // both caps are two wide, the sides are crossed at y = 0.75 and y = -0.75
+        let down = Ray::new(Tuple::point(1.5, 3.0, 0.0), Tuple::vector(0.0, -1.0, 0.0));
+        assert_eq!(cone.intersect(&down).len(), 4);
+        assert_eq!(cone.bounds().max, Tuple::point(2.0, 1.0, 2.0));
+    }
+
     #[test]
     fn normal_works() {
-        let points = vec![
+        let points = [
             Tuple::point(0.0, 0.0, 0.0),
             Tuple::point(1.0, 1.0, 1.0),
             Tuple::point(-1.0, -1.0, 0.0),
         ];
 
-        let normals = vec![
+        let normals = [
             Tuple::vector(0.0, 0.0, 0.0),
             Tuple::vector(1.0, -(2.0_f64.sqrt()), 1.0),
             Tuple::vector(-1.0, 1.0, 0.0),
@@ -1,11 +1,14 @@
-use std::sync::atomic::Ordering;
+use std::{any::Any, sync::atomic::Ordering};
 
 use crate::{
     draw::material::Material,
     math::{matrix::Matrix, ray::Ray, tuples::Tuple, utils::EPSILON},
 };
 
-use super::intersect::{Intersectable, Intersection, OBJECT_COUNTER};
+use super::{
+    bounds::BoundingBox,
+    intersect::{Intersectable, Intersection, OBJECT_COUNTER},
+};
 
 pub struct Cube {
     id: usize,
@@ -22,9 +25,7 @@ impl Cube {
         let matrices = match transform {
             Some(matrix) => {
                 assert_eq!(matrix.size, 4);
-                let inverse = matrix.inverse();
-                let mut inv_transpose = matrix.inverse();
-                inv_transpose.transpose();
+                let (inverse, inv_transpose) = matrix.inverse_and_transpose();
                 (matrix, inverse, inv_transpose)
             }
             None => (
@@ -66,7 +67,7 @@ fn check_axis(origin: f64, direction: f64) -> (f64, f64) {
 }
 
 impl Intersectable for Cube {
-    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection> {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
         let x = check_axis(ray.origin.x, ray.direction.x);
         let y = check_axis(ray.origin.y, ray.direction.y);
         let z = check_axis(ray.origin.z, ray.direction.z);
@@ -104,6 +105,10 @@ impl Intersectable for Cube {
         &self.material
     }
 
+    fn get_material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
     fn get_transform(&self) -> &Matrix {
         &self.transform
     }
@@ -131,6 +136,14 @@ impl Intersectable for Cube {
     fn set_material(&mut self, mat: Material) {
         self.material = mat;
     }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::new(Tuple::point(-1.0, -1.0, -1.0), Tuple::point(1.0, 1.0, 1.0))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 #[cfg(test)]
@@ -142,7 +155,7 @@ mod test {
     #[test]
     fn intersect() {
         let c = Cube::new(None);
-        let rays = vec![
+        let rays = [
             Ray::new(Tuple::point(5.0, 0.5, 0.0), Tuple::vector(-1.0, 0.0, 0.0)),
             Ray::new(Tuple::point(-5.0, 0.5, 0.0), Tuple::vector(1.0, 0.0, 0.0)),
             Ray::new(Tuple::point(0.5, 5.0, 0.0), Tuple::vector(0.0, -1.0, 0.0)),
@@ -152,7 +165,7 @@ mod test {
             Ray::new(Tuple::point(0.0, 0.5, 0.0), Tuple::vector(0.0, 0.0, 1.0)),
         ];
 
-        let ts = vec![
+        let ts = [
             (4.0, 6.0),
             (4.0, 6.0),
             (4.0, 6.0),
@@ -173,7 +186,7 @@ mod test {
     #[test]
     fn intersection_miss() {
         let c = Cube::new(None);
-        let rays = vec![
+        let rays = [
             Ray::new(
                 Tuple::point(-2.0, 0.0, 0.0),
                 Tuple::vector(0.2673, 0.5345, 0.8018),
@@ -191,8 +204,8 @@ mod test {
             Ray::new(Tuple::point(2.0, 2.0, 0.0), Tuple::vector(-1.0, 0.0, 0.0)),
         ];
 
-        for i in 0..rays.len() {
-            let xs = c.intersect(&rays[i]);
+        for ray in &rays {
+            let xs = c.intersect(ray);
             assert!(xs.is_empty());
         }
     }
@@ -200,7 +213,7 @@ mod test {
     #[test]
     fn normal_test() {
         let c = Cube::new(None);
-        let points = vec![
+        let points = [
             Tuple::point(1., 0.5, -0.8),
             Tuple::point(-1., -0.2, 0.9),
             Tuple::point(-0.4, 1., -0.1),
@@ -211,7 +224,7 @@ mod test {
             Tuple::point(-1., -1., -1.),
         ];
 
-        let normals = vec![
+        let normals = [
             Tuple::vector(1., 0., 0.),
             Tuple::vector(-1., 0., 0.),
             Tuple::vector(0., 1., 0.),
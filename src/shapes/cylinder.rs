@@ -1,11 +1,25 @@
-use std::sync::atomic::Ordering;
+use std::{any::Any, sync::atomic::Ordering};
 
 use crate::{
     draw::material::Material,
     math::{matrix::Matrix, ray::Ray, tuples::Tuple, utils::EPSILON},
 };
 
-use super::intersect::{Intersectable, Intersection, OBJECT_COUNTER};
+use super::{
+    bounds::BoundingBox,
+    intersect::{Intersectable, Intersection, OBJECT_COUNTER},
+};
+
+/*
+    Which surfaces of the cylinder can be intersected, turning off the tube of a
+    closed cylinder while keeping the caps gives flat rings
+*/
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "cache", derive(serde::Serialize, serde::Deserialize))]
+pub struct CylinderParts {
+    pub tube: bool,
+    pub caps: bool,
+}
 
 pub struct Cylinder {
     id: usize,
@@ -14,9 +28,11 @@ pub struct Cylinder {
     inverse_transform_transpose: Matrix,
     pub parent: Option<usize>,
     pub material: Material,
-    pub minimum: f64, // bottom cylinder cutoff
-    pub maximum: f64, // top cylinder cutoff
-    pub closed: bool, // wether not not to cap the cylinder
+    pub minimum: f64,         // bottom cylinder cutoff
+    pub maximum: f64,         // top cylinder cutoff
+    pub radius: f64,          // distance from the y axis to the tube
+    pub closed: bool,         // wether not not to cap the cylinder
+    pub parts: CylinderParts, // which surfaces to render, an open cylinder has no caps
 }
 
 impl Cylinder {
@@ -25,9 +41,7 @@ impl Cylinder {
         let matrices = match transform {
             Some(matrix) => {
                 assert_eq!(matrix.size, 4);
-                let inverse = matrix.inverse();
-                let mut inv_transpose = matrix.inverse();
-                inv_transpose.transpose();
+                let (inverse, inv_transpose) = matrix.inverse_and_transpose();
                 (matrix, inverse, inv_transpose)
             }
             None => (
@@ -45,14 +59,19 @@ impl Cylinder {
             id,
             minimum: f64::NEG_INFINITY,
             maximum: f64::INFINITY,
+            radius: 1.0,
             closed: false,
+            parts: CylinderParts {
+                tube: true,
+                caps: true,
+            },
             parent: None,
         }
     }
 
-    fn intersect_caps(&self, ray: &Ray) -> Vec<Intersection> {
+    fn intersect_caps(&self, ray: &Ray) -> Vec<Intersection<'_>> {
         // if there are not caps to intersect or the ray is vertical, we have nothing to do
-        if !self.closed || ray.direction.y.abs() < EPSILON {
+        if !self.closed || !self.parts.caps || ray.direction.y.abs() < EPSILON {
             return vec![];
         }
 
@@ -60,38 +79,75 @@ impl Cylinder {
 
         // check for an intersection at the bottom cap
         let t0 = (self.minimum - ray.origin.y) / ray.direction.y;
-        if check_cap(ray, t0) {
+        if check_cap(ray, t0, self.radius) {
             xs.push(Intersection::new(self, t0));
         }
 
         // check for an intersection at the top cap
         let t1 = (self.maximum - ray.origin.y) / ray.direction.y;
-        if check_cap(ray, t1) {
+        if check_cap(ray, t1, self.radius) {
             xs.push(Intersection::new(self, t1));
         }
 
         xs
     }
+
+    /*
+        For a ray with a width find where it passes closest to the axis and how much of
+        the ray's footprint the tube covers there, None if the tube is not thinner than
+        the footprint or the footprint misses it entirely
+    */
+    fn tube_coverage(&self, ray: &Ray, a: f64) -> Option<(f64, f64)> {
+        if ray.width <= 0.0 {
+            return None;
+        }
+
+        let t = -(ray.origin.x * ray.direction.x + ray.origin.z * ray.direction.z) / a;
+        let y = ray.origin.y + t * ray.direction.y;
+        if t <= 0.0 || y <= self.minimum || y >= self.maximum {
+            return None;
+        }
+
+        let x = ray.origin.x + t * ray.direction.x;
+        let z = ray.origin.z + t * ray.direction.z;
+        let dist = (x * x + z * z).sqrt();
+        let radius = ray.width * t;
+
+        // compare the footprint of the ray across the axis with the diameter of the tube
+        let overlap = (dist + radius).min(self.radius) - (dist - radius).max(-self.radius);
+        let coverage = overlap / (2.0 * radius);
+        if overlap <= 0.0 || coverage >= 1.0 {
+            return None;
+        }
+
+        Some((t, coverage))
+    }
 }
 
 impl Intersectable for Cylinder {
-    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection> {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
         let a = ray.direction.x.powi(2) + ray.direction.z.powi(2);
 
-        // ray is parallel to the cylinder, could still intersect a cap however
-        if a.abs() < EPSILON {
+        // ray is parallel to the cylinder or there is no tube, could still intersect a cap however
+        if !self.parts.tube || a.abs() < EPSILON {
             return self.intersect_caps(ray);
         }
 
         let b = 2.0 * ray.origin.x * ray.direction.x + 2.0 * ray.origin.z * ray.direction.z;
 
-        let c = ray.origin.x.powi(2) + ray.origin.z.powi(2) - 1.0;
+        let c = ray.origin.x.powi(2) + ray.origin.z.powi(2) - self.radius.powi(2);
 
         let disc = b.powi(2) - 4.0 * a * c;
+        let coverage = self.tube_coverage(ray, a);
 
         // ray does not intersect cylinder
         if disc < 0.0 {
-            return vec![];
+            // but the footprint of a wide ray passing close by still partly covers it,
+            // which is a single hit where the ray passes closest to the axis
+            return match coverage {
+                Some((t, coverage)) => vec![Intersection::new(self, t).with_coverage(coverage)],
+                None => vec![],
+            };
         }
 
         let mut t0 = (-b - disc.sqrt()) / (2.0 * a);
@@ -113,6 +169,12 @@ impl Intersectable for Cylinder {
             surface_intersects.push(Intersection::new(self, t1));
         }
 
+        if let Some((_, coverage)) = coverage {
+            for i in surface_intersects.iter_mut() {
+                i.coverage = coverage;
+            }
+        }
+
         let mut cap_intersects = self.intersect_caps(ray);
         surface_intersects.append(&mut cap_intersects);
         surface_intersects
@@ -120,10 +182,11 @@ impl Intersectable for Cylinder {
 
     fn local_normal_at(&self, object_point: Tuple, _: Intersection) -> Tuple {
         let dist = object_point.x.powi(2) + object_point.z.powi(2);
+        let radius2 = self.radius.powi(2);
 
-        if dist < 1.0 && object_point.y >= self.maximum - EPSILON {
+        if dist < radius2 && object_point.y >= self.maximum - EPSILON {
             Tuple::vector(0.0, 1.0, 0.0)
-        } else if dist < 1.0 && object_point.y <= self.minimum + EPSILON {
+        } else if dist < radius2 && object_point.y <= self.minimum + EPSILON {
             Tuple::vector(0.0, -1.0, 0.0)
         } else {
             Tuple::vector(object_point.x, 0.0, object_point.z)
@@ -134,6 +197,10 @@ impl Intersectable for Cylinder {
         &self.material
     }
 
+    fn get_material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
     fn get_transform(&self) -> &Matrix {
         &self.transform
     }
@@ -161,13 +228,24 @@ impl Intersectable for Cylinder {
     fn set_material(&mut self, mat: Material) {
         self.material = mat;
     }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::new(
+            Tuple::point(-self.radius, self.minimum, -self.radius),
+            Tuple::point(self.radius, self.maximum, self.radius),
+        )
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
-fn check_cap(ray: &Ray, t: f64) -> bool {
+fn check_cap(ray: &Ray, t: f64, radius: f64) -> bool {
     let x = ray.origin.x + t * ray.direction.x;
     let z = ray.origin.z + t * ray.direction.z;
 
-    (x.powi(2) + z.powi(2)) <= 1.0
+    (x.powi(2) + z.powi(2)) <= radius.powi(2)
 }
 
 #[cfg(test)]
@@ -177,19 +255,19 @@ mod test {
         shapes::intersect::Intersectable,
     };
 
-    use super::Cylinder;
+    use super::{Cylinder, CylinderParts};
 
     #[test]
     fn ray_misses_cylinder() {
         let cyl = Cylinder::new(None);
 
-        let origin = vec![
+        let origin = [
             Tuple::point(1.0, 0.0, 0.0),
             Tuple::point(0.0, 0.0, 0.0),
             Tuple::point(0.0, 0.0, -5.0),
         ];
 
-        let direction = vec![
+        let direction = [
             Tuple::vector(0.0, 1.0, 0.0),
             Tuple::vector(0.0, 1.0, 0.0),
             Tuple::vector(1.0, 1.0, 1.0),
@@ -205,19 +283,19 @@ mod test {
 
     #[test]
     fn ray_intersects_cylinder() {
-        let origin = vec![
+        let origin = [
             Tuple::point(1.0, 0.0, -5.0),
             Tuple::point(0.0, 0.0, -5.0),
             Tuple::point(0.5, 0.0, -5.0),
         ];
 
-        let direction = vec![
+        let direction = [
             Tuple::vector(0.0, 0.0, 1.0),
             Tuple::vector(0.0, 0.0, 1.0),
             Tuple::vector(0.1, 1.0, 1.0),
         ];
 
-        let ts = vec![(5.0, 5.0), (4.0, 6.0), (6.80798, 7.08872)];
+        let ts = [(5.0, 5.0), (4.0, 6.0), (6.80798, 7.08872)];
 
         let cyl = Cylinder::new(None);
 
@@ -231,9 +309,34 @@ mod test {
         }
     }
 
+    #[test]
+    fn wider_cylinder() {
+        let mut cyl = Cylinder::new(None);
+        let ray = Ray::new(Tuple::point(0.0, 0.0, 0.0), Tuple::vector(1.0, 0.0, 0.0));
+        let xs = cyl.intersect(&ray);
+        assert!(f64_eq(xs[0].t, -1.0) && f64_eq(xs[1].t, 1.0));
+
+        // twice the radius puts the walls twice as far along x
+        cyl.radius = 2.0;
+        let xs = cyl.intersect(&ray);
+        assert_eq!(xs.len(), 2);
+        assert!(f64_eq(xs[0].t, -2.0) && f64_eq(xs[1].t, 2.0));
+
+        // and the caps grow with it
+        cyl.minimum = 1.0;
+        cyl.maximum = 2.0;
+        cyl.closed = true;
+        cyl.parts = CylinderParts {
+            tube: false,
+            caps: true,
+        };
+        let down = Ray::new(Tuple::point(1.5, 3.0, 0.0), Tuple::vector(0.0, -1.0, 0.0));
+        assert_eq!(cyl.intersect(&down).len(), 2);
+    }
+
     #[test]
     fn intersecting_constrained_cylinder() {
-        let points = vec![
+        let points = [
             Tuple::point(0.0, 1.5, 0.0),
             Tuple::point(0.0, 3.0, -5.0),
             Tuple::point(0.0, 0.0, -5.0),
@@ -241,7 +344,7 @@ mod test {
             Tuple::point(0.0, 1.0, -5.0),
         ];
 
-        let directions = vec![
+        let directions = [
             Tuple::vector(0.1, 1.0, 0.0),
             Tuple::vector(0.0, 0.0, 1.0),
             Tuple::vector(0.0, 0.0, 1.0),
@@ -268,7 +371,7 @@ mod test {
 
     #[test]
     fn intersecting_caps_of_closed_cylinder() {
-        let points = vec![
+        let points = [
             Tuple::point(0.0, 3.0, 0.0),
             Tuple::point(0.0, 3.0, -2.0),
             Tuple::point(0.0, 4.0, -2.0),
@@ -276,7 +379,7 @@ mod test {
             Tuple::point(0.0, -1.0, -2.0),
         ];
 
-        let directions = vec![
+        let directions = [
             Tuple::vector(0.0, -1.0, 0.0),
             Tuple::vector(0.0, -1.0, 2.0),
             Tuple::vector(0.0, -1.0, 1.0),
@@ -296,4 +399,26 @@ mod test {
             assert_eq!(xs.len(), 2);
         }
     }
+
+    #[test]
+    fn intersecting_caps_only() {
+        let mut cyl = Cylinder::new(None);
+        cyl.minimum = 1.0;
+        cyl.maximum = 2.0;
+        cyl.closed = true;
+        cyl.parts = CylinderParts {
+            tube: false,
+            caps: true,
+        };
+
+        let along_axis = Ray::new(Tuple::point(0.0, 3.0, 0.0), Tuple::vector(0.0, -1.0, 0.0));
+        let xs = cyl.intersect(&along_axis);
+        assert_eq!(xs.len(), 2);
+        assert!(f64_eq(xs[0].t, 2.0));
+        assert!(f64_eq(xs[1].t, 1.0));
+
+        // would hit the tube twice if it was present
+        let through_side = Ray::new(Tuple::point(0.0, 1.5, -2.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert!(cyl.intersect(&through_side).is_empty());
+    }
 }
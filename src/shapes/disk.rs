@@ -0,0 +1,171 @@
+use std::{any::Any, sync::atomic::Ordering};
+
+use crate::{
+    draw::material::Material,
+    math::{matrix::Matrix, ray::Ray, tuples::Tuple, utils::EPSILON},
+};
+
+use super::{
+    bounds::BoundingBox,
+    intersect::{Intersectable, Intersection, OBJECT_COUNTER},
+};
+
+/*
+    A flat circle in the xz plane facing up, a non zero inner radius
+    cuts a hole out of the middle to make a washer
+*/
+pub struct Disk {
+    id: usize,
+    transform: Matrix,
+    inverse_transform: Matrix,
+    inverse_transform_transpose: Matrix,
+    pub parent: Option<usize>,
+    pub material: Material,
+    pub inner_radius: f64, // radius of the hole in the middle, zero for a solid disk
+    pub outer_radius: f64,
+}
+
+impl Disk {
+    pub fn new(transform: Option<Matrix>) -> Disk {
+        let id = OBJECT_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let matrices = match transform {
+            Some(matrix) => {
+                assert_eq!(matrix.size, 4);
+                let (inverse, inv_transpose) = matrix.inverse_and_transpose();
+                (matrix, inverse, inv_transpose)
+            }
+            None => (
+                Matrix::identity(4),
+                Matrix::identity(4),
+                Matrix::identity(4),
+            ),
+        };
+
+        Self {
+            transform: matrices.0,
+            inverse_transform: matrices.1,
+            inverse_transform_transpose: matrices.2,
+            material: Material::default_material(),
+            id,
+            parent: None,
+            inner_radius: 0.0,
+            outer_radius: 1.0,
+        }
+    }
+}
+
+impl Intersectable for Disk {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        // the same as a plane, a ray parallel to the disk never meets it
+        if ray.direction.y.abs() < EPSILON {
+            return vec![];
+        }
+
+        let t = -ray.origin.y / ray.direction.y;
+        let x = ray.origin.x + t * ray.direction.x;
+        let z = ray.origin.z + t * ray.direction.z;
+        let radius2 = x * x + z * z;
+        if radius2 < self.inner_radius.powi(2) || radius2 > self.outer_radius.powi(2) {
+            return vec![];
+        }
+
+        vec![Intersection::new(self, t)]
+    }
+
+    fn local_normal_at(&self, _: Tuple, _: Intersection) -> Tuple {
+        Tuple::vector(0.0, 1.0, 0.0)
+    }
+
+    fn get_material(&self) -> &Material {
+        &self.material
+    }
+
+    fn get_material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    fn get_transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn get_inverse_transform(&self) -> &Matrix {
+        &self.inverse_transform
+    }
+
+    fn get_inverse_transform_transpose(&self) -> &Matrix {
+        &self.inverse_transform_transpose
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn get_parent_id(&self) -> Option<usize> {
+        self.parent
+    }
+
+    fn set_parent_id(&mut self, id: usize) {
+        self.parent = Some(id)
+    }
+
+    fn set_material(&mut self, mat: Material) {
+        self.material = mat;
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::new(
+            Tuple::point(-self.outer_radius, 0.0, -self.outer_radius),
+            Tuple::point(self.outer_radius, 0.0, self.outer_radius),
+        )
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn intersecting_a_disk() {
+        let down = Tuple::vector(0.0, -1.0, 0.0);
+        let disk = Disk::new(Some(Matrix::translation(0.0, 1.0, 0.0)));
+
+        let xs = disk.intersect(&Ray::new(Tuple::point(0.0, 3.0, 0.0), down));
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 2.0);
+        assert_eq!(
+            disk.normal_at(Tuple::point(0.5, 1.0, 0.0), xs[0], None),
+            Tuple::vector(0.0, 1.0, 0.0)
+        );
+
+        // past the rim, and parallel to the disk
+        assert!(disk
+            .intersect(&Ray::new(Tuple::point(0.8, 3.0, 0.8), down))
+            .is_empty());
+        let flat = Ray::new(Tuple::point(0.0, 1.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert!(disk.intersect(&flat).is_empty());
+    }
+
+    #[test]
+    fn rays_pass_through_the_hole_in_a_washer() {
+        let mut washer = Disk::new(None);
+        washer.inner_radius = 0.5;
+        washer.outer_radius = 2.0;
+        let down = Tuple::vector(0.0, -1.0, 0.0);
+
+        for (x, hits) in [
+            (0.0, false),
+            (0.4, false),
+            (0.5, true),
+            (1.5, true),
+            (2.1, false),
+        ] {
+            let xs = washer.intersect(&Ray::new(Tuple::point(x, 1.0, 0.0), down));
+            assert_eq!(xs.len() == 1, hits, "{}", x);
+        }
+        assert_eq!(washer.bounds().max, Tuple::point(2.0, 0.0, 2.0));
+    }
+}
@@ -1,11 +1,25 @@
-use std::sync::atomic::Ordering;
+use std::{any::Any, sync::atomic::Ordering};
 
 use crate::{
     draw::material::Material,
     math::{matrix::Matrix, ray::Ray, tuples::Tuple},
 };
 
-use super::intersect::{Intersectable, Intersection, OBJECT_COUNTER};
+use super::{
+    bounds::BoundingBox,
+    intersect::{merge_coincident_hits, Intersectable, Intersection, OBJECT_COUNTER},
+};
+
+/*
+    How a group decides where to split its children when building a bounding volume hierarchy
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplitMethod {
+    Median,               // halve the bounds along the longest axis
+    SurfaceAreaHeuristic, // pick the split with the lowest estimated traversal cost
+}
+
+type Children = Vec<Box<dyn Intersectable>>;
 
 pub struct Group {
     id: usize,
@@ -14,7 +28,9 @@ pub struct Group {
     inverse_transform_transpose: Matrix,
     pub parent: Option<usize>,
     pub material: Material,
-    pub objects: Vec<Box<dyn Intersectable>>,
+    objects: Vec<Box<dyn Intersectable>>, // only changed through the group so the bounds stay correct
+    bounds: BoundingBox, // union of the children's bounds, kept up to date as objects are added
+    parent_bounds: Option<BoundingBox>, // the bounds in parent space, only kept when the transform is axis aligned
 }
 
 impl Group {
@@ -23,9 +39,7 @@ impl Group {
         let matrices = match transform {
             Some(matrix) => {
                 assert_eq!(matrix.size, 4);
-                let inverse = matrix.inverse();
-                let mut inv_transpose = matrix.inverse();
-                inv_transpose.transpose();
+                let (inverse, inv_transpose) = matrix.inverse_and_transpose();
                 (matrix, inverse, inv_transpose)
             }
             None => (
@@ -34,6 +48,11 @@ impl Group {
                 Matrix::identity(4),
             ),
         };
+        let parent_bounds = if matrices.0.is_axis_aligned() {
+            Some(BoundingBox::empty())
+        } else {
+            None
+        };
         Self {
             transform: matrices.0,
             inverse_transform: matrices.1,
@@ -42,14 +61,203 @@ impl Group {
             id,
             objects: vec![],
             parent: None,
+            bounds: BoundingBox::empty(),
+            parent_bounds,
         }
     }
 
     pub fn add_object(&mut self, mut shape: Box<dyn Intersectable>) {
-        shape.set_parent_id(self.id);
         // any object added to the group inherits the groups pattern
         shape.set_material(Material::from_material(&self.material));
+        self.add_object_keep_material(shape);
+    }
+
+    pub fn add_object_keep_material(&mut self, mut shape: Box<dyn Intersectable>) {
+        shape.set_parent_id(self.id);
+        self.bounds.merge(&shape.parent_space_bounds());
         self.objects.push(shape);
+        self.update_parent_bounds();
+    }
+
+    /*
+        Move the whole group by composing m on top of its current transform, the
+        children are left alone since they are always seen through this transform.
+        A group nested inside another must be transformed before it is added
+    */
+    pub fn apply_transform(&mut self, m: &Matrix) {
+        assert_eq!(m.size, 4);
+        self.transform = m * &self.transform;
+        (self.inverse_transform, self.inverse_transform_transpose) =
+            self.transform.inverse_and_transpose();
+        self.parent_bounds = if self.transform.is_axis_aligned() {
+            Some(BoundingBox::empty())
+        } else {
+            None
+        };
+        self.update_parent_bounds();
+    }
+
+    /*
+        Remove the object with the given id from this group or any group nested
+        inside it, returns false if no such object exists
+    */
+    pub fn remove_object(&mut self, id: usize) -> bool {
+        let removed = match self.objects.iter().position(|o| o.get_id() == id) {
+            Some(index) => {
+                self.objects.remove(index);
+                true
+            }
+            None => self.objects.iter_mut().any(|o| o.remove_object_by_id(id)),
+        };
+
+        if removed {
+            self.update_bounds();
+        }
+        removed
+    }
+
+    fn update_bounds(&mut self) {
+        self.bounds = BoundingBox::empty();
+        for o in &self.objects {
+            self.bounds.merge(&o.parent_space_bounds());
+        }
+        self.update_parent_bounds();
+    }
+
+    fn update_parent_bounds(&mut self) {
+        if self.parent_bounds.is_some() {
+            self.parent_bounds = Some(self.bounds.transform(&self.transform));
+        }
+    }
+
+    fn intersect_children(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        let mut intersects = vec![];
+        for s in &self.objects {
+            intersects.append(&mut s.intersect(ray));
+        }
+        intersects.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+        merge_coincident_hits(&mut intersects, ray);
+        intersects
+    }
+
+    /*
+        Build a bounding volume hierarchy by splitting the bounds in half along the longest axis
+        and moving children which fit entirely in one half into a new sub group,
+        any group with at least threshold children is split
+    */
+    pub fn divide(&mut self, threshold: usize) {
+        self.subdivide(threshold, SplitMethod::Median);
+    }
+
+    /*
+        Like divide but children are split where the surface area heuristic estimates
+        the lowest cost of tracing a ray through the two resulting sub groups
+    */
+    pub fn divide_sah(&mut self, threshold: usize) {
+        self.subdivide(threshold, SplitMethod::SurfaceAreaHeuristic);
+    }
+
+    fn partition_median(&mut self) -> (Children, Children) {
+        let (left_bounds, right_bounds) = self.bounds.split();
+        let mut left = vec![];
+        let mut right = vec![];
+        let mut remaining = vec![];
+
+        for o in self.objects.drain(..) {
+            let bounds = o.parent_space_bounds();
+            if left_bounds.contains_box(&bounds) {
+                left.push(o);
+            } else if right_bounds.contains_box(&bounds) {
+                right.push(o);
+            } else {
+                remaining.push(o);
+            }
+        }
+
+        self.objects = remaining;
+        (left, right)
+    }
+
+    fn partition_sah(&mut self) -> (Children, Children) {
+        // unbounded children such as planes can not be placed on either side
+        let (finite, infinite): (Vec<_>, Vec<_>) = self
+            .objects
+            .drain(..)
+            .partition(|o| o.parent_space_bounds().is_finite());
+        self.objects = infinite;
+
+        let bounds: Vec<BoundingBox> = finite.iter().map(|o| o.parent_space_bounds()).collect();
+        let n = finite.len();
+        // cost of leaving every child in this group, any split has to beat it
+        let mut best_cost = self.bounds.surface_area() * n as f64;
+        let mut best_split: Option<Vec<usize>> = None;
+
+        for axis in 0..3 {
+            let centroid = |i: usize| {
+                let c = bounds[i].center();
+                [c.x, c.y, c.z][axis]
+            };
+            let mut order: Vec<usize> = (0..n).collect();
+            order.sort_by(|&a, &b| centroid(a).partial_cmp(&centroid(b)).unwrap());
+
+            // surface area of the children to the right of each split position
+            let mut right_areas = vec![0.0; n];
+            let mut right_bounds = BoundingBox::empty();
+            for i in (1..n).rev() {
+                right_bounds.merge(&bounds[order[i]]);
+                right_areas[i] = right_bounds.surface_area();
+            }
+
+            let mut left_bounds = BoundingBox::empty();
+            for i in 1..n {
+                left_bounds.merge(&bounds[order[i - 1]]);
+                // splitting adds a bounds test for each of the two new sub groups
+                let cost = 2.0 * self.bounds.surface_area()
+                    + left_bounds.surface_area() * i as f64
+                    + right_areas[i] * (n - i) as f64;
+                if cost < best_cost {
+                    best_cost = cost;
+                    best_split = Some(order[..i].to_vec());
+                }
+            }
+        }
+
+        let mut left = vec![];
+        let mut right = vec![];
+        match best_split {
+            Some(left_indices) => {
+                let mut in_left = vec![false; n];
+                for i in left_indices {
+                    in_left[i] = true;
+                }
+                for (o, is_left) in finite.into_iter().zip(in_left) {
+                    if is_left {
+                        left.push(o);
+                    } else {
+                        right.push(o);
+                    }
+                }
+            }
+            None => self.objects.extend(finite),
+        }
+
+        (left, right)
+    }
+
+    fn make_subgroup(&mut self, objects: Children) {
+        let mut sub_group = Group::new(None, Some(Material::from_material(&self.material)));
+        for o in objects {
+            sub_group.add_object_keep_material(o);
+        }
+        self.add_object_keep_material(Box::new(sub_group));
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
     }
 
     pub fn get_object(&self, index: usize) -> Option<&dyn Intersectable> {
@@ -58,16 +266,41 @@ impl Group {
             None => None,
         }
     }
+
+    pub fn objects(&self) -> &[Box<dyn Intersectable>] {
+        &self.objects
+    }
 }
 
 impl Intersectable for Group {
-    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection> {
-        let mut intersects = vec![];
-        for s in &self.objects {
-            intersects.append(&mut s.intersect(ray));
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        if !self.bounds.intersects(ray) {
+            return vec![];
+        }
+        self.intersect_children(ray)
+    }
+
+    /*
+        An axis aligned transform keeps the bounds a box in parent space, so a ray
+        which misses them is thrown out without being transformed first
+    */
+    fn intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        match self.parent_bounds {
+            Some(bounds) if !bounds.intersects(ray) => vec![],
+            Some(_) => self.intersect_children(&ray.apply_transform(&self.inverse_transform)),
+            None => self.local_intersect(&ray.apply_transform(&self.inverse_transform)),
         }
-        intersects.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
-        intersects
+    }
+
+    fn any_hit_before(&self, ray: &Ray, max_t: f64) -> bool {
+        if let Some(bounds) = self.parent_bounds {
+            if !bounds.intersects(ray) {
+                return false;
+            }
+        }
+        let r = ray.apply_transform(self.get_inverse_transform());
+        (self.parent_bounds.is_some() || self.bounds.intersects(&r))
+            && self.objects.iter().any(|s| s.any_hit_before(&r, max_t))
     }
 
     fn get_object_by_id(&self, id: usize) -> Option<&dyn Intersectable> {
@@ -86,6 +319,43 @@ impl Intersectable for Group {
         shape
     }
 
+    fn remove_object_by_id(&mut self, id: usize) -> bool {
+        self.remove_object(id)
+    }
+
+    fn includes(&self, id: usize) -> bool {
+        self.id == id || self.objects.iter().any(|o| o.includes(id))
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        self.bounds
+    }
+
+    fn subdivide(&mut self, threshold: usize, split: SplitMethod) {
+        if threshold <= self.objects.len() {
+            let (left, right) = match split {
+                SplitMethod::Median => self.partition_median(),
+                SplitMethod::SurfaceAreaHeuristic => self.partition_sah(),
+            };
+            if self.objects.is_empty() && (left.is_empty() || right.is_empty()) {
+                // a single sub group holding every child would never get any smaller
+                self.objects = left;
+                self.objects.extend(right);
+            } else {
+                if !left.is_empty() {
+                    self.make_subgroup(left);
+                }
+                if !right.is_empty() {
+                    self.make_subgroup(right);
+                }
+            }
+        }
+
+        for o in self.objects.iter_mut() {
+            o.subdivide(threshold, split);
+        }
+    }
+
     fn local_normal_at(&self, _: Tuple, _: Intersection) -> Tuple {
         panic!("A group does not have a normal, something went wrong")
     }
@@ -94,6 +364,10 @@ impl Intersectable for Group {
         &self.material
     }
 
+    fn get_material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
     fn get_transform(&self) -> &Matrix {
         &self.transform
     }
@@ -121,6 +395,10 @@ impl Intersectable for Group {
     fn set_material(&mut self, mat: Material) {
         self.material = mat;
     }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 #[cfg(test)]
@@ -128,10 +406,234 @@ mod test {
 
     use std::f64::consts::PI;
 
-    use crate::{scene::world::World, shapes::sphere::Sphere};
+    use crate::{
+        math::utils::f64_eq,
+        obj_parser::parse_obj_file,
+        scene::world::World,
+        shapes::{
+            cube::Cube,
+            cylinder::Cylinder,
+            intersect::{filter_intersections, hit, prepare_computations, CsgOperation},
+            sphere::Sphere,
+            triangle::Triangle,
+        },
+    };
 
     use super::*;
 
+    #[test]
+    fn axis_aligned_groups_skip_transforming_missed_rays() {
+        let build = |transform: Matrix| {
+            let mut g = Group::new(Some(transform), None);
+            g.add_object(Box::new(Sphere::new(Some(Matrix::translation(
+                2.0, 0.0, 0.0,
+            )))));
+            g.add_object(Box::new(Cube::new(Some(Matrix::translation(
+                -2.0, 1.0, 0.0,
+            )))));
+            g
+        };
+        let aligned = build(&Matrix::translation(0.5, -1.0, 3.0) * &Matrix::scaling(2.0, 1.5, 1.0));
+        let rotated = build(&Matrix::translation(0.5, -1.0, 3.0) * &Matrix::rotation_y(PI / 5.0));
+        assert!(aligned.parent_bounds.is_some());
+        assert!(rotated.parent_bounds.is_none());
+
+        for g in [&aligned, &rotated] {
+            let inv = g.get_inverse_transform();
+            for i in 0..40 {
+                for j in 0..30 {
+                    let origin =
+                        Tuple::point(-9.83 + i as f64 * 0.51, -6.77 + j as f64 * 0.47, -10.0);
+                    let ray = Ray::new(origin, Tuple::vector(0.13, -0.07, 1.0).normalize());
+                    let local = ray.apply_transform(inv);
+
+                    // the general path transforms the ray and tests the local bounds
+                    let general = g.bounds.intersects(&local);
+                    if let Some(bounds) = g.parent_bounds {
+                        assert_eq!(bounds.intersects(&ray), general);
+                    }
+
+                    let expected: Vec<f64> =
+                        g.local_intersect(&local).iter().map(|x| x.t).collect();
+                    let actual: Vec<f64> = g.intersect(&ray).iter().map(|x| x.t).collect();
+                    assert_eq!(actual, expected);
+                    assert_eq!(g.any_hit_before(&ray, f64::INFINITY), !expected.is_empty());
+                }
+            }
+        }
+    }
+
+    // a row of small triangles along x, densely packed near the origin and sparse further out
+    fn skewed_triangles() -> Group {
+        let mut g = Group::new(None, None);
+        let mut xs: Vec<f64> = (0..150).map(|i| i as f64 * 0.05).collect();
+        xs.extend((0..50).map(|i| 10.0 + i as f64 * 2.0));
+        for x in xs {
+            g.add_object(Box::new(Triangle::new(
+                Tuple::point(x, 0.0, 0.0),
+                Tuple::point(x + 0.04, 0.0, 0.0),
+                Tuple::point(x, 1.0, 0.1),
+                None,
+            )));
+        }
+        g
+    }
+
+    // expected number of bounds and shape tests for a ray which hits the bounds of this group
+    fn traversal_cost(g: &Group) -> f64 {
+        let area = g.bounds().surface_area();
+        g.objects
+            .iter()
+            .map(|o| match o.as_any().downcast_ref::<Group>() {
+                Some(sub) => 1.0 + sub.bounds().surface_area() / area * traversal_cost(sub),
+                None => 1.0,
+            })
+            .sum()
+    }
+
+    fn all_hits(g: &Group) -> Vec<f64> {
+        let mut ts = vec![];
+        for i in 0..200 {
+            let x = i as f64 * 0.51 - 1.0;
+            let r = Ray::new(
+                Tuple::point(x, 0.5, -5.0),
+                Tuple::vector(0.01, 0.0, 1.0).normalize(),
+            );
+            ts.extend(g.intersect(&r).iter().map(|i| i.t));
+        }
+        ts
+    }
+
+    #[test]
+    fn group_includes_nested_children() {
+        let s = Sphere::new(None);
+        let sphere_id = s.get_id();
+        let mut inner = Group::new(None, None);
+        inner.add_object(Box::new(s));
+        let inner_id = inner.get_id();
+        let mut outer = Group::new(None, None);
+        outer.add_object(Box::new(inner));
+
+        let unrelated = Sphere::new(None);
+        assert!(outer.includes(outer.get_id()));
+        assert!(outer.includes(inner_id));
+        assert!(outer.includes(sphere_id));
+        assert!(!outer.includes(unrelated.get_id()));
+        assert!(unrelated.includes(unrelated.get_id()));
+        assert!(!unrelated.includes(sphere_id));
+    }
+
+    #[test]
+    fn includes_tells_csg_operands_apart() {
+        // the left operand is a group holding a sphere, the right a lone cube
+        let mut left = Group::new(None, None);
+        left.add_object(Box::new(Sphere::new(None)));
+        let right = Cube::new(Some(Matrix::translation(0.0, 0.0, 0.5)));
+
+        let ray = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let mut xs = left.intersect(&ray);
+        xs.append(&mut right.intersect(&ray));
+        xs.sort_by(|a, b| a.t.partial_cmp(&b.t).unwrap());
+
+        let result = filter_intersections(CsgOperation::Union, &xs, |s| left.includes(s.get_id()));
+        let ts: Vec<f64> = result.iter().map(|i| i.t).collect();
+        assert_eq!(ts.len(), 2);
+        assert!(f64_eq(ts[0], 4.0));
+        assert!(f64_eq(ts[1], 6.5));
+    }
+
+    #[test]
+    fn group_bounds_contain_children() {
+        let mut g = Group::new(None, None);
+        g.add_object(Box::new(Sphere::new(Some(
+            &Matrix::translation(2.0, 5.0, -3.0) * &Matrix::scaling(2.0, 2.0, 2.0),
+        ))));
+        let mut c = Cylinder::new(Some(
+            &Matrix::translation(-4.0, -1.0, 4.0) * &Matrix::scaling(0.5, 1.0, 0.5),
+        ));
+        c.minimum = -2.0;
+        c.maximum = 2.0;
+        g.add_object(Box::new(c));
+
+        let b = g.bounds();
+        assert_eq!(b.min, Tuple::point(-4.5, -3.0, -5.0));
+        assert_eq!(b.max, Tuple::point(4.0, 7.0, 4.5));
+    }
+
+    #[test]
+    fn removing_an_object_shrinks_bounds() {
+        let mut g = Group::new(None, None);
+        let far = Sphere::new(Some(Matrix::translation(10.0, 0.0, 0.0)));
+        let far_id = far.get_id();
+        g.add_object(Box::new(Sphere::new(None)));
+        g.add_object(Box::new(far));
+        assert_eq!(g.bounds().max, Tuple::point(11.0, 1.0, 1.0));
+
+        assert!(g.remove_object(far_id));
+        assert_eq!(g.bounds().max, Tuple::point(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn ray_missing_bounds_skips_children() {
+        let mut g = Group::new(None, None);
+        g.add_object(Box::new(Cube::new(None)));
+        let r = Ray::new(Tuple::point(5.0, 5.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert!(g.local_intersect(&r).is_empty());
+    }
+
+    #[test]
+    fn dividing_a_group_partitions_children() {
+        let mut g = Group::new(None, None);
+        let s1 = Sphere::new(Some(Matrix::translation(-2.0, -2.0, 0.0)));
+        let s2 = Sphere::new(Some(Matrix::translation(-2.0, 2.0, 0.0)));
+        let s3 = Sphere::new(Some(Matrix::scaling(4.0, 4.0, 4.0)));
+        let (id1, id2, id3) = (s1.get_id(), s2.get_id(), s3.get_id());
+        g.add_object(Box::new(s1));
+        g.add_object(Box::new(s2));
+        g.add_object(Box::new(s3));
+
+        g.divide(1);
+        assert_eq!(g.len(), 2);
+        assert_eq!(g.objects[0].get_id(), id3);
+        let sub = g.objects[1].as_any().downcast_ref::<Group>().unwrap();
+        assert_eq!(sub.len(), 2);
+        let left = sub.objects[0].as_any().downcast_ref::<Group>().unwrap();
+        let right = sub.objects[1].as_any().downcast_ref::<Group>().unwrap();
+        assert_eq!(left.objects[0].get_id(), id1);
+        assert_eq!(right.objects[0].get_id(), id2);
+    }
+
+    #[test]
+    fn dividing_keeps_intersections() {
+        let expected = all_hits(&skewed_triangles());
+        assert!(!expected.is_empty());
+
+        let mut median = skewed_triangles();
+        median.divide(4);
+        let mut sah = skewed_triangles();
+        sah.divide_sah(4);
+
+        for divided in [&median, &sah] {
+            let actual = all_hits(divided);
+            assert_eq!(expected.len(), actual.len());
+            for (e, a) in expected.iter().zip(actual.iter()) {
+                assert!(f64_eq(*e, *a));
+            }
+        }
+    }
+
+    #[test]
+    fn sah_is_cheaper_than_median_on_skewed_meshes() {
+        let mut median = skewed_triangles();
+        median.divide(4);
+        let mut sah = skewed_triangles();
+        sah.divide_sah(4);
+
+        let median_cost = traversal_cost(&median);
+        let sah_cost = traversal_cost(&sah);
+        assert!(sah_cost < median_cost);
+    }
+
     #[test]
     fn shape_added_successfully() {
         let mut g = Group::new(None, None);
@@ -217,4 +719,162 @@ mod test {
             )
         )
     }
+
+    #[test]
+    fn removing_an_object() {
+        let mut g = Group::new(None, None);
+        let s1 = Sphere::new(None);
+        let s2 = Sphere::new(None);
+        let s3 = Sphere::new(None);
+        let s2_id = s2.get_id();
+        g.add_object(Box::new(s1));
+        g.add_object(Box::new(s2));
+        g.add_object(Box::new(s3));
+        assert_eq!(g.len(), 3);
+
+        assert!(g.remove_object(s2_id));
+        assert_eq!(g.len(), 2);
+        assert!(g.get_object_by_id(s2_id).is_none());
+        assert!(!g.remove_object(s2_id));
+    }
+
+    #[test]
+    fn removing_a_nested_object() {
+        let mut outer = Group::new(None, None);
+        let mut inner = Group::new(None, None);
+        let s = Sphere::new(None);
+        let s_id = s.get_id();
+        inner.add_object(Box::new(s));
+        outer.add_object(Box::new(inner));
+
+        assert!(outer.remove_object(s_id));
+        assert_eq!(outer.len(), 1);
+        assert!(outer.get_object_by_id(s_id).is_none());
+        assert!(outer.objects[0].get_object_by_id(s_id).is_none());
+    }
+
+    #[test]
+    fn ray_through_shared_edge_hits_once() {
+        let data = "
+        v -1 1 0
+        v -1 -1 0
+        v 1 -1 0
+        v 1 1 0
+        f 1 2 3 4";
+        let g = parse_obj_file(data, None, None);
+        assert_eq!(g.len(), 2);
+
+        // (0, 0) lies on the diagonal shared by both triangles
+        let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        let xs = g.intersect(&r);
+        assert_eq!(xs.len(), 1);
+        assert!(f64_eq(hit(&xs).unwrap().t, 5.0));
+    }
+
+    #[test]
+    fn tangent_hits_on_one_shape_are_kept() {
+        let mut g = Group::new(None, None);
+        g.add_object(Box::new(Sphere::new(None)));
+        let r = Ray::new(Tuple::point(0.0, 1.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert_eq!(g.intersect(&r).len(), 2);
+    }
+
+    #[test]
+    fn touching_siblings_keep_their_hits() {
+        let mut g = Group::new(None, None);
+        g.add_object(Box::new(Sphere::new(None)));
+        g.add_object(Box::new(Cube::new(Some(Matrix::translation(
+            2.0, 0.0, 0.0,
+        )))));
+        // the sphere is left at t = 6 just as the cube is entered
+        let r = Ray::new(Tuple::point(-5.0, 0.0, 0.0), Tuple::vector(1.0, 0.0, 0.0));
+        assert_eq!(g.intersect(&r).len(), 4);
+    }
+
+    // a closed glass octahedron with a vertex one unit out along each axis
+    fn glass_octahedron() -> Group {
+        let mut glass = Material::default_material();
+        glass.transparency = 1.0;
+        glass.refractive_index = 1.5;
+        let mut g = Group::new(None, Some(glass));
+        let mesh = g.get_id();
+        for sx in [-1.0, 1.0] {
+            for sy in [-1.0, 1.0] {
+                for sz in [-1.0, 1.0] {
+                    let a = Tuple::point(sx, 0.0, 0.0);
+                    let b = Tuple::point(0.0, sy, 0.0);
+                    let c = Tuple::point(0.0, 0.0, sz);
+                    // wind every face so its normal points outwards
+                    let mut face = if sx * sy * sz > 0.0 {
+                        Triangle::new(a, c, b, None)
+                    } else {
+                        Triangle::new(a, b, c, None)
+                    };
+                    face.mesh = Some(mesh);
+                    g.add_object(Box::new(face));
+                }
+            }
+        }
+        g
+    }
+
+    #[test]
+    fn refracting_through_shared_mesh_edges() {
+        let whole = glass_octahedron();
+        let mut divided = glass_octahedron();
+        divided.divide(2);
+        let mut divided_sah = glass_octahedron();
+        divided_sah.divide_sah(2);
+        // the faces either side of an edge end up in different sub groups
+        assert!(divided.len() < whole.len() && divided_sah.len() < whole.len());
+
+        // enters and leaves through the edges between the faces either side of x = 0
+        let r = Ray::new(Tuple::point(0.0, 0.5, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        for g in [whole, divided, divided_sah] {
+            let xs = g.intersect(&r);
+            assert_eq!(xs.len(), 2);
+
+            let entry = prepare_computations(&xs[0], &r, &xs, None);
+            assert!(f64_eq(entry.t, 4.5));
+            assert_eq!((entry.n1, entry.n2), (1.0, 1.5));
+            let exit = prepare_computations(&xs[1], &r, &xs, None);
+            assert!(f64_eq(exit.t, 5.5));
+            assert_eq!((exit.n1, exit.n2), (1.5, 1.0));
+        }
+    }
+
+    #[test]
+    fn dividing_a_mesh_keeps_shared_edges_merged() {
+        // a strip of 8 unit quads along x, each split into two triangles along its diagonal
+        let mut data = String::new();
+        for i in 0..=8 {
+            data += &format!("v {} 0 0\nv {} 1 0\n", i, i);
+        }
+        for i in 0..8 {
+            let (bottom, top) = (2 * i + 1, 2 * i + 2);
+            data += &format!("f {} {} {} {}\n", bottom, bottom + 2, top + 2, top);
+        }
+        let mut g = parse_obj_file(&data, None, None);
+        assert_eq!(g.len(), 16);
+        g.divide(2);
+        assert!(g.len() < 16);
+
+        // down the edges between quads and across the diagonals inside them
+        for i in 1..16 {
+            let x = i as f64 / 2.0;
+            let r = Ray::new(Tuple::point(x, 0.5, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+            assert_eq!(g.intersect(&r).len(), 1);
+        }
+    }
+
+    #[test]
+    fn skimming_a_mesh_ridge_keeps_both_hits() {
+        let g = glass_octahedron();
+
+        // touches the edge between two faces, going in through one and straight back out the other
+        let r = Ray::new(Tuple::point(-5.0, 0.5, -0.5), Tuple::vector(1.0, 0.0, 0.0));
+        let xs = g.intersect(&r);
+        assert_eq!(xs.len(), 2);
+        assert!(xs.iter().all(|i| f64_eq(i.t, 5.0)));
+    }
 }
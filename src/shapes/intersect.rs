@@ -1,7 +1,7 @@
-use std::sync::atomic::AtomicUsize;
+use std::{any::Any, sync::atomic::AtomicUsize};
 
 use crate::{
-    draw::material::Material,
+    draw::{color::Color, material::Material},
     math::{
         matrix::Matrix,
         ray::Ray,
@@ -11,6 +11,8 @@ use crate::{
     scene::world::World,
 };
 
+use super::{bounds::BoundingBox, group::SplitMethod};
+
 // atomic counter to ensure each shape in the scene will have a unique id
 pub static OBJECT_COUNTER: AtomicUsize = AtomicUsize::new(0);
 
@@ -20,6 +22,7 @@ pub struct Intersection<'a> {
     pub t: f64,
     pub u: Option<f64>,
     pub v: Option<f64>,
+    pub coverage: f64, // fraction of a wide ray covered by the shape, 1 unless the shape is thinner than the ray
 }
 
 impl<'a> Intersection<'a> {
@@ -29,6 +32,7 @@ impl<'a> Intersection<'a> {
             t,
             u: None,
             v: None,
+            coverage: 1.0,
         }
     }
 
@@ -38,14 +42,21 @@ impl<'a> Intersection<'a> {
             t,
             u: Some(u),
             v: Some(v),
+            coverage: 1.0,
         }
     }
+
+    pub fn with_coverage(mut self, coverage: f64) -> Self {
+        self.coverage = coverage;
+        self
+    }
 }
 
 pub trait Intersectable: Sync + Send {
-    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection>;
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>>;
     fn local_normal_at(&self, t: Tuple, hit: Intersection) -> Tuple;
     fn get_material(&self) -> &Material;
+    fn get_material_mut(&mut self) -> &mut Material; // change one property in place without building a new material
     fn set_material(&mut self, mat: Material);
     fn get_transform(&self) -> &Matrix;
     fn get_inverse_transform(&self) -> &Matrix;
@@ -53,17 +64,52 @@ pub trait Intersectable: Sync + Send {
     fn get_id(&self) -> usize; // random number to uniquely identify this shape
     fn get_parent_id(&self) -> Option<usize>;
     fn set_parent_id(&mut self, id: usize);
+    fn as_any(&self) -> &dyn Any;
+    fn bounds(&self) -> BoundingBox; // axis aligned bounds in object space
+
+    // bounds of this shape in the space of its parent
+    fn parent_space_bounds(&self) -> BoundingBox {
+        self.bounds().transform(self.get_transform())
+    }
+
+    fn subdivide(&mut self, _threshold: usize, _split: SplitMethod) {}
+
+    // a color carried by the shape itself at the hit, such as painted vertices, used instead of the material pattern
+    fn color_at_hit(&self, _hit: &Intersection) -> Option<Color> {
+        None
+    }
+
+    // the mesh this shape is a face of, all faces of a mesh bound one solid for refraction
+    fn mesh_id(&self) -> Option<usize> {
+        None
+    }
 
-    fn intersect(&self, ray: &Ray) -> Vec<Intersection> {
+    fn intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
         let inv = self.get_inverse_transform();
         let r = ray.apply_transform(inv);
         self.local_intersect(&r)
     }
 
+    // if the ray hits this shape between its origin and max_t, used for shadows where the closest hit does not matter
+    fn any_hit_before(&self, ray: &Ray, max_t: f64) -> bool {
+        // a shadow catcher only shows shadows, it never casts them
+        !self.get_material().shadow_catcher
+            && self.intersect(ray).iter().any(|i| i.t > 0.0 && i.t < max_t)
+    }
+
     fn get_object_by_id(&self, _id: usize) -> Option<&dyn Intersectable> {
         None
     }
 
+    // whether the shape with this id is this shape or any shape nested inside of it
+    fn includes(&self, id: usize) -> bool {
+        self.get_id() == id
+    }
+
+    fn remove_object_by_id(&mut self, _id: usize) -> bool {
+        false
+    }
+
     fn world_to_object(&self, point: Tuple, w: &World) -> Tuple {
         let object_point = match self.get_parent_id() {
             Some(id) => {
@@ -138,6 +184,107 @@ pub fn hit<'a>(intersections: &[Intersection<'a>]) -> Option<Intersection<'a>> {
     front_intersection
 }
 
+/*
+    Like hit but negative intersections are considered as well, returns the intersection
+    closest to the ray origin in either direction which is useful when reasoning about
+    where a ray entered or left a shape it started inside of
+*/
+pub fn hit_including_negatives<'a>(intersections: &[Intersection<'a>]) -> Option<Intersection<'a>> {
+    let mut closest: Option<Intersection> = None;
+
+    for intersection in intersections {
+        if closest.is_none() || intersection.t.abs() < closest.unwrap().t.abs() {
+            closest = Some(*intersection);
+        }
+    }
+
+    closest
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    Difference,
+}
+
+/*
+    Decide if an intersection should be kept when combining two shapes,
+    left_hit is true if the left shape was hit, in_left and in_right
+    are true if the hit happened while inside the left or right shape
+*/
+pub fn intersection_allowed(
+    op: CsgOperation,
+    left_hit: bool,
+    in_left: bool,
+    in_right: bool,
+) -> bool {
+    match op {
+        CsgOperation::Union => (left_hit && !in_right) || (!left_hit && !in_left),
+        CsgOperation::Intersection => (left_hit && in_right) || (!left_hit && in_left),
+        CsgOperation::Difference => (left_hit && !in_right) || (!left_hit && in_left),
+    }
+}
+
+/*
+    Walk a sorted list of intersections keeping only the ones allowed by the operation,
+    is_left tells which of the two combined shapes an intersected shape belongs to,
+    usually |s| left.includes(s.get_id())
+*/
+pub fn filter_intersections<'a>(
+    op: CsgOperation,
+    intersections: &[Intersection<'a>],
+    is_left: impl Fn(&dyn Intersectable) -> bool,
+) -> Vec<Intersection<'a>> {
+    let mut in_left = false;
+    let mut in_right = false;
+    let mut result = vec![];
+
+    for intersection in intersections {
+        let left_hit = is_left(intersection.shape);
+
+        if intersection_allowed(op, left_hit, in_left, in_right) {
+            result.push(*intersection);
+        }
+
+        if left_hit {
+            in_left = !in_left;
+        } else {
+            in_right = !in_right;
+        }
+    }
+
+    result
+}
+
+/*
+    Meshes share edges between neighbouring triangles so a ray through an edge
+    can hit two faces of the same mesh at the same t, keep only the first of those
+    so the refraction bookkeeping sees a single surface crossing. A ray skimming
+    a ridge enters through one face and leaves through the other so those hits
+    are both kept, as are hits on any other shapes. Expects the intersections
+    to be sorted by t and the ray to be in the same space as the shapes
+*/
+pub fn merge_coincident_hits(intersections: &mut Vec<Intersection>, ray: &Ray) {
+    let entering = |i: &Intersection| {
+        i.shape
+            .normal_at(ray.position(i.t), *i, None)
+            .dot(&ray.direction)
+            < 0.0
+    };
+    intersections.dedup_by(|b, a| {
+        a.shape.mesh_id().is_some()
+            && a.shape.mesh_id() == b.shape.mesh_id()
+            && f64_eq(a.t, b.t)
+            && entering(a) == entering(b)
+    });
+}
+
+// a ray is inside a mesh between crossing any two of its faces, not just the same face twice
+fn container_id(shape: &dyn Intersectable) -> usize {
+    shape.mesh_id().unwrap_or_else(|| shape.get_id())
+}
+
 pub fn object_space_to_world_space(shape: &dyn Intersectable, object_normal: &Tuple) -> Tuple {
     let mut world_normal = shape.get_inverse_transform_transpose() * object_normal;
     world_normal.w = 0.0;
@@ -159,8 +306,15 @@ pub struct Computations<'a> {
     pub inside: bool, // if the ray was cast from inside the object
     pub n1: f64,
     pub n2: f64,
+    pub coverage: f64,
+    pub exit_point: Tuple, // a point just past the far side of the object along the ray
+    pub surface_color: Option<Color>, // overrides the material pattern when the shape provides its own color
+    pub footprint: f64, // radius of a wide ray where it meets the surface, zero for an ideal ray
 }
 
+// the most the surface offset is scaled up by for rays skimming along a surface
+const MAX_GRAZING_OFFSET: f64 = 10.0;
+
 fn hits_equal(a: &Intersection, b: &Intersection) -> bool {
     a.shape.get_id() == b.shape.get_id() && f64_eq(a.t, b.t)
 }
@@ -174,14 +328,22 @@ pub fn prepare_computations<'a>(
     let point = ray.position(hit.t);
     let mut normalv = hit.shape.normal_at(point, *hit, world);
     let eyev = -ray.direction;
-    let inside = normalv.dot(&eyev) < 0.0;
+    let back_face = normalv.dot(&eyev) < 0.0;
 
-    if inside {
+    // the normal always turns to face the eye, but a double sided surface is a thin
+    // sheet so seeing its back face does not put the ray inside of it
+    let inside = back_face && !hit.shape.get_material().double_sided;
+    if back_face {
         normalv *= -1.0;
     }
 
-    let over_point = point + normalv * EPSILON;
-    let under_point = point - normalv * EPSILON;
+    // large scenes need a bigger nudge off the surface to avoid acne, and so do grazing hits
+    // where a tiny error in t moves the point a long way compared to its height off the surface
+    let cos = normalv.dot(&eyev) / eyev.magnitude();
+    let grazing = (1.0 / cos.abs().max(f64::EPSILON)).min(MAX_GRAZING_OFFSET);
+    let offset = world.map_or(EPSILON, |w| w.surface_offset) * grazing;
+    let over_point = point + normalv * offset;
+    let under_point = point - normalv * offset;
 
     let reflectv = ray.direction.reflect(&normalv);
 
@@ -203,7 +365,7 @@ pub fn prepare_computations<'a>(
         // otherwise we are entering the object and need to keep it in the list
         match containers
             .iter()
-            .position(|x| x.get_id() == i.shape.get_id())
+            .position(|x| container_id(*x) == container_id(i.shape))
         {
             Some(index) => {
                 containers.remove(index);
@@ -221,6 +383,17 @@ pub fn prepare_computations<'a>(
         }
     }
 
+    // only a partly covered hit needs to know where the ray leaves the object
+    let exit_point = if hit.coverage < 1.0 {
+        let exit_t = intersections
+            .iter()
+            .filter(|i| i.shape.get_id() == hit.shape.get_id())
+            .fold(hit.t, |t, i| t.max(i.t));
+        ray.position(exit_t) + ray.direction * EPSILON
+    } else {
+        point
+    };
+
     Computations {
         t: hit.t,
         object: hit.shape,
@@ -233,6 +406,10 @@ pub fn prepare_computations<'a>(
         inside,
         n1,
         n2,
+        coverage: hit.coverage,
+        exit_point,
+        surface_color: hit.shape.color_at_hit(hit),
+        footprint: ray.width * hit.t * ray.direction.magnitude(),
     }
 }
 
@@ -244,11 +421,71 @@ mod test {
     use crate::{
         math::{matrix::Matrix, utils::f64_eq},
         scene::world::World,
-        shapes::{plane::Plane, sphere::Sphere},
+        shapes::{plane::Plane, sphere::Sphere, triangle::Triangle},
     };
 
     use super::*;
 
+    #[test]
+    fn csg_truth_table() {
+        // (left_hit, in_left, in_right, union, intersection, difference)
+        let table = [
+            (true, true, true, false, true, false),
+            (true, true, false, true, false, true),
+            (true, false, true, false, true, false),
+            (true, false, false, true, false, true),
+            (false, true, true, false, true, true),
+            (false, true, false, false, true, true),
+            (false, false, true, true, false, false),
+            (false, false, false, true, false, false),
+        ];
+
+        for (left_hit, in_left, in_right, union, intersection, difference) in table {
+            let allowed = |op| intersection_allowed(op, left_hit, in_left, in_right);
+            assert_eq!(allowed(CsgOperation::Union), union);
+            assert_eq!(allowed(CsgOperation::Intersection), intersection);
+            assert_eq!(allowed(CsgOperation::Difference), difference);
+        }
+    }
+
+    #[test]
+    fn filtering_intersections() {
+        let s1 = Sphere::new(None);
+        let s2 = Sphere::new(None);
+        let xs = [
+            Intersection::new(&s1, 1.0),
+            Intersection::new(&s2, 2.0),
+            Intersection::new(&s1, 3.0),
+            Intersection::new(&s2, 4.0),
+        ];
+        let is_left = |s: &dyn Intersectable| s.get_id() == s1.get_id();
+
+        let expected = [
+            (CsgOperation::Union, 0, 3),
+            (CsgOperation::Intersection, 1, 2),
+            (CsgOperation::Difference, 0, 1),
+        ];
+        for (op, first, second) in expected {
+            let result = filter_intersections(op, &xs, is_left);
+            assert_eq!(result.len(), 2);
+            assert!(hits_equal(&result[0], &xs[first]));
+            assert!(hits_equal(&result[1], &xs[second]));
+        }
+    }
+
+    #[test]
+    fn hit_including_negatives_all_negative() {
+        let s = Sphere::new(None);
+        let xs = [
+            Intersection::new(&s, -5.0),
+            Intersection::new(&s, -0.5),
+            Intersection::new(&s, -2.0),
+        ];
+        assert!(hit(&xs).is_none());
+        let i = hit_including_negatives(&xs).unwrap();
+        assert!(f64_eq(i.t, -0.5));
+    }
+
     #[test]
     fn normal_vector_normalized() {
         let s = Sphere::new(None);
@@ -347,6 +584,25 @@ mod test {
         assert!(comps.inside);
     }
 
+    #[test]
+    fn prepare_computations_double_sided_back_face() {
+        // the front of the triangle faces -z and the ray sees its back
+        let mut t = Triangle::new(
+            Tuple::point(0.0, 1.0, 0.0),
+            Tuple::point(-1.0, 0.0, 0.0),
+            Tuple::point(1.0, 0.0, 0.0),
+            None,
+        );
+        let r = Ray::new(Tuple::point(0.0, 0.5, 5.0), Tuple::vector(0.0, 0.0, -1.0));
+        for double_sided in [false, true] {
+            t.material.double_sided = double_sided;
+            let xs = t.intersect(&r);
+            let comps = prepare_computations(&xs[0], &r, &xs, None);
+            assert_eq!(comps.normalv, Tuple::vector(0.0, 0.0, 1.0));
+            assert_eq!(comps.inside, !double_sided);
+        }
+    }
+
     #[test]
     fn hit_should_offset_point() {
         let r = Ray::new(Tuple::point(0.0, 0.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
@@ -422,4 +678,57 @@ mod test {
         assert!(comps.under_point.z > EPSILON / 2.);
         assert!(comps.point.z < comps.under_point.z);
     }
+
+    #[test]
+    fn grazing_reflections_clear_the_surface() {
+        // far enough from the origin that rounding in the hit point is close to EPSILON
+        let far = 3e11;
+        let plane = Plane::new(Some(
+            &Matrix::translation(far, -far * 0.3, far * 0.7)
+                * &(&Matrix::rotation_x(0.3) * &Matrix::rotation_z(-0.2)),
+        ));
+        let transform = plane.get_transform();
+        let up = (transform * &Tuple::vector(0.0, 1.0, 0.0)).normalize();
+
+        for i in 0..500 {
+            let a = i as f64 * 0.37;
+            let target = transform * &Tuple::point(a * 10.0, 0.0, a.sin() * 30.0);
+            let along = (transform * &Tuple::vector(a.cos(), 0.0, a.sin())).normalize();
+            let direction = (along - up * 0.01).normalize();
+            let r = Ray::new(target - direction * 100.0, direction);
+
+            let xs = plane.intersect(&r);
+            let h = hit(&xs).unwrap();
+            let comps = prepare_computations(&h, &r, &xs, None);
+            let bounce = Ray::new(comps.over_point, comps.reflectv);
+            assert!(plane.intersect(&bounce).iter().all(|x| x.t < 0.0));
+        }
+
+        // head on hits keep the usual small offset
+        let flat = Plane::new(None);
+        let offset_for = |r: Ray| {
+            let xs = flat.intersect(&r);
+            let h = hit(&xs).unwrap();
+            let comps = prepare_computations(&h, &r, &xs, None);
+            // in units of the usual offset so the comparison is not swamped by EPSILON
+            comps.over_point.y / EPSILON
+        };
+        let from = Tuple::point(0.0, 1.0, 0.0);
+        let down = Tuple::vector(0.0, -1.0, 0.0);
+        assert!(f64_eq(offset_for(Ray::new(from, down)), 1.0));
+        let at_60 = Tuple::vector(3.0_f64.sqrt(), -1.0, 0.0).normalize();
+        assert!(f64_eq(offset_for(Ray::new(from, at_60)), 2.0));
+        let skimming = Tuple::vector(1.0, -0.001, 0.0).normalize();
+        assert!(f64_eq(
+            offset_for(Ray::new(from, skimming)),
+            MAX_GRAZING_OFFSET
+        ));
+
+        // a ray running exactly along the surface has a cosine of -0.0, which must not flip the offset
+        let along = Ray::new(Tuple::point(-1.0, 0.0, 0.0), Tuple::vector(1.0, 0.0, 0.0));
+        let xs = [Intersection::new(&flat, 1.0)];
+        let comps = prepare_computations(&xs[0], &along, &xs, None);
+        assert!(f64_eq(comps.over_point.y / EPSILON, MAX_GRAZING_OFFSET));
+        assert!(f64_eq(comps.under_point.y / EPSILON, -MAX_GRAZING_OFFSET));
+    }
 }
@@ -1,10 +1,15 @@
+pub mod bounds;
 pub mod cone;
 pub mod cube;
 pub mod cylinder;
+pub mod disk;
 pub mod group;
 pub mod intersect;
 pub mod plane;
+pub mod quadric;
+pub mod rectangle;
 pub mod smooth_triangle;
 pub mod sphere;
+pub mod torus;
 pub mod triangle;
 pub mod ttriangle;
@@ -1,11 +1,14 @@
-use std::sync::atomic::Ordering;
+use std::{any::Any, sync::atomic::Ordering};
 
 use crate::{
     draw::material::Material,
     math::{matrix::Matrix, ray::Ray, tuples::Tuple, utils::EPSILON},
 };
 
-use super::intersect::{Intersectable, Intersection, OBJECT_COUNTER};
+use super::{
+    bounds::BoundingBox,
+    intersect::{Intersectable, Intersection, OBJECT_COUNTER},
+};
 
 pub struct Plane {
     id: usize,
@@ -22,9 +25,7 @@ impl Plane {
         let matrices = match transform {
             Some(matrix) => {
                 assert_eq!(matrix.size, 4);
-                let inverse = matrix.inverse();
-                let mut inv_transpose = matrix.inverse();
-                inv_transpose.transpose();
+                let (inverse, inv_transpose) = matrix.inverse_and_transpose();
                 (matrix, inverse, inv_transpose)
             }
             None => (
@@ -46,7 +47,7 @@ impl Plane {
 }
 
 impl Intersectable for Plane {
-    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection> {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
         // for the purposes of keeping the calculations easy assume the plane is flat in the xz direction
 
         // the ray is parallel to the plane, thus it will never intersect it
@@ -65,6 +66,10 @@ impl Intersectable for Plane {
         &self.material
     }
 
+    fn get_material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
     fn get_transform(&self) -> &Matrix {
         &self.transform
     }
@@ -92,6 +97,17 @@ impl Intersectable for Plane {
     fn set_material(&mut self, mat: Material) {
         self.material = mat;
     }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::new(
+            Tuple::point(f64::NEG_INFINITY, 0.0, f64::NEG_INFINITY),
+            Tuple::point(f64::INFINITY, 0.0, f64::INFINITY),
+        )
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 #[cfg(test)]
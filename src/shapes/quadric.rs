@@ -0,0 +1,208 @@
+use std::{any::Any, sync::atomic::Ordering};
+
+use crate::{
+    draw::material::Material,
+    math::{matrix::Matrix, polynomial::real_roots, ray::Ray, tuples::Tuple},
+};
+
+use super::{
+    bounds::BoundingBox,
+    intersect::{Intersectable, Intersection, OBJECT_COUNTER},
+};
+
+/*
+    Any surface of the form Ax^2 + By^2 + Cz^2 + Dxy + Exz + Fyz + Gx + Hy + Iz + J = 0,
+    which covers spheres, paraboloids, hyperboloids and the like
+*/
+pub struct Quadric {
+    id: usize,
+    transform: Matrix,
+    inverse_transform: Matrix,
+    inverse_transform_transpose: Matrix,
+    pub parent: Option<usize>,
+    pub material: Material,
+    pub coefficients: [f64; 10], // A through J
+}
+
+impl Quadric {
+    pub fn new(coefficients: [f64; 10], transform: Option<Matrix>) -> Quadric {
+        let id = OBJECT_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let matrices = match transform {
+            Some(matrix) => {
+                assert_eq!(matrix.size, 4);
+                let (inverse, inv_transpose) = matrix.inverse_and_transpose();
+                (matrix, inverse, inv_transpose)
+            }
+            None => (
+                Matrix::identity(4),
+                Matrix::identity(4),
+                Matrix::identity(4),
+            ),
+        };
+
+        Self {
+            transform: matrices.0,
+            inverse_transform: matrices.1,
+            inverse_transform_transpose: matrices.2,
+            material: Material::default_material(),
+            id,
+            parent: None,
+            coefficients,
+        }
+    }
+}
+
+impl Intersectable for Quadric {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        let [a, b, c, d, e, f, g, h, i, j] = self.coefficients;
+        let (o, dir) = (ray.origin, ray.direction);
+
+        // substitute origin + t * direction into the surface and collect powers of t
+        let t2 = a * dir.x * dir.x
+            + b * dir.y * dir.y
+            + c * dir.z * dir.z
+            + d * dir.x * dir.y
+            + e * dir.x * dir.z
+            + f * dir.y * dir.z;
+        let t1 = 2.0 * (a * o.x * dir.x + b * o.y * dir.y + c * o.z * dir.z)
+            + d * (o.x * dir.y + o.y * dir.x)
+            + e * (o.x * dir.z + o.z * dir.x)
+            + f * (o.y * dir.z + o.z * dir.y)
+            + g * dir.x
+            + h * dir.y
+            + i * dir.z;
+        let t0 = a * o.x * o.x
+            + b * o.y * o.y
+            + c * o.z * o.z
+            + d * o.x * o.y
+            + e * o.x * o.z
+            + f * o.y * o.z
+            + g * o.x
+            + h * o.y
+            + i * o.z
+            + j;
+
+        real_roots(&[t2, t1, t0])
+            .into_iter()
+            .map(|t| Intersection::new(self, t))
+            .collect()
+    }
+
+    fn local_normal_at(&self, p: Tuple, _: Intersection) -> Tuple {
+        // the gradient of the surface equation
+        let [a, b, c, d, e, f, g, h, i, _] = self.coefficients;
+        Tuple::vector(
+            2.0 * a * p.x + d * p.y + e * p.z + g,
+            2.0 * b * p.y + d * p.x + f * p.z + h,
+            2.0 * c * p.z + e * p.x + f * p.y + i,
+        )
+    }
+
+    fn get_material(&self) -> &Material {
+        &self.material
+    }
+
+    fn get_material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    fn get_transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn get_inverse_transform(&self) -> &Matrix {
+        &self.inverse_transform
+    }
+
+    fn get_inverse_transform_transpose(&self) -> &Matrix {
+        &self.inverse_transform_transpose
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn get_parent_id(&self) -> Option<usize> {
+        self.parent
+    }
+
+    fn set_parent_id(&mut self, id: usize) {
+        self.parent = Some(id);
+    }
+
+    fn set_material(&mut self, mat: Material) {
+        self.material = mat;
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::infinite()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{math::utils::f64_eq, shapes::sphere::Sphere};
+
+    use super::*;
+
+    const UNIT_SPHERE: [f64; 10] = [1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0];
+
+    #[test]
+    fn a_quadric_sphere_matches_a_sphere() {
+        let transform = &Matrix::translation(1.0, -2.0, 3.0) * &Matrix::scaling(2.0, 1.0, 0.5);
+        let quadric = Quadric::new(UNIT_SPHERE, Some(transform.clone()));
+        let sphere = Sphere::new(Some(transform));
+
+        let rays = [
+            (Tuple::point(1.0, -2.0, -5.0), Tuple::vector(0.0, 0.0, 1.0)),
+            (Tuple::point(-4.0, 0.0, 0.0), Tuple::vector(1.0, -0.4, 0.6)),
+            (Tuple::point(1.0, -2.0, 3.0), Tuple::vector(0.3, 1.0, 0.2)),
+            (Tuple::point(5.0, 5.0, 5.0), Tuple::vector(0.0, 1.0, 0.0)),
+        ];
+        for (origin, direction) in rays {
+            let r = Ray::new(origin, direction.normalize());
+            let expected = sphere.intersect(&r);
+            let xs = quadric.intersect(&r);
+            assert_eq!(xs.len(), expected.len());
+            for (x, e) in xs.iter().zip(&expected) {
+                assert!(f64_eq(x.t, e.t), "{} {}", x.t, e.t);
+                let point = r.position(x.t);
+                assert_eq!(
+                    quadric.normal_at(point, *x, None),
+                    sphere.normal_at(point, *e, None)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn intersecting_a_paraboloid() {
+        // y = x^2 + z^2, a bowl opening upwards
+        let bowl = Quadric::new([1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0], None);
+
+        // straight down the axis only touches the bottom once
+        let xs = bowl.intersect(&Ray::new(
+            Tuple::point(0.0, 5.0, 0.0),
+            Tuple::vector(0.0, -1.0, 0.0),
+        ));
+        assert_eq!(xs.len(), 1);
+        assert!(f64_eq(xs[0].t, 5.0));
+        assert_eq!(
+            bowl.normal_at(Tuple::point(0.0, 0.0, 0.0), xs[0], None),
+            Tuple::vector(0.0, -1.0, 0.0)
+        );
+
+        // across the bowl at y = 4 goes in and out of the wall at x = -2 and 2
+        let xs = bowl.intersect(&Ray::new(
+            Tuple::point(-5.0, 4.0, 0.0),
+            Tuple::vector(1.0, 0.0, 0.0),
+        ));
+        assert_eq!(xs.len(), 2);
+        assert!(f64_eq(xs[0].t, 3.0) && f64_eq(xs[1].t, 7.0));
+        assert!(!bowl.bounds().is_finite());
+    }
+}
@@ -0,0 +1,150 @@
+use std::{any::Any, sync::atomic::Ordering};
+
+use crate::{
+    draw::material::Material,
+    math::{matrix::Matrix, ray::Ray, tuples::Tuple, utils::EPSILON},
+};
+
+use super::{
+    bounds::BoundingBox,
+    intersect::{Intersectable, Intersection, OBJECT_COUNTER},
+};
+
+/*
+    A finite piece of a plane, width along x and depth along z, centered
+    on the origin and facing up
+*/
+pub struct Rectangle {
+    id: usize,
+    transform: Matrix,
+    inverse_transform: Matrix,
+    inverse_transform_transpose: Matrix,
+    pub parent: Option<usize>,
+    pub material: Material,
+    pub width: f64,
+    pub depth: f64,
+}
+
+impl Rectangle {
+    pub fn new(width: f64, depth: f64, transform: Option<Matrix>) -> Rectangle {
+        let id = OBJECT_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let matrices = match transform {
+            Some(matrix) => {
+                assert_eq!(matrix.size, 4);
+                let (inverse, inv_transpose) = matrix.inverse_and_transpose();
+                (matrix, inverse, inv_transpose)
+            }
+            None => (
+                Matrix::identity(4),
+                Matrix::identity(4),
+                Matrix::identity(4),
+            ),
+        };
+
+        Self {
+            transform: matrices.0,
+            inverse_transform: matrices.1,
+            inverse_transform_transpose: matrices.2,
+            material: Material::default_material(),
+            id,
+            parent: None,
+            width,
+            depth,
+        }
+    }
+}
+
+impl Intersectable for Rectangle {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        // the same as a plane, a ray parallel to the rectangle never meets it
+        if ray.direction.y.abs() < EPSILON {
+            return vec![];
+        }
+
+        let t = -ray.origin.y / ray.direction.y;
+        let x = ray.origin.x + t * ray.direction.x;
+        let z = ray.origin.z + t * ray.direction.z;
+        if x.abs() > self.width / 2.0 || z.abs() > self.depth / 2.0 {
+            return vec![];
+        }
+
+        vec![Intersection::new(self, t)]
+    }
+
+    fn local_normal_at(&self, _: Tuple, _: Intersection) -> Tuple {
+        Tuple::vector(0.0, 1.0, 0.0)
+    }
+
+    fn get_material(&self) -> &Material {
+        &self.material
+    }
+
+    fn get_material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    fn get_transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn get_inverse_transform(&self) -> &Matrix {
+        &self.inverse_transform
+    }
+
+    fn get_inverse_transform_transpose(&self) -> &Matrix {
+        &self.inverse_transform_transpose
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn get_parent_id(&self) -> Option<usize> {
+        self.parent
+    }
+
+    fn set_parent_id(&mut self, id: usize) {
+        self.parent = Some(id)
+    }
+
+    fn set_material(&mut self, mat: Material) {
+        self.material = mat;
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let (x, z) = (self.width / 2.0, self.depth / 2.0);
+        BoundingBox::new(Tuple::point(-x, 0.0, -z), Tuple::point(x, 0.0, z))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn intersecting_a_rectangle() {
+        let down = Tuple::vector(0.0, -1.0, 0.0);
+        let table = Rectangle::new(4.0, 2.0, Some(Matrix::translation(0.0, 1.0, 0.0)));
+
+        let xs = table.intersect(&Ray::new(Tuple::point(0.0, 3.0, 0.0), down));
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 2.0);
+        assert_eq!(
+            table.normal_at(Tuple::point(1.0, 1.0, 0.5), xs[0], None),
+            Tuple::vector(0.0, 1.0, 0.0)
+        );
+
+        // inside along x but past the edge in z, and the other way round
+        for (x, z, hits) in [(1.9, 0.9, true), (1.9, 1.1, false), (2.1, 0.0, false)] {
+            let xs = table.intersect(&Ray::new(Tuple::point(x, 3.0, z), down));
+            assert_eq!(xs.len() == 1, hits, "{} {}", x, z);
+        }
+        let flat = Ray::new(Tuple::point(0.0, 1.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
+        assert!(table.intersect(&flat).is_empty());
+        assert_eq!(table.bounds().min, Tuple::point(-2.0, 0.0, -1.0));
+    }
+}
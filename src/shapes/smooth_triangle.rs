@@ -1,14 +1,15 @@
-use std::sync::atomic::Ordering;
+use std::{any::Any, sync::atomic::Ordering};
 
 use crate::{
-    draw::material::Material,
+    draw::{color::Color, material::Material},
     math::{matrix::Matrix, ray::Ray, tuples::Tuple},
     shapes::intersect::OBJECT_COUNTER,
 };
 
 use super::{
+    bounds::BoundingBox,
     intersect::{Intersectable, Intersection},
-    ttriangle::{moller_trumbore_inner, TTriangle},
+    ttriangle::{blend_vertex_colors, moller_trumbore_inner, TTriangle},
 };
 
 pub struct SmoothTriangle {
@@ -23,7 +24,10 @@ pub struct SmoothTriangle {
     inverse_transform: Matrix,
     inverse_transform_transpose: Matrix,
     pub parent: Option<usize>,
+    pub mesh: Option<usize>, // the mesh this face belongs to, kept when the face is moved into another group
     pub material: Material,
+    pub vertex_colors: Option<[Color; 3]>, // colors at p1, p2 and p3 blended across the face
+    pub flip_normals: bool, // negate the interpolated normal, for meshes whose vertex normals point inwards
     e1: Tuple,
     e2: Tuple,
     normal: Tuple,
@@ -50,9 +54,7 @@ impl SmoothTriangle {
         let matrices = match transform {
             Some(matrix) => {
                 assert_eq!(matrix.size, 4);
-                let inverse = matrix.inverse();
-                let mut inv_transpose = matrix.inverse();
-                inv_transpose.transpose();
+                let (inverse, inv_transpose) = matrix.inverse_and_transpose();
                 (matrix, inverse, inv_transpose)
             }
             None => (
@@ -80,8 +82,11 @@ impl SmoothTriangle {
             inverse_transform: matrices.1,
             inverse_transform_transpose: matrices.2,
             material: Material::default_material(),
+            vertex_colors: None,
+            flip_normals: false,
             id,
             parent: None,
+            mesh: None,
             e1,
             e2,
             normal,
@@ -104,7 +109,12 @@ impl TTriangle for SmoothTriangle {
 }
 
 impl Intersectable for SmoothTriangle {
-    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection> {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        // a ray travelling the same way as the normal is approaching from behind
+        if self.material.cull_backfaces && ray.direction.dot(&self.normal) > 0.0 {
+            return vec![];
+        }
+
         match moller_trumbore_inner(self, ray) {
             Some(values) => vec![Intersection::new_uv(self, values.0, values.1, values.2)],
             None => vec![],
@@ -112,15 +122,34 @@ impl Intersectable for SmoothTriangle {
     }
 
     fn local_normal_at(&self, _: Tuple, hit: Intersection) -> Tuple {
-        self.n2 * hit.u.unwrap()
-            + self.n3 * hit.v.unwrap()
-            + self.n1 * (1.0 - hit.u.unwrap() - hit.v.unwrap())
+        // a hit made without barycentric coordinates can only be shaded flat
+        let normal = match (hit.u, hit.v) {
+            (Some(u), Some(v)) => self.n2 * u + self.n3 * v + self.n1 * (1.0 - u - v),
+            _ => self.normal,
+        };
+        if self.flip_normals {
+            -normal
+        } else {
+            normal
+        }
     }
 
     fn get_material(&self) -> &Material {
         &self.material
     }
 
+    fn get_material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    fn color_at_hit(&self, hit: &Intersection) -> Option<Color> {
+        blend_vertex_colors(&self.vertex_colors, hit)
+    }
+
+    fn mesh_id(&self) -> Option<usize> {
+        self.mesh
+    }
+
     fn get_transform(&self) -> &Matrix {
         &self.transform
     }
@@ -148,6 +177,18 @@ impl Intersectable for SmoothTriangle {
     fn set_material(&mut self, mat: Material) {
         self.material = mat;
     }
+
+    fn bounds(&self) -> BoundingBox {
+        let mut bounds = BoundingBox::empty();
+        bounds.add_point(self.p1);
+        bounds.add_point(self.p2);
+        bounds.add_point(self.p3);
+        bounds
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 #[cfg(test)]
@@ -188,6 +229,14 @@ mod test {
         assert_eq!(n, Tuple::vector(-0.5547, 0.83205, 0.0));
     }
 
+    #[test]
+    fn normal_without_barycentric_coordinates() {
+        let tri = test_triangle();
+        let i = Intersection::new(&tri, 1.0);
+        let n = tri.normal_at(Tuple::point(0.0, 0.5, 0.0), i, None);
+        assert_eq!(n, Tuple::vector(0.0, 0.0, -1.0));
+    }
+
     #[test]
     fn prepare_computations_interpolated_normal() {
         let tri = test_triangle();
@@ -1,11 +1,14 @@
-use std::sync::atomic::Ordering;
+use std::{any::Any, sync::atomic::Ordering};
 
 use crate::{
     draw::{color::Color, material::Material, patterns::Solid},
     math::{matrix::Matrix, ray::Ray, tuples::Tuple},
 };
 
-use super::intersect::{Intersectable, Intersection, OBJECT_COUNTER};
+use super::{
+    bounds::BoundingBox,
+    intersect::{Intersectable, Intersection, OBJECT_COUNTER},
+};
 
 pub struct Sphere {
     id: usize,
@@ -22,9 +25,7 @@ impl Sphere {
         let matrices = match transform {
             Some(matrix) => {
                 assert_eq!(matrix.size, 4);
-                let inverse = matrix.inverse();
-                let mut inv_transpose = matrix.inverse();
-                inv_transpose.transpose();
+                let (inverse, inv_transpose) = matrix.inverse_and_transpose();
                 (matrix, inverse, inv_transpose)
             }
             None => (
@@ -44,6 +45,16 @@ impl Sphere {
         }
     }
 
+    // a sphere of radius one at the origin
+    pub fn unit() -> Sphere {
+        Sphere::new(None)
+    }
+
+    // a sphere stretched to the given radius along each axis
+    pub fn ellipsoid(a: f64, b: f64, c: f64) -> Sphere {
+        Sphere::new(Some(Matrix::scaling(a, b, c)))
+    }
+
     pub fn new_glass_sphere(transform: Option<Matrix>) -> Sphere {
         let mut gs = Sphere::new(transform);
         gs.material.transparency = 1.0;
@@ -62,7 +73,7 @@ impl Intersectable for Sphere {
     /*
         Determine at what points the ray intersects the sphere, if any
     */
-    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection> {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
         // cast the ray
         let sphere_to_ray = ray.origin - Tuple::point(0.0, 0.0, 0.0);
 
@@ -87,6 +98,10 @@ impl Intersectable for Sphere {
         &self.material
     }
 
+    fn get_material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
     fn get_transform(&self) -> &Matrix {
         &self.transform
     }
@@ -114,12 +129,20 @@ impl Intersectable for Sphere {
     fn set_material(&mut self, mat: Material) {
         self.material = mat;
     }
+
+    fn bounds(&self) -> BoundingBox {
+        BoundingBox::new(Tuple::point(-1.0, -1.0, -1.0), Tuple::point(1.0, 1.0, 1.0))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 #[cfg(test)]
 mod test {
 
-    use crate::math::tuples::Tuple;
+    use crate::math::{tuples::Tuple, utils::f64_eq};
 
     use super::*;
 
@@ -132,6 +155,33 @@ mod test {
         assert_eq!(xs[1].t, 6.0)
     }
 
+    #[test]
+    fn intersecting_an_ellipsoid() {
+        let s = Sphere::ellipsoid(2.0, 1.0, 1.0);
+        let along_x = Ray::new(Tuple::point(0.0, 0.0, 0.0), Tuple::vector(1.0, 0.0, 0.0));
+        let along_y = Ray::new(Tuple::point(0.0, 0.0, 0.0), Tuple::vector(0.0, 1.0, 0.0));
+
+        let xs = s.intersect(&along_x);
+        assert!(f64_eq(xs[0].t, -2.0) && f64_eq(xs[1].t, 2.0));
+        let xs = s.intersect(&along_y);
+        assert!(f64_eq(xs[0].t, -1.0) && f64_eq(xs[1].t, 1.0));
+
+        assert_eq!(Sphere::unit().get_transform(), &Matrix::identity(4));
+    }
+
+    #[test]
+    fn cached_matrices_match_the_transform() {
+        let m = &Matrix::translation(1.0, -2.0, 3.0)
+            * &(&Matrix::rotation_y(0.7) * &Matrix::scaling(2.0, 0.5, 1.5));
+        let s = Sphere::new(Some(m.clone()));
+
+        let mut transpose = m.inverse();
+        transpose.transpose();
+        assert_eq!(s.get_inverse_transform(), &m.inverse());
+        assert_eq!(s.get_inverse_transform_transpose(), &transpose);
+        assert_eq!(&m * s.get_inverse_transform(), Matrix::identity(4));
+    }
+
     #[test]
     fn ray_intersect_sphere_top() {
         let r = Ray::new(Tuple::point(0.0, 1.0, -5.0), Tuple::vector(0.0, 0.0, 1.0));
@@ -0,0 +1,243 @@
+use std::{any::Any, sync::atomic::Ordering};
+
+use crate::{
+    draw::material::Material,
+    math::{matrix::Matrix, polynomial::real_roots, ray::Ray, tuples::Tuple},
+};
+
+use super::{
+    bounds::BoundingBox,
+    intersect::{Intersectable, Intersection, OBJECT_COUNTER},
+};
+
+/*
+    A ring lying flat in the xz plane around the y axis, the center of the
+    tube is major_radius from the origin and the tube is minor_radius thick
+*/
+pub struct Torus {
+    id: usize,
+    transform: Matrix,
+    inverse_transform: Matrix,
+    inverse_transform_transpose: Matrix,
+    pub parent: Option<usize>,
+    pub material: Material,
+    pub major_radius: f64, // from the origin to the center of the tube
+    pub minor_radius: f64, // radius of the tube
+}
+
+impl Torus {
+    pub fn new(transform: Option<Matrix>) -> Torus {
+        let id = OBJECT_COUNTER.fetch_add(1, Ordering::SeqCst);
+        let matrices = match transform {
+            Some(matrix) => {
+                assert_eq!(matrix.size, 4);
+                let (inverse, inv_transpose) = matrix.inverse_and_transpose();
+                (matrix, inverse, inv_transpose)
+            }
+            None => (
+                Matrix::identity(4),
+                Matrix::identity(4),
+                Matrix::identity(4),
+            ),
+        };
+
+        Self {
+            transform: matrices.0,
+            inverse_transform: matrices.1,
+            inverse_transform_transpose: matrices.2,
+            material: Material::default_material(),
+            id,
+            parent: None,
+            major_radius: 1.0,
+            minor_radius: 0.25,
+        }
+    }
+}
+
+impl Intersectable for Torus {
+    /*
+        A point is on the torus when (|p|^2 + R^2 - r^2)^2 = 4R^2(x^2 + z^2),
+        putting the ray into that gives a quartic in t
+    */
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        let (o, d) = (ray.origin, ray.direction);
+        let major2 = self.major_radius.powi(2);
+        let minor2 = self.minor_radius.powi(2);
+
+        let origin = Tuple::vector(o.x, o.y, o.z);
+        let dd = d.dot(&d);
+        let od = origin.dot(&d);
+        let e = origin.dot(&origin) - major2 - minor2;
+
+        let coefficients = [
+            dd * dd,
+            4.0 * dd * od,
+            2.0 * dd * e + 4.0 * od * od + 4.0 * major2 * d.y * d.y,
+            4.0 * od * e + 8.0 * major2 * o.y * d.y,
+            e * e - 4.0 * major2 * (minor2 - o.y * o.y),
+        ];
+
+        real_roots(&coefficients)
+            .into_iter()
+            .map(|t| Intersection::new(self, t))
+            .collect()
+    }
+
+    fn local_normal_at(&self, object_point: Tuple, _: Intersection) -> Tuple {
+        // away from the closest point on the circle running through the middle of the tube
+        let radial = (object_point.x.powi(2) + object_point.z.powi(2)).sqrt();
+        if radial == 0.0 {
+            return Tuple::vector(0.0, object_point.y.signum(), 0.0);
+        }
+        let scale = self.major_radius / radial;
+        Tuple::vector(
+            object_point.x * (1.0 - scale),
+            object_point.y,
+            object_point.z * (1.0 - scale),
+        )
+    }
+
+    fn get_material(&self) -> &Material {
+        &self.material
+    }
+
+    fn get_material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    fn get_transform(&self) -> &Matrix {
+        &self.transform
+    }
+
+    fn get_inverse_transform(&self) -> &Matrix {
+        &self.inverse_transform
+    }
+
+    fn get_inverse_transform_transpose(&self) -> &Matrix {
+        &self.inverse_transform_transpose
+    }
+
+    fn get_id(&self) -> usize {
+        self.id
+    }
+
+    fn get_parent_id(&self) -> Option<usize> {
+        self.parent
+    }
+
+    fn set_parent_id(&mut self, id: usize) {
+        self.parent = Some(id);
+    }
+
+    fn set_material(&mut self, mat: Material) {
+        self.material = mat;
+    }
+
+    fn bounds(&self) -> BoundingBox {
+        let outer = self.major_radius + self.minor_radius;
+        BoundingBox::new(
+            Tuple::point(-outer, -self.minor_radius, -outer),
+            Tuple::point(outer, self.minor_radius, outer),
+        )
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::math::utils::f64_eq;
+
+    use super::*;
+
+    fn ts(torus: &Torus, origin: Tuple, direction: Tuple) -> Vec<f64> {
+        let r = Ray::new(origin, direction.normalize());
+        torus.intersect(&r).iter().map(|i| i.t).collect()
+    }
+
+    #[test]
+    fn intersecting_a_torus() {
+        let torus = Torus::new(None);
+
+        // straight through the hole without touching the ring
+        assert!(ts(
+            &torus,
+            Tuple::point(0.0, 5.0, 0.0),
+            Tuple::vector(0.0, -1.0, 0.0)
+        )
+        .is_empty());
+        // over the top and outside of it
+        assert!(ts(
+            &torus,
+            Tuple::point(0.0, 0.3, -5.0),
+            Tuple::vector(0.0, 0.0, 1.0)
+        )
+        .is_empty());
+        assert!(ts(
+            &torus,
+            Tuple::point(2.0, 0.0, -5.0),
+            Tuple::vector(0.0, 0.0, 1.0)
+        )
+        .is_empty());
+
+        // across the middle the ray goes through the tube on both sides of the hole
+        let xs = ts(
+            &torus,
+            Tuple::point(-5.0, 0.0, 0.0),
+            Tuple::vector(1.0, 0.0, 0.0),
+        );
+        assert_eq!(xs.len(), 4);
+        for (t, expected) in xs.iter().zip([3.75, 4.25, 5.75, 6.25]) {
+            assert!(f64_eq(*t, expected), "{:?}", xs);
+        }
+
+        // down through the tube itself
+        let xs = ts(
+            &torus,
+            Tuple::point(1.0, 5.0, 0.0),
+            Tuple::vector(0.0, -1.0, 0.0),
+        );
+        assert_eq!(xs.len(), 2);
+        assert!(f64_eq(xs[0], 4.75) && f64_eq(xs[1], 5.25));
+
+        // a fatter ring scaled up and moved away
+        let mut far = Torus::new(Some(
+            &Matrix::translation(0.0, 0.0, 100.0) * &Matrix::scaling(2.0, 2.0, 2.0),
+        ));
+        far.minor_radius = 0.5;
+        let xs = ts(
+            &far,
+            Tuple::point(0.0, 0.0, 0.0),
+            Tuple::vector(0.0, 0.0, 1.0),
+        );
+        assert_eq!(xs.len(), 4);
+        for (t, expected) in xs.iter().zip([97.0, 99.0, 101.0, 103.0]) {
+            assert!(f64_eq(*t, expected), "{:?}", xs);
+        }
+    }
+
+    #[test]
+    fn normal_on_a_torus() {
+        let torus = Torus::new(None);
+        let i = Intersection::new(&torus, 0.0);
+        let cases = [
+            (Tuple::point(1.25, 0.0, 0.0), Tuple::vector(1.0, 0.0, 0.0)),
+            (Tuple::point(0.75, 0.0, 0.0), Tuple::vector(-1.0, 0.0, 0.0)),
+            (Tuple::point(0.0, 0.25, 1.0), Tuple::vector(0.0, 1.0, 0.0)),
+            (
+                Tuple::point(0.0, -0.25, -1.0),
+                Tuple::vector(0.0, -1.0, 0.0),
+            ),
+            (
+                Tuple::point(-1.0 - 0.25 * 0.6, 0.25 * 0.8, 0.0),
+                Tuple::vector(-0.6, 0.8, 0.0),
+            ),
+        ];
+        for (point, normal) in cases {
+            assert_eq!(torus.normal_at(point, i, None), normal);
+        }
+        assert_eq!(torus.bounds().max, Tuple::point(1.25, 0.25, 1.25));
+    }
+}
@@ -1,13 +1,14 @@
-use std::sync::atomic::Ordering;
+use std::{any::Any, sync::atomic::Ordering};
 
 use crate::{
-    draw::material::Material,
+    draw::{color::Color, material::Material},
     math::{matrix::Matrix, ray::Ray, tuples::Tuple},
 };
 
 use super::{
+    bounds::BoundingBox,
     intersect::{Intersectable, Intersection, OBJECT_COUNTER},
-    ttriangle::{moller_trumbore_inner, TTriangle},
+    ttriangle::{blend_vertex_colors, moller_trumbore_inner, TTriangle},
 };
 
 pub struct Triangle {
@@ -19,7 +20,9 @@ pub struct Triangle {
     inverse_transform: Matrix,
     inverse_transform_transpose: Matrix,
     pub parent: Option<usize>,
+    pub mesh: Option<usize>, // the mesh this face belongs to, kept when the face is moved into another group
     pub material: Material,
+    pub vertex_colors: Option<[Color; 3]>, // colors at p1, p2 and p3 blended across the face
     e1: Tuple,
     e2: Tuple,
     normal: Tuple,
@@ -34,9 +37,7 @@ impl Triangle {
         let matrices = match transform {
             Some(matrix) => {
                 assert_eq!(matrix.size, 4);
-                let inverse = matrix.inverse();
-                let mut inv_transpose = matrix.inverse();
-                inv_transpose.transpose();
+                let (inverse, inv_transpose) = matrix.inverse_and_transpose();
                 (matrix, inverse, inv_transpose)
             }
             None => (
@@ -61,8 +62,10 @@ impl Triangle {
             inverse_transform: matrices.1,
             inverse_transform_transpose: matrices.2,
             material: Material::default_material(),
+            vertex_colors: None,
             id,
             parent: None,
+            mesh: None,
             e1,
             e2,
             normal,
@@ -85,9 +88,14 @@ impl TTriangle for Triangle {
 }
 
 impl Intersectable for Triangle {
-    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection> {
+    fn local_intersect(&self, ray: &Ray) -> Vec<Intersection<'_>> {
+        // a ray travelling the same way as the normal is approaching from behind
+        if self.material.cull_backfaces && ray.direction.dot(&self.normal) > 0.0 {
+            return vec![];
+        }
+
         match moller_trumbore_inner(self, ray) {
-            Some(values) => vec![Intersection::new(self, values.0)],
+            Some(values) => vec![Intersection::new_uv(self, values.0, values.1, values.2)],
             None => vec![],
         }
     }
@@ -100,6 +108,18 @@ impl Intersectable for Triangle {
         &self.material
     }
 
+    fn get_material_mut(&mut self) -> &mut Material {
+        &mut self.material
+    }
+
+    fn color_at_hit(&self, hit: &Intersection) -> Option<Color> {
+        blend_vertex_colors(&self.vertex_colors, hit)
+    }
+
+    fn mesh_id(&self) -> Option<usize> {
+        self.mesh
+    }
+
     fn get_transform(&self) -> &Matrix {
         &self.transform
     }
@@ -127,13 +147,26 @@ impl Intersectable for Triangle {
     fn set_material(&mut self, mat: Material) {
         self.material = mat;
     }
+
+    fn bounds(&self) -> BoundingBox {
+        let mut bounds = BoundingBox::empty();
+        bounds.add_point(self.p1);
+        bounds.add_point(self.p2);
+        bounds.add_point(self.p3);
+        bounds
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
 }
 
 #[cfg(test)]
 mod test {
     use crate::{
+        draw::{color::Color, material::Material},
         math::{ray::Ray, tuples::Tuple},
-        shapes::intersect::{Intersectable, Intersection},
+        shapes::intersect::{prepare_computations, Intersectable, Intersection},
     };
 
     use super::Triangle;
@@ -221,4 +254,59 @@ mod test {
         assert_eq!(xs.len(), 1);
         assert_eq!(xs[0].t, 2.0);
     }
+
+    #[test]
+    fn vertex_colors_blend_at_centroid() {
+        let mut t = Triangle::new(
+            Tuple::point(0.0, 1.0, 0.0),
+            Tuple::point(-1.0, 0.0, 0.0),
+            Tuple::point(1.0, 0.0, 0.0),
+            None,
+        );
+        let r = Ray::new(
+            Tuple::point(0.0, 1.0 / 3.0, -2.0),
+            Tuple::vector(0.0, 0.0, 1.0),
+        );
+
+        // without painted vertices the material pattern is used
+        let xs = t.intersect(&r);
+        assert_eq!(t.color_at_hit(&xs[0]), None);
+
+        t.vertex_colors = Some([
+            Color::new(1.0, 0.0, 0.0),
+            Color::new(0.0, 1.0, 0.0),
+            Color::new(0.0, 0.0, 1.0),
+        ]);
+        let xs = t.intersect(&r);
+        let comps = prepare_computations(&xs[0], &r, &xs, None);
+        assert_eq!(
+            comps.surface_color,
+            Some(Color::new(1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0))
+        );
+
+        // right next to a corner the color is almost entirely that corner's
+        let r = Ray::new(Tuple::point(0.9, 0.05, -2.0), Tuple::vector(0.0, 0.0, 1.0));
+        let xs = t.intersect(&r);
+        assert_eq!(t.color_at_hit(&xs[0]), Some(Color::new(0.05, 0.025, 0.925)));
+    }
+
+    #[test]
+    fn backface_culling() {
+        let mut t = Triangle::new(
+            Tuple::point(0.0, 1.0, 0.0),
+            Tuple::point(-1.0, 0.0, 0.0),
+            Tuple::point(1.0, 0.0, 0.0),
+            None,
+        );
+        // the normal points towards -z
+        let front = Ray::new(Tuple::point(0.0, 0.5, -2.0), Tuple::vector(0.0, 0.0, 1.0));
+        let back = Ray::new(Tuple::point(0.0, 0.5, 2.0), Tuple::vector(0.0, 0.0, -1.0));
+
+        assert_eq!(t.intersect(&front).len(), 1);
+        assert_eq!(t.intersect(&back).len(), 1);
+
+        t.material = Material::builder().cull_backfaces(true).build();
+        assert_eq!(t.intersect(&front).len(), 1);
+        assert!(t.intersect(&back).is_empty());
+    }
 }
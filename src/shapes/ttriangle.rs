@@ -1,4 +1,9 @@
-use crate::math::{tuples::Tuple, ray::Ray, utils::EPSILON};
+use crate::{
+    draw::color::Color,
+    math::{ray::Ray, tuples::Tuple, utils::EPSILON},
+};
+
+use super::intersect::Intersection;
 
 pub trait TTriangle {
     fn e1(&self) -> Tuple;
@@ -31,4 +36,15 @@ pub fn moller_trumbore_inner(shape: &dyn TTriangle, ray: &Ray) -> Option<(f64, f
     let t = f * shape.e2().dot(&origin_cross_e1);
 
     Some((t, u, v))
-}
\ No newline at end of file
+}
+
+/*
+    Blend the colors at each corner using the barycentric coordinates of the hit,
+    u weights the second point and v the third the same way normals are interpolated
+*/
+pub fn blend_vertex_colors(colors: &Option<[Color; 3]>, hit: &Intersection) -> Option<Color> {
+    match (colors, hit.u, hit.v) {
+        (Some([c1, c2, c3]), Some(u), Some(v)) => Some(*c2 * u + *c3 * v + *c1 * (1.0 - u - v)),
+        _ => None,
+    }
+}